@@ -0,0 +1,49 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use twilight_model::application::interaction::ApplicationCommand;
+
+use todo_bot::commands::{AdminCommand, PrefsCommand, TaskCommand};
+use todo_bot::parser::ParseCommand;
+use todo_bot::test_util::InteractionFixture;
+
+/// Benches parsing `command` as `C`, leaving the cloning of the command out of the measurement.
+fn bench_parse<C: ParseCommand>(c: &mut Criterion, name: &str, command: ApplicationCommand) {
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || command.clone(),
+            |command| C::parse(command).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn parse(c: &mut Criterion) {
+    bench_parse::<TaskCommand>(
+        c,
+        "parse task",
+        InteractionFixture::new("task")
+            .guild(10)
+            .string_option("task", "buy milk")
+            .build(),
+    );
+    bench_parse::<PrefsCommand>(
+        c,
+        "parse prefs",
+        InteractionFixture::new("prefs")
+            .string_option("delivery", "dm")
+            .string_option("lists", "guild")
+            .string_option("digest", "08:30")
+            .string_option("utc-offset", "-05:30")
+            .build(),
+    );
+    bench_parse::<AdminCommand>(
+        c,
+        "parse admin subcommand",
+        InteractionFixture::new("admin")
+            .guild(10)
+            .subcommand("reset-commands", |sub| sub)
+            .build(),
+    );
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+CREATE TABLE tasks (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    -- Zero-based position in the user's list; `NULL` once the task is completed.
+    position BIGINT,
+    text TEXT NOT NULL,
+    emoji TEXT,
+    -- Milliseconds since the Unix epoch.
+    created_at BIGINT NOT NULL,
+    completed_at BIGINT
+);
+
+CREATE INDEX tasks_user_position ON tasks (user_id, position);
@@ -0,0 +1 @@
+ALTER TABLE tasks ADD COLUMN image_url TEXT;
@@ -0,0 +1,4 @@
+CREATE TABLE preferences (
+    user_id BIGINT PRIMARY KEY,
+    delivery TEXT NOT NULL
+);
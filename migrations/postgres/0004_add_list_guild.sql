@@ -0,0 +1,7 @@
+-- The guild a task's list belongs to; `NULL` for the user's global list.
+ALTER TABLE tasks ADD COLUMN guild_id BIGINT;
+
+DROP INDEX tasks_user_position;
+CREATE INDEX tasks_user_guild_position ON tasks (user_id, guild_id, position);
+
+ALTER TABLE preferences ADD COLUMN scope TEXT NOT NULL DEFAULT 'global';
@@ -0,0 +1,10 @@
+CREATE TABLE command_usage (
+    command TEXT NOT NULL,
+    -- Days since the Unix epoch, in UTC.
+    day BIGINT NOT NULL,
+    invocations BIGINT NOT NULL,
+    errors BIGINT NOT NULL,
+    -- Milliseconds since the Unix epoch.
+    last_used BIGINT NOT NULL,
+    PRIMARY KEY (command, day)
+);
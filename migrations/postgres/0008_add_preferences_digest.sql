@@ -0,0 +1,6 @@
+-- When to DM the user their open tasks each day, in minutes after midnight in their timezone, and
+-- the locale to write it in; both `NULL` if they haven't asked for it.
+ALTER TABLE preferences ADD COLUMN digest_at INTEGER;
+ALTER TABLE preferences ADD COLUMN digest_locale TEXT;
+-- The user's offset from UTC, in minutes.
+ALTER TABLE preferences ADD COLUMN utc_offset INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE tasks (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    -- Zero-based position in the user's list; `NULL` once the task is completed.
+    position INTEGER,
+    text TEXT NOT NULL,
+    emoji TEXT,
+    -- Milliseconds since the Unix epoch.
+    created_at INTEGER NOT NULL,
+    completed_at INTEGER
+);
+
+CREATE INDEX tasks_user_position ON tasks (user_id, position);
@@ -0,0 +1,4 @@
+CREATE TABLE preferences (
+    user_id INTEGER PRIMARY KEY,
+    delivery TEXT NOT NULL
+);
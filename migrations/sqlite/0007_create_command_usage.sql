@@ -0,0 +1,10 @@
+CREATE TABLE command_usage (
+    command TEXT NOT NULL,
+    -- Days since the Unix epoch, in UTC.
+    day INTEGER NOT NULL,
+    invocations INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    -- Milliseconds since the Unix epoch.
+    last_used INTEGER NOT NULL,
+    PRIMARY KEY (command, day)
+);
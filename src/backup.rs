@@ -0,0 +1,99 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::storage::{self, Storage};
+use crate::State;
+
+const PREFIX: &str = "todo-";
+const EXTENSION: &str = ".json";
+
+/// A backup which has been written.
+pub struct Backup {
+    pub path: PathBuf,
+    /// The size of the file, in bytes.
+    pub size: u64,
+}
+
+/// Writes a backup of everything in `storage` to a new timestamped file in `dir`, and then
+/// deletes all but the newest `retain` backups there.
+///
+/// Backups are written in the format of the JSON backend whichever backend they're taken from, so
+/// any of them can be restored by pointing `json_path` at it.
+pub async fn backup(storage: &dyn Storage, dir: &Path, retain: usize) -> anyhow::Result<Backup> {
+    let data = storage.export_all().await?;
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("failed to create `{}`", dir.display()))?;
+    let timestamp = storage::to_millis(SystemTime::now());
+    let path = dir.join(format!("{PREFIX}{timestamp}{EXTENSION}"));
+    storage::export(&path, &data)
+        .await
+        .with_context(|| format!("failed to write `{}`", path.display()))?;
+    let size = tokio::fs::metadata(&path).await?.len();
+    prune(dir, retain).await?;
+    Ok(Backup { path, size })
+}
+
+/// Deletes all but the newest `retain` backups in `dir`, leaving any other files alone.
+async fn prune(dir: &Path, retain: usize) -> anyhow::Result<()> {
+    let mut backups = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let timestamp = entry.file_name().to_str().and_then(|name| {
+            name.strip_prefix(PREFIX)?
+                .strip_suffix(EXTENSION)?
+                .parse::<i64>()
+                .ok()
+        });
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp, entry.path()));
+        }
+    }
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    for (_, path) in backups.into_iter().skip(retain) {
+        match tokio::fs::remove_file(&path).await {
+            // Another backup running at the same time may have got there first.
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(e).context(format!("failed to delete `{}`", path.display()))
+            }
+            _ => log::debug!("deleted old backup `{}`", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Takes a backup every `period`, starting one period from now.
+///
+/// Failures are logged, and DMed to the owner of the bot if `backup_notify_owner` is set.
+pub async fn run_periodic(state: Arc<State>, period: Duration) {
+    let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        match state.backup().await {
+            Ok(backup) => log::info!(
+                "wrote a backup of {} bytes to `{}`",
+                backup.size,
+                backup.path.display(),
+            ),
+            Err(e) => {
+                log::error!("scheduled backup failed: {e:#}");
+                state.report_error("Scheduled backup failed", &e, None);
+                if state.config.backup_notify_owner {
+                    let content = format!("Scheduled backup failed: {e:#}");
+                    if let Err(e) = state
+                        .send_dm(state.application.owner.id, &content, &[], &[])
+                        .await
+                    {
+                        log::warn!("failed to DM the owner about the failed backup: {e:#}");
+                    }
+                }
+            }
+        }
+    }
+}
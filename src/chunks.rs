@@ -0,0 +1,140 @@
+use twilight_model::application::callback::CallbackData;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The most characters Discord allows in a message's content.
+pub const MAX_CONTENT: usize = 2000;
+
+/// Splits `content` into pieces of at most `limit` characters, which join back into `content`.
+///
+/// Pieces are broken after the last line break that fits, so that each task on a list stays in
+/// one piece, or failing that after the last space, and only mid-word for a word longer than the
+/// limit, and even then not within a grapheme, such as an emoji made of several characters. The
+/// line break or space a piece is broken at ends the piece, where Discord doesn't show it.
+pub fn split(content: &str, limit: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = content;
+    while rest.chars().count() > limit {
+        let end = split_point(rest, limit);
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+/// The byte index to end the first piece of `text` at.
+fn split_point(text: &str, limit: usize) -> usize {
+    let max = text
+        .char_indices()
+        .nth(limit)
+        .map_or(text.len(), |(idx, _)| idx);
+    let head = &text[..max];
+    head.rfind('\n')
+        .or_else(|| head.rfind(' '))
+        .map(|idx| idx + 1)
+        .or_else(|| {
+            text.grapheme_indices(true)
+                .map(|(idx, _)| idx)
+                .take_while(|&idx| idx <= max)
+                .filter(|&idx| idx > 0)
+                .last()
+        })
+        // A single grapheme longer than the limit has to be split somewhere.
+        .unwrap_or(max)
+}
+
+/// Splits a response whose content is too long for one message, leaving the first piece in
+/// `data` and returning the rest as follow-up messages.
+///
+/// The embeds and components go on the last message, after all of the content, and every
+/// message keeps the response's flags and allowed mentions.
+pub fn split_response(data: &mut CallbackData) -> Vec<CallbackData> {
+    let Some(content) = data.content.take() else {
+        return Vec::new();
+    };
+    let mut pieces = split(&content, MAX_CONTENT).into_iter();
+    data.content = pieces.next().map(String::from);
+    let mut followups = pieces
+        .map(|piece| CallbackData {
+            allowed_mentions: data.allowed_mentions.clone(),
+            components: None,
+            content: Some(piece.into()),
+            embeds: None,
+            flags: data.flags,
+            tts: None,
+        })
+        .collect::<Vec<_>>();
+    if let Some(last) = followups.last_mut() {
+        last.embeds = data.embeds.take();
+        last.components = data.components.take();
+    }
+    followups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits `content` into messages, checking that each fits and that none of it is lost.
+    fn split_messages(content: &str) -> Vec<&str> {
+        let pieces = split(content, MAX_CONTENT);
+        for piece in &pieces {
+            assert!(piece.chars().count() <= MAX_CONTENT);
+        }
+        assert_eq!(pieces.concat(), content);
+        pieces
+    }
+
+    #[test]
+    fn leaves_content_at_the_limit_whole() {
+        let content = "a".repeat(MAX_CONTENT);
+        assert_eq!(split_messages(&content), [&*content]);
+    }
+
+    #[test]
+    fn splits_a_word_one_past_the_limit() {
+        let content = "a".repeat(MAX_CONTENT + 1);
+        assert_eq!(split_messages(&content), [&content[..MAX_CONTENT], "a"]);
+    }
+
+    #[test]
+    fn breaks_after_the_last_line_break_that_fits() {
+        // There's a space after the line break, but still within the limit.
+        let first = format!("{}\n", "a".repeat(1000));
+        let second = format!("{} {}", "b".repeat(500), "c".repeat(600));
+        let content = format!("{first}{second}");
+        assert_eq!(split_messages(&content), [first, second]);
+    }
+
+    #[test]
+    fn breaks_after_the_last_space_without_a_line_break() {
+        let first = format!("{} ", "a".repeat(1500));
+        let content = format!("{first}{}", "b".repeat(1000));
+        assert_eq!(split_messages(&content), [&*first, &content[first.len()..]]);
+    }
+
+    #[test]
+    fn counts_multi_byte_characters_once() {
+        let content = "é".repeat(MAX_CONTENT + 1);
+        let pieces = split_messages(&content);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[1], "é");
+    }
+
+    #[test]
+    fn doesnt_split_a_grapheme() {
+        // The limit falls between the thumbs up and its skin tone.
+        let content = format!("{}👍🏽b", "a".repeat(MAX_CONTENT - 1));
+        assert_eq!(
+            split_messages(&content),
+            [&*"a".repeat(MAX_CONTENT - 1), "👍🏽b"]
+        );
+
+        // And here between the `e` and its accent.
+        let content = format!("{}e\u{301}", "a".repeat(MAX_CONTENT - 1));
+        assert_eq!(
+            split_messages(&content),
+            [&*"a".repeat(MAX_CONTENT - 1), "e\u{301}"]
+        );
+    }
+}
@@ -0,0 +1,2441 @@
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use twilight_http::request::AttachmentFile;
+use twilight_model::{
+    application::{
+        callback::InteractionResponse,
+        command::{Command, CommandOption, CommandOptionType, CommandType},
+        component::{button::ButtonStyle, ActionRow, Button, Component},
+        interaction::{
+            application_command::CommandOptionValue, ApplicationCommand,
+            MessageComponentInteraction,
+        },
+    },
+    channel::{
+        embed::{Embed, EmbedThumbnail},
+        message::{AllowedMentions, MessageFlags},
+    },
+    guild::Permissions,
+    id::{
+        marker::{AttachmentMarker, ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    },
+    user::User,
+};
+use twilight_util::builder::CallbackDataBuilder;
+
+use crate::chunks;
+use crate::components::HandleComponent;
+use crate::config::{Config, LongTasks};
+use crate::digest;
+use crate::http::{HttpError, CANNOT_MESSAGE_USER};
+use crate::messages::{self, message};
+use crate::parser::{
+    parse_channel, parse_guild, parse_invoker_with_source, parse_locale, parse_member_permissions,
+    parse_target_message, parse_user, resolve_image, resolve_text_file, timestamp_of, CommandError,
+    LenientInteger, OptionError, Options, ParseCommand, ParseOption, UserOrMention, UserSource,
+};
+use crate::registry::{ResponsePolicy, RunCommand};
+use crate::storage::{
+    self, AddTask, Delivery, Digest, ListKey, ListScope, Placement, Preferences, StorageError,
+    TransferMode,
+};
+use crate::task::{ReactionEmoji, Task};
+use crate::undo::{self, Action};
+use crate::State;
+
+/// The cooldown cost of a command which changes what's stored.
+const MUTATING_COST: u32 = 2;
+
+#[derive(Debug)]
+pub struct TaskCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: String,
+    pub emoji: Option<ReactionEmoji>,
+    pub image_url: Option<String>,
+    /// Whether to add the task to the top of the list rather than the bottom.
+    pub top: bool,
+}
+
+impl ParseCommand for TaskCommand {
+    const COMMAND: &'static str = "task";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let resolved = command.data.resolved;
+        let mut options = Options::new(command.data.options);
+        // A task of only whitespace would show up as a blank line on the list.
+        let task = options.required_with("task", |task: &String| {
+            if task.trim().is_empty() {
+                Err("a task can't be blank".into())
+            } else {
+                Ok(())
+            }
+        });
+        let emoji = options.optional("emoji");
+        let image_url = options
+            .optional::<Id<AttachmentMarker>>("image")
+            .and_then(|id| {
+                id.map(|id| resolve_image(resolved.as_ref(), "image", id))
+                    .transpose()
+            })
+            .map(|image| image.map(|image| image.url.clone()));
+        let top = options.optional("top");
+        match (user, task, emoji, image_url, top) {
+            (Ok(user), Ok(task), Ok(emoji), Ok(image_url), Ok(top)) => Ok(TaskCommand {
+                user,
+                guild,
+                locale,
+                task,
+                emoji,
+                image_url,
+                top: top.unwrap_or_default(),
+            }),
+            (user, task, emoji, image_url, top) => Err(CommandError::collect([
+                user.err(),
+                task.err(),
+                emoji.err(),
+                image_url.err(),
+                top.err(),
+            ])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for TaskCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling task command: {:?}", self);
+        let Some(text) = limit_task_length(&state.config, self.task) else {
+            let cb = CallbackDataBuilder::new()
+                .content(message!(
+                    &self.locale,
+                    "task.too_long",
+                    max = state.config.max_task_length,
+                ))
+                .flags(MessageFlags::EPHEMERAL)
+                .build();
+            return Ok(InteractionResponse::ChannelMessageWithSource(cb));
+        };
+        let task = Task {
+            text,
+            emoji: self.emoji,
+            image_url: self.image_url,
+            created_at: SystemTime::now(),
+            pinned: false,
+            archived: false,
+        };
+        let list = user_list(state, self.user, self.guild).await?;
+        let placement = if self.top {
+            Placement::Top
+        } else {
+            Placement::Bottom
+        };
+        let added = state
+            .storage
+            .add_task(
+                list,
+                &task,
+                placement,
+                state.config.dedup_tasks,
+                state.config.max_tasks,
+            )
+            .await;
+        let cb = match added {
+            Ok(AddTask::Added(idx)) => {
+                state.undo.record(
+                    self.user,
+                    Action::Added {
+                        list,
+                        index: idx,
+                        task: task.clone(),
+                    },
+                );
+                let content = match &state.config.added_template {
+                    Some(template) => {
+                        messages::render(template, &[("task", &task), ("index", &idx)])
+                    }
+                    None => message!(&self.locale, "task.added", task = task, index = idx),
+                };
+                CallbackDataBuilder::new().content(content).build()
+            }
+            Ok(AddTask::Duplicate(idx)) => CallbackDataBuilder::new()
+                .content(message!(
+                    &self.locale,
+                    "task.duplicate",
+                    task = task,
+                    index = idx
+                ))
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+            Err(StorageError::ListFull(limit)) => CallbackDataBuilder::new()
+                .content(message!(&self.locale, "task.list_full", limit = limit))
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+/// The "Add to todo" message context menu command, which adds the text of the message it's used
+/// on to the user's list as a task.
+#[derive(Debug)]
+pub struct AddToTodoCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    /// The content of the message the command was used on.
+    pub task: String,
+}
+
+impl ParseCommand for AddToTodoCommand {
+    const COMMAND: &'static str = "Add to todo";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let task = parse_target_message(&command).map(|message| message.content.trim().into());
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(AddToTodoCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for AddToTodoCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling add to todo command: {:?}", self);
+        // A message of only attachments or embeds has no text to use.
+        let content = if self.task.is_empty() {
+            message!(&self.locale, "add_to_todo.empty")
+        } else if let Some(text) = limit_task_length(&state.config, self.task) {
+            let task = Task {
+                text,
+                emoji: None,
+                image_url: None,
+                created_at: SystemTime::now(),
+                pinned: false,
+                archived: false,
+            };
+            let list = user_list(state, self.user, self.guild).await?;
+            let added = state
+                .storage
+                .add_task(
+                    list,
+                    &task,
+                    Placement::Bottom,
+                    state.config.dedup_tasks,
+                    state.config.max_tasks,
+                )
+                .await;
+            match added {
+                Ok(AddTask::Added(idx)) => {
+                    let content = message!(&self.locale, "task.added", task = task, index = idx);
+                    state.undo.record(
+                        self.user,
+                        Action::Added {
+                            list,
+                            index: idx,
+                            task,
+                        },
+                    );
+                    content
+                }
+                Ok(AddTask::Duplicate(idx)) => {
+                    message!(&self.locale, "task.duplicate", task = task, index = idx)
+                }
+                Err(StorageError::ListFull(limit)) => {
+                    message!(&self.locale, "task.list_full", limit = limit)
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            message!(
+                &self.locale,
+                "task.too_long",
+                max = state.config.max_task_length,
+            )
+        };
+        // Only the user sees this, since the message it was used on is someone else's.
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+/// The most tasks `/task-bulk` adds at once.
+const MAX_BULK_TASKS: usize = 50;
+
+/// The largest file `/task-bulk` accepts, in bytes.
+const MAX_BULK_FILE: u64 = 64 * 1024;
+
+/// Adds a task for each line of an attached text file, all at once.
+///
+/// Bulk additions can't be undone with `/undo`, though since they go at the bottom of the list,
+/// the changes made before them still can be.
+#[derive(Debug)]
+pub struct TaskBulkCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub file_url: String,
+    pub file_size: u64,
+}
+
+impl ParseCommand for TaskBulkCommand {
+    const COMMAND: &'static str = "task-bulk";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let resolved = command.data.resolved;
+        let mut options = Options::new(command.data.options);
+        let file = options
+            .required::<Id<AttachmentMarker>>("file")
+            .and_then(|id| resolve_text_file(resolved.as_ref(), "file", id))
+            .map(|file| (file.url.clone(), file.size));
+        match (user, file) {
+            (Ok(user), Ok((file_url, file_size))) => Ok(TaskBulkCommand {
+                user,
+                guild,
+                locale,
+                file_url,
+                file_size,
+            }),
+            (user, file) => Err(CommandError::collect([user.err(), file.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for TaskBulkCommand {
+    const COST: u32 = MUTATING_COST;
+    // Deferred since the file has to be downloaded first.
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling task-bulk command: {:?}", self);
+        let locale = &self.locale;
+        let content = match download_text(state, &self.file_url, self.file_size).await? {
+            Download::TooLarge => {
+                message!(locale, "task_bulk.too_large", max = MAX_BULK_FILE / 1024)
+            }
+            Download::NotText => message!(locale, "task_bulk.not_text"),
+            Download::Text(text) => {
+                let lines = bulk_lines(&text);
+                if lines.is_empty() {
+                    message!(locale, "task_bulk.empty")
+                } else if lines.len() > MAX_BULK_TASKS {
+                    message!(
+                        locale,
+                        "task_bulk.too_many",
+                        count = lines.len(),
+                        max = MAX_BULK_TASKS,
+                    )
+                } else {
+                    add_bulk(state, &self, lines).await?
+                }
+            }
+        };
+        let cb = CallbackDataBuilder::new().content(content).build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+/// A file attached to a command, once downloaded.
+enum Download {
+    Text(String),
+    TooLarge,
+    NotText,
+}
+
+/// Downloads a text file attached to a command, unless it's larger than [`MAX_BULK_FILE`].
+///
+/// The size Discord gives is checked before downloading, and the size of what's downloaded after.
+async fn download_text(state: &State, url: &str, size: u64) -> anyhow::Result<Download> {
+    if size > MAX_BULK_FILE {
+        return Ok(Download::TooLarge);
+    }
+    let bytes = state
+        .downloads
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .context("failed to download an attached file")?
+        .bytes()
+        .await
+        .context("failed to download an attached file")?;
+    if bytes.len() as u64 > MAX_BULK_FILE {
+        return Ok(Download::TooLarge);
+    }
+    Ok(match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => Download::Text(text),
+        Err(_) => Download::NotText,
+    })
+}
+
+/// The tasks in a file given to `/task-bulk`: one per line, leaving out blank lines and any
+/// leading `-` or `*` bullet.
+fn bulk_lines(text: &str) -> Vec<String> {
+    text.lines()
+        .map(|line| {
+            let line = line.trim();
+            line.strip_prefix(['-', '*'])
+                .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+                .unwrap_or(line)
+                .trim()
+        })
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Adds the tasks from a `/task-bulk` file, returning the response listing them.
+async fn add_bulk(
+    state: &State,
+    command: &TaskBulkCommand,
+    lines: Vec<String>,
+) -> anyhow::Result<String> {
+    let locale = &command.locale;
+    let mut texts = Vec::with_capacity(lines.len());
+    for (idx, line) in lines.into_iter().enumerate() {
+        match limit_task_length(&state.config, line) {
+            Some(text) => texts.push(text),
+            None => {
+                return Ok(message!(
+                    locale,
+                    "task_bulk.too_long",
+                    number = idx + 1,
+                    max = state.config.max_task_length,
+                ));
+            }
+        }
+    }
+    let now = SystemTime::now();
+    let tasks = texts
+        .into_iter()
+        .map(|text| Task {
+            text,
+            emoji: None,
+            image_url: None,
+            created_at: now,
+            pinned: false,
+            archived: false,
+        })
+        .collect::<Vec<_>>();
+    let list = user_list(state, command.user, command.guild).await?;
+    let added = state
+        .storage
+        .add_tasks(
+            list,
+            &tasks,
+            state.config.dedup_tasks,
+            state.config.max_tasks,
+        )
+        .await;
+    let outcomes = match added {
+        Ok(outcomes) => outcomes,
+        Err(StorageError::ListFull(limit)) => {
+            return Ok(message!(
+                locale,
+                "task_bulk.list_full",
+                count = tasks.len(),
+                limit = limit,
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let count = outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome, AddTask::Added(_)))
+        .count();
+    let mut content = vec![message!(locale, "task_bulk.added", count = count)];
+    content.extend(
+        tasks
+            .iter()
+            .zip(&outcomes)
+            .map(|(task, outcome)| match outcome {
+                AddTask::Added(idx) => format!("`{idx}.` {task}"),
+                AddTask::Duplicate(idx) => {
+                    message!(locale, "task_bulk.duplicate", task = task, index = idx)
+                }
+            }),
+    );
+    Ok(content.join("\n"))
+}
+
+#[derive(Debug)]
+pub struct DoneCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: usize,
+}
+
+impl ParseCommand for DoneCommand {
+    const COMMAND: &'static str = "done";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let task = options.required("task").map(|LenientInteger(task)| task);
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(DoneCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for DoneCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling done command: {:?}", self);
+        let list = user_list(state, self.user, self.guild).await?;
+        let removed = state.storage.complete_task(list, self.task).await;
+        let cb = match removed {
+            Ok(task) => {
+                if let Some(webhook) = &state.webhook {
+                    webhook.notify(self.user, &task);
+                }
+                let content = message!(&self.locale, "done.completed", task = task);
+                state.undo.record(
+                    self.user,
+                    Action::Completed {
+                        list,
+                        index: self.task,
+                        task,
+                    },
+                );
+                CallbackDataBuilder::new().content(content).build()
+            }
+            Err(StorageError::NoSuchTask(_)) => CallbackDataBuilder::new()
+                .content(message!(
+                    &self.locale,
+                    "task.no_such_task",
+                    index = self.task
+                ))
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct PinCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: usize,
+}
+
+impl ParseCommand for PinCommand {
+    const COMMAND: &'static str = "pin";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let task = options.required("task").map(|LenientInteger(task)| task);
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(PinCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for PinCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling pin command: {:?}", self);
+        set_pinned(state, self.user, self.guild, &self.locale, self.task, true).await
+    }
+}
+
+#[derive(Debug)]
+pub struct UnpinCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: usize,
+}
+
+impl ParseCommand for UnpinCommand {
+    const COMMAND: &'static str = "unpin";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let task = options.required("task").map(|LenientInteger(task)| task);
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(UnpinCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for UnpinCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling unpin command: {:?}", self);
+        set_pinned(state, self.user, self.guild, &self.locale, self.task, false).await
+    }
+}
+
+/// Pins or unpins a task on the user's list, for `/pin` and `/unpin`.
+async fn set_pinned(
+    state: &State,
+    user: Id<UserMarker>,
+    guild: Option<Id<GuildMarker>>,
+    locale: &str,
+    index: usize,
+    pinned: bool,
+) -> anyhow::Result<InteractionResponse> {
+    let list = user_list(state, user, guild).await?;
+    let cb = match state.storage.set_pinned(list, index, pinned).await {
+        Ok(task) => {
+            let content = if pinned {
+                message!(locale, "pin.pinned", task = task)
+            } else {
+                message!(locale, "pin.unpinned", task = task)
+            };
+            state.undo.record(
+                user,
+                Action::Pinned {
+                    list,
+                    index,
+                    task,
+                    pinned,
+                },
+            );
+            CallbackDataBuilder::new().content(content).build()
+        }
+        Err(StorageError::NoSuchTask(_)) => CallbackDataBuilder::new()
+            .content(message!(locale, "task.no_such_task", index = index))
+            .flags(MessageFlags::EPHEMERAL)
+            .build(),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(InteractionResponse::ChannelMessageWithSource(cb))
+}
+
+#[derive(Debug)]
+pub struct ArchiveCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: usize,
+}
+
+impl ParseCommand for ArchiveCommand {
+    const COMMAND: &'static str = "archive";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let task = options.required("task").map(|LenientInteger(task)| task);
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(ArchiveCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for ArchiveCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling archive command: {:?}", self);
+        set_archived(state, self.user, self.guild, &self.locale, self.task, true).await
+    }
+}
+
+#[derive(Debug)]
+pub struct UnarchiveCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub task: usize,
+}
+
+impl ParseCommand for UnarchiveCommand {
+    const COMMAND: &'static str = "unarchive";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let task = options.required("task").map(|LenientInteger(task)| task);
+        match (user, task) {
+            (Ok(user), Ok(task)) => Ok(UnarchiveCommand {
+                user,
+                guild,
+                locale,
+                task,
+            }),
+            (user, task) => Err(CommandError::collect([user.err(), task.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for UnarchiveCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling unarchive command: {:?}", self);
+        set_archived(state, self.user, self.guild, &self.locale, self.task, false).await
+    }
+}
+
+/// Archives or restores a task on the user's list, for `/archive` and `/unarchive`.
+async fn set_archived(
+    state: &State,
+    user: Id<UserMarker>,
+    guild: Option<Id<GuildMarker>>,
+    locale: &str,
+    index: usize,
+    archived: bool,
+) -> anyhow::Result<InteractionResponse> {
+    let list = user_list(state, user, guild).await?;
+    let cb = match state.storage.set_archived(list, index, archived).await {
+        Ok(task) => {
+            let content = if archived {
+                message!(locale, "archive.archived", task = task)
+            } else {
+                message!(locale, "archive.unarchived", task = task)
+            };
+            state.undo.record(
+                user,
+                Action::Archived {
+                    list,
+                    index,
+                    task,
+                    archived,
+                },
+            );
+            CallbackDataBuilder::new().content(content).build()
+        }
+        Err(StorageError::NoSuchTask(_)) => CallbackDataBuilder::new()
+            .content(message!(locale, "task.no_such_task", index = index))
+            .flags(MessageFlags::EPHEMERAL)
+            .build(),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(InteractionResponse::ChannelMessageWithSource(cb))
+}
+
+#[derive(Debug)]
+pub struct ArchivedCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+impl ParseCommand for ArchivedCommand {
+    const COMMAND: &'static str = "archived";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(ArchivedCommand {
+            user: parse_user(&command)?,
+            guild: parse_guild(&command).ok(),
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for ArchivedCommand {
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling archived command: {:?}", self);
+        let list = user_list(state, self.user, self.guild).await?;
+        let tasks = state.storage.list_tasks(list).await?;
+        // Archived tasks keep their place on the list, so they're shown with the index other
+        // commands expect.
+        let archived = tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| task.archived)
+            .map(|(idx, task)| format!("`{}.` {task}", idx + 1))
+            .collect::<Vec<_>>();
+        let content = if archived.is_empty() {
+            message!(&self.locale, "archive.empty")
+        } else {
+            archived.join("\n")
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct ListCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    /// The token for sending a list too long for a message as a follow-up file.
+    pub token: String,
+    pub sort: ListSort,
+    /// The most tasks to show, or `None` to show them all.
+    pub limit: Option<usize>,
+}
+
+/// The order to show tasks in for the `list` command.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ListSort {
+    /// The order the tasks are stored in.
+    #[default]
+    Added,
+    /// Most recently created first.
+    Newest,
+    /// Alphabetically by the task text, ignoring case.
+    Alphabetical,
+}
+
+impl ParseOption for ListSort {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        match &*string {
+            "added" => Ok(ListSort::Added),
+            "newest" => Ok(ListSort::Newest),
+            "alphabetical" => Ok(ListSort::Alphabetical),
+            _ => Err(OptionError::InvalidValue {
+                value: string,
+                reason: "expected one of `added`, `newest`, or `alphabetical`".into(),
+            }),
+        }
+    }
+}
+
+impl ParseCommand for ListCommand {
+    const COMMAND: &'static str = "list";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let token = command.token.clone();
+        let mut options = Options::new(command.data.options);
+        let sort = options.optional("sort");
+        let limit = options.optional("limit");
+        match (user, sort, limit) {
+            (Ok(user), Ok(sort), Ok(limit)) => Ok(ListCommand {
+                user,
+                guild,
+                locale,
+                token,
+                sort: sort.unwrap_or_default(),
+                limit,
+            }),
+            (user, sort, limit) => {
+                Err(CommandError::collect([user.err(), sort.err(), limit.err()]))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for ListCommand {
+    // Deferred so that a list sent as a file can follow the acknowledgement.
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling list command: {:?}", self);
+        let preferences = state.storage.preferences(self.user).await?;
+        let list = preferences.scope.list(self.user, self.guild);
+        let tasks = state.storage.list_tasks(list).await?;
+        // Keep each task's position in the list, so the indices shown are the ones other
+        // commands expect, whatever order the tasks are displayed in.
+        let mut tasks = tasks
+            .into_iter()
+            .enumerate()
+            .filter(|(_, task)| !task.archived)
+            .collect::<Vec<_>>();
+        match self.sort {
+            ListSort::Added => {}
+            ListSort::Newest => tasks.sort_by_key(|(_, task)| Reverse(task.created_at)),
+            ListSort::Alphabetical => {
+                tasks.sort_by_cached_key(|(_, task)| (task.text.to_lowercase(), task.created_at))
+            }
+        }
+        // Pinned tasks come first whatever the order; the sort is stable, so each group keeps it.
+        tasks.sort_by_key(|(_, task)| !task.pinned);
+        let more = match self.limit {
+            Some(limit) if limit < tasks.len() => {
+                let more = tasks.len() - limit;
+                tasks.truncate(limit);
+                Some(message!(&self.locale, "list.more", count = more))
+            }
+            _ => None,
+        };
+        let content = if tasks.is_empty() && more.is_none() {
+            message!(&self.locale, "list.empty")
+        } else {
+            tasks
+                .iter()
+                .map(|(idx, task)| {
+                    let pin = if task.pinned { "📌 " } else { "" };
+                    format!("`{}.` {pin}{task}", idx + 1)
+                })
+                .chain(more.clone())
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let embeds = tasks
+            .iter()
+            .filter_map(|(idx, task)| Some((idx, task, task.image_url.clone()?)))
+            .take(MAX_EMBEDS)
+            .map(|(idx, task, url)| image_embed(idx + 1, task, url))
+            .collect::<Vec<_>>();
+        // A list too long for one message is sent whole as a file, if so configured, rather than
+        // over several messages.
+        let (content, file) =
+            if state.config.long_lists_as_file && content.chars().count() > chunks::MAX_CONTENT {
+                // Plain text, without the formatting Discord would render.
+                let file = tasks
+                    .iter()
+                    .map(|(idx, task)| {
+                        let pin = if task.pinned { "📌 " } else { "" };
+                        format!("{}. {pin}{task}\n", idx + 1)
+                    })
+                    .chain(more.map(|more| format!("{more}\n")))
+                    .collect::<String>();
+                (message!(&self.locale, "list.attached"), Some(file))
+            } else {
+                (content, None)
+            };
+        let files = file
+            .iter()
+            .map(|file| AttachmentFile::from_bytes(LIST_FILENAME, file.as_bytes()))
+            .collect::<Vec<_>>();
+        let cb = match preferences.delivery {
+            Delivery::Dm => match state.send_dm(self.user, &content, &embeds, &files).await {
+                Ok(()) => CallbackDataBuilder::new()
+                    .content(message!(&self.locale, "list.sent"))
+                    .flags(MessageFlags::EPHEMERAL)
+                    .build(),
+                Err(e) => {
+                    let code = e.downcast_ref::<HttpError>().and_then(HttpError::code);
+                    if code == Some(CANNOT_MESSAGE_USER) {
+                        log::info!("user {} doesn't accept DMs from the bot", self.user);
+                    } else {
+                        log::warn!("failed to DM user {}: {e:#}", self.user);
+                    }
+                    send_ephemeral_files(state, &self.token, &files).await?;
+                    CallbackDataBuilder::new()
+                        .content(message!(&self.locale, "list.dm_failed", list = content))
+                        .embeds(embeds)
+                        .flags(MessageFlags::EPHEMERAL)
+                        .build()
+                }
+            },
+            Delivery::Ephemeral => {
+                send_ephemeral_files(state, &self.token, &files).await?;
+                CallbackDataBuilder::new()
+                    .content(content)
+                    .embeds(embeds)
+                    .flags(MessageFlags::EPHEMERAL)
+                    .build()
+            }
+        };
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+/// The name of the file a list too long for a message is sent as.
+const LIST_FILENAME: &str = "todo.txt";
+
+/// Sends files as an ephemeral follow-up to a deferred interaction, if there are any.
+async fn send_ephemeral_files(
+    state: &State,
+    token: &str,
+    files: &[AttachmentFile<'_>],
+) -> anyhow::Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    state.discord.create_followup_files(token, files).await
+}
+
+/// The most embeds Discord allows on a single message.
+const MAX_EMBEDS: usize = 10;
+
+/// An embed showing a task's image as a thumbnail.
+///
+/// If the image has since expired, Discord shows the embed without it.
+fn image_embed(index: usize, task: &Task, url: String) -> Embed {
+    Embed {
+        author: None,
+        color: None,
+        description: None,
+        fields: Vec::new(),
+        footer: None,
+        image: None,
+        kind: "rich".into(),
+        provider: None,
+        thumbnail: Some(EmbedThumbnail {
+            height: None,
+            proxy_url: None,
+            url,
+            width: None,
+        }),
+        timestamp: None,
+        title: Some(format!("{index}. {task}")),
+        url: None,
+        video: None,
+    }
+}
+
+#[derive(Debug)]
+pub struct CountCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+impl ParseCommand for CountCommand {
+    const COMMAND: &'static str = "count";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(CountCommand {
+            user: parse_user(&command)?,
+            guild: parse_guild(&command).ok(),
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for CountCommand {
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling count command: {:?}", self);
+        let list = user_list(state, self.user, self.guild).await?;
+        let count = state.storage.count_tasks(list).await?;
+        let open = count.open;
+        let content = match (count.completed, open == 1) {
+            (None, true) => message!(&self.locale, "count.one", open = open),
+            (None, false) => message!(&self.locale, "count.other", open = open),
+            (Some(completed), true) => message!(
+                &self.locale,
+                "count.completed.one",
+                open = open,
+                completed = completed,
+            ),
+            (Some(completed), false) => message!(
+                &self.locale,
+                "count.completed.other",
+                open = open,
+                completed = completed,
+            ),
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct TransferCommand {
+    pub user: Id<UserMarker>,
+    /// The guild the command was used in, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub to: Id<UserMarker>,
+    pub mode: TransferMode,
+}
+
+impl ParseOption for TransferMode {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        match &*string {
+            "append" => Ok(TransferMode::Append),
+            "replace" => Ok(TransferMode::Replace),
+            _ => Err(OptionError::InvalidValue {
+                value: string,
+                reason: "expected one of `append` or `replace`".into(),
+            }),
+        }
+    }
+}
+
+impl ParseCommand for TransferCommand {
+    const COMMAND: &'static str = "transfer";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let guild = parse_guild(&command).ok();
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let to = options.required("to").map(|UserOrMention(to)| to);
+        let mode = options.optional("mode");
+        match (user, to, mode) {
+            (Ok(user), Ok(to), Ok(mode)) => Ok(TransferCommand {
+                user,
+                guild,
+                locale,
+                to,
+                mode: mode.unwrap_or_default(),
+            }),
+            (user, to, mode) => Err(CommandError::collect([user.err(), to.err(), mode.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for TransferCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling transfer command: {:?}", self);
+        let content = if self.user == self.to {
+            message!(&self.locale, "transfer.to_self")
+        } else {
+            // Each side's list is whichever one their commands here would use.
+            let from = user_list(state, self.user, self.guild).await?;
+            let to = user_list(state, self.to, self.guild).await?;
+            let count = state.storage.transfer_tasks(from, to, self.mode).await?;
+            state.undo.forget(self.user);
+            state.undo.forget(self.to);
+            let user = format!("<@{}>", self.to);
+            if count == 1 {
+                message!(
+                    &self.locale,
+                    "transfer.done.one",
+                    count = count,
+                    user = user
+                )
+            } else {
+                message!(
+                    &self.locale,
+                    "transfer.done.other",
+                    count = count,
+                    user = user
+                )
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .allowed_mentions(AllowedMentions::default())
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct PrefsCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    /// The new delivery preference, or `None` to leave it unchanged.
+    pub delivery: Option<Delivery>,
+    /// The new list scope, or `None` to leave it unchanged.
+    pub lists: Option<ListScope>,
+    /// The new time of the daily digest, or `None` to leave it unchanged.
+    pub digest: Option<DigestTime>,
+    /// The new offset from UTC, or `None` to leave it unchanged.
+    pub utc_offset: Option<UtcOffset>,
+}
+
+/// When to send the daily digest, given as `HH:MM` or `off`.
+#[derive(Clone, Copy, Debug)]
+pub enum DigestTime {
+    Off,
+    /// In minutes after midnight in the user's timezone.
+    At(u16),
+}
+
+impl ParseOption for DigestTime {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        if string.trim().eq_ignore_ascii_case("off") {
+            return Ok(DigestTime::Off);
+        }
+        digest::parse_time(&string)
+            .map(DigestTime::At)
+            .ok_or_else(|| OptionError::InvalidValue {
+                value: string,
+                reason: "expected a time like `08:30`, or `off`".into(),
+            })
+    }
+}
+
+/// An offset from UTC in minutes, given like `+2` or `-05:30`.
+#[derive(Clone, Copy, Debug)]
+pub struct UtcOffset(pub i32);
+
+impl ParseOption for UtcOffset {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        digest::parse_utc_offset(&string)
+            .map(UtcOffset)
+            .ok_or_else(|| OptionError::InvalidValue {
+                value: string,
+                reason: "expected an offset from UTC like `+2` or `-05:30`".into(),
+            })
+    }
+}
+
+impl ParseOption for Delivery {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        Delivery::from_name(&string).ok_or_else(|| OptionError::InvalidValue {
+            value: string,
+            reason: "expected one of `ephemeral` or `dm`".into(),
+        })
+    }
+}
+
+impl ParseOption for ListScope {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        ListScope::from_name(&string).ok_or_else(|| OptionError::InvalidValue {
+            value: string,
+            reason: "expected one of `global` or `guild`".into(),
+        })
+    }
+}
+
+impl ParseCommand for PrefsCommand {
+    const COMMAND: &'static str = "prefs";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let delivery = options.optional("delivery");
+        let lists = options.optional("lists");
+        let digest = options.optional("digest");
+        let utc_offset = options.optional("utc-offset");
+        match (user, delivery, lists, digest, utc_offset) {
+            (Ok(user), Ok(delivery), Ok(lists), Ok(digest), Ok(utc_offset)) => Ok(PrefsCommand {
+                user,
+                locale,
+                delivery,
+                lists,
+                digest,
+                utc_offset,
+            }),
+            (user, delivery, lists, digest, utc_offset) => Err(CommandError::collect([
+                user.err(),
+                delivery.err(),
+                lists.err(),
+                digest.err(),
+                utc_offset.err(),
+            ])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for PrefsCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling prefs command: {:?}", self);
+        let mut preferences = state.storage.preferences(self.user).await?;
+        let previous = preferences.clone();
+        let updated = self.delivery.is_some()
+            || self.lists.is_some()
+            || self.digest.is_some()
+            || self.utc_offset.is_some();
+        if let Some(delivery) = self.delivery {
+            preferences.delivery = delivery;
+        }
+        if let Some(lists) = self.lists {
+            preferences.scope = lists;
+        }
+        if let Some(digest) = self.digest {
+            preferences.digest = match digest {
+                DigestTime::Off => None,
+                DigestTime::At(at) => Some(Digest {
+                    at,
+                    locale: self.locale.clone(),
+                }),
+            };
+        }
+        if let Some(UtcOffset(offset)) = self.utc_offset {
+            preferences.utc_offset = offset;
+        }
+        if updated {
+            state
+                .storage
+                .set_preferences(self.user, &preferences)
+                .await?;
+            state
+                .undo
+                .record(self.user, Action::Preferences { previous });
+        }
+        let cb = CallbackDataBuilder::new()
+            .content(describe_preferences(&self.locale, &preferences, updated))
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+fn describe_preferences(locale: &str, preferences: &Preferences, updated: bool) -> String {
+    let delivery = match preferences.delivery {
+        Delivery::Ephemeral => message!(locale, "prefs.delivery.ephemeral"),
+        Delivery::Dm => message!(locale, "prefs.delivery.dm"),
+    };
+    let lists = match preferences.scope {
+        ListScope::Global => message!(locale, "prefs.lists.global"),
+        ListScope::Guild => message!(locale, "prefs.lists.guild"),
+    };
+    let digest = match &preferences.digest {
+        Some(digest) => message!(
+            locale,
+            "prefs.digest.at",
+            time = digest::format_time(digest.at),
+            offset = digest::format_utc_offset(preferences.utc_offset),
+        ),
+        None => message!(locale, "prefs.digest.off"),
+    };
+    if updated {
+        message!(
+            locale,
+            "prefs.updated",
+            delivery = delivery,
+            lists = lists,
+            digest = digest
+        )
+    } else {
+        message!(
+            locale,
+            "prefs.current",
+            delivery = delivery,
+            lists = lists,
+            digest = digest
+        )
+    }
+}
+
+/// Reverses the user's most recent change, as recorded in [`undo::UndoHistory`].
+///
+/// A change to a task which has since been completed or removed is dropped, since there's nothing
+/// left to reverse it on.
+#[derive(Debug)]
+pub struct UndoCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+impl ParseCommand for UndoCommand {
+    const COMMAND: &'static str = "undo";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(UndoCommand {
+            user: parse_user(&command)?,
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for UndoCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling undo command: {:?}", self);
+        let locale = &self.locale;
+        let action = match state.undo.pop(self.user) {
+            Some(action) => action,
+            None => {
+                let cb = CallbackDataBuilder::new()
+                    .content(message!(locale, "undo.nothing"))
+                    .flags(MessageFlags::EPHEMERAL)
+                    .build();
+                return Ok(InteractionResponse::ChannelMessageWithSource(cb));
+            }
+        };
+        // Task changes are reported like the commands they reverse, and anything else only to the
+        // user.
+        let (content, ephemeral) = match action {
+            Action::Added { list, index, task } => {
+                let tasks = state.storage.list_tasks(list).await?;
+                match undo::find_task(&tasks, index, &task) {
+                    Some(index) => {
+                        let task = state.storage.remove_task(list, index).await?;
+                        (message!(locale, "undo.added", task = task), false)
+                    }
+                    None => (message!(locale, "undo.gone", task = task), true),
+                }
+            }
+            Action::Completed { list, index, task } => {
+                let restored = state
+                    .storage
+                    .restore_task(list, index, &task, state.config.max_tasks)
+                    .await;
+                match restored {
+                    Ok(index) => (
+                        message!(locale, "undo.completed", task = task, index = index),
+                        false,
+                    ),
+                    Err(StorageError::ListFull(limit)) => {
+                        // Kept so it can be undone once there's room.
+                        state
+                            .undo
+                            .record(self.user, Action::Completed { list, index, task });
+                        (message!(locale, "task.list_full", limit = limit), true)
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Action::Pinned {
+                list,
+                index,
+                task,
+                pinned,
+            } => {
+                let tasks = state.storage.list_tasks(list).await?;
+                match undo::find_task(&tasks, index, &task) {
+                    Some(index) => {
+                        let task = state.storage.set_pinned(list, index, !pinned).await?;
+                        let content = if pinned {
+                            message!(locale, "pin.unpinned", task = task)
+                        } else {
+                            message!(locale, "pin.pinned", task = task)
+                        };
+                        (content, false)
+                    }
+                    None => (message!(locale, "undo.gone", task = task), true),
+                }
+            }
+            Action::Archived {
+                list,
+                index,
+                task,
+                archived,
+            } => {
+                let tasks = state.storage.list_tasks(list).await?;
+                match undo::find_task(&tasks, index, &task) {
+                    Some(index) => {
+                        let task = state.storage.set_archived(list, index, !archived).await?;
+                        let content = if archived {
+                            message!(locale, "archive.unarchived", task = task)
+                        } else {
+                            message!(locale, "archive.archived", task = task)
+                        };
+                        (content, false)
+                    }
+                    None => (message!(locale, "undo.gone", task = task), true),
+                }
+            }
+            Action::Preferences { previous } => {
+                state.storage.set_preferences(self.user, &previous).await?;
+                (describe_preferences(locale, &previous, true), true)
+            }
+        };
+        let mut cb = CallbackDataBuilder::new().content(content);
+        if ephemeral {
+            cb = cb.flags(MessageFlags::EPHEMERAL);
+        }
+        Ok(InteractionResponse::ChannelMessageWithSource(cb.build()))
+    }
+}
+
+#[derive(Debug)]
+pub struct MigrateListCommand {
+    pub user: Id<UserMarker>,
+    /// The guild to move the list to, or `None` in a DM.
+    pub guild: Option<Id<GuildMarker>>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+impl ParseCommand for MigrateListCommand {
+    const COMMAND: &'static str = "migrate-list";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(MigrateListCommand {
+            user: parse_user(&command)?,
+            guild: parse_guild(&command).ok(),
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for MigrateListCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling migrate-list command: {:?}", self);
+        let content = match self.guild {
+            None => message!(&self.locale, "migrate.not_in_guild"),
+            Some(guild) => {
+                let to = ListKey {
+                    user: self.user,
+                    guild: Some(guild),
+                };
+                let count = state
+                    .storage
+                    .transfer_tasks(ListKey::global(self.user), to, TransferMode::Append)
+                    .await?;
+                state.undo.forget(self.user);
+                let mut content = if count == 1 {
+                    message!(&self.locale, "migrate.moved.one", count = count)
+                } else {
+                    message!(&self.locale, "migrate.moved.other", count = count)
+                };
+                let preferences = state.storage.preferences(self.user).await?;
+                if preferences.scope == ListScope::Global {
+                    content.push('\n');
+                    content.push_str(&message!(&self.locale, "migrate.still_global"));
+                }
+                content
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct ForgetMeCommand {
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+/// The buttons sent by `/forget-me`, confirming or cancelling it.
+///
+/// The data deleted is always the clicking user's, so a button can't delete anyone else's.
+#[derive(Clone, Copy, Debug)]
+pub enum ForgetMeButton {
+    Confirm,
+    Cancel,
+}
+
+impl ParseCommand for ForgetMeCommand {
+    const COMMAND: &'static str = "forget-me";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(ForgetMeCommand {
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for ForgetMeCommand {
+    const COST: u32 = MUTATING_COST;
+
+    async fn run(self, _state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling forget-me command: {:?}", self);
+        let button = |button: ForgetMeButton, label: String, style| {
+            Component::Button(Button {
+                custom_id: Some(button.custom_id()),
+                disabled: false,
+                emoji: None,
+                label: Some(label),
+                style,
+                url: None,
+            })
+        };
+        let buttons = Component::ActionRow(ActionRow {
+            components: vec![
+                button(
+                    ForgetMeButton::Confirm,
+                    message!(&self.locale, "forget_me.button.confirm"),
+                    ButtonStyle::Danger,
+                ),
+                button(
+                    ForgetMeButton::Cancel,
+                    message!(&self.locale, "forget_me.button.cancel"),
+                    ButtonStyle::Secondary,
+                ),
+            ],
+        });
+        let cb = CallbackDataBuilder::new()
+            .content(message!(&self.locale, "forget_me.confirm"))
+            .components([buttons])
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[async_trait::async_trait]
+impl HandleComponent for ForgetMeButton {
+    const PREFIX: &'static str = "forget-me";
+
+    fn decode(payload: &str) -> Option<Self> {
+        match payload {
+            "confirm" => Some(ForgetMeButton::Confirm),
+            "cancel" => Some(ForgetMeButton::Cancel),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            ForgetMeButton::Confirm => "confirm".into(),
+            ForgetMeButton::Cancel => "cancel".into(),
+        }
+    }
+
+    async fn handle(
+        self,
+        state: &State,
+        component: &MessageComponentInteraction,
+    ) -> anyhow::Result<InteractionResponse> {
+        let locale = &component.locale;
+        let content = match self {
+            ForgetMeButton::Confirm => {
+                let user = component.author_id().ok_or(CommandError::MissingUser)?;
+                let deleted = state.storage.delete_user(user).await?;
+                state.undo.forget(user);
+                log::info!("deleted the data of user {user}: {deleted:?}");
+                let mut lines = vec![message!(locale, "forget_me.deleted", tasks = deleted.tasks)];
+                if deleted.completed > 0 {
+                    lines.push(message!(
+                        locale,
+                        "forget_me.deleted_completed",
+                        completed = deleted.completed,
+                    ));
+                }
+                if deleted.preferences {
+                    lines.push(message!(locale, "forget_me.deleted_preferences"));
+                }
+                lines.join("\n")
+            }
+            ForgetMeButton::Cancel => message!(locale, "forget_me.cancelled"),
+        };
+        // Replace the confirmation, so its buttons can't be clicked again.
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .components([])
+            .build();
+        Ok(InteractionResponse::UpdateMessage(cb))
+    }
+}
+
+/// Applies the configured maximum length to the text of a new task, cutting it short or returning
+/// `None` if it's to be rejected.
+fn limit_task_length(config: &Config, text: String) -> Option<String> {
+    let max = config.max_task_length;
+    if text.char_indices().nth(max).is_none() {
+        return Some(text);
+    }
+    match config.long_tasks {
+        LongTasks::Reject => None,
+        LongTasks::Truncate => {
+            // One character short, to leave room for the ellipsis.
+            let end = text
+                .char_indices()
+                .nth(max - 1)
+                .map_or(text.len(), |(idx, _)| idx);
+            Some(format!("{}…", text[..end].trim_end()))
+        }
+    }
+}
+
+/// The list a user's commands in `guild` act on, according to their preferences.
+async fn user_list(
+    state: &State,
+    user: Id<UserMarker>,
+    guild: Option<Id<GuildMarker>>,
+) -> Result<ListKey, StorageError> {
+    let preferences = state.storage.preferences(user).await?;
+    Ok(preferences.scope.list(user, guild))
+}
+
+#[derive(Debug)]
+pub struct WhoamiCommand {
+    pub user: User,
+    pub source: UserSource,
+    pub guild: Option<Id<GuildMarker>>,
+    pub channel: Id<ChannelMarker>,
+    pub locale: String,
+    pub permissions: Option<Permissions>,
+}
+
+impl ParseCommand for WhoamiCommand {
+    const COMMAND: &'static str = "whoami";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let (user, source) = parse_invoker_with_source(&command)?;
+        Ok(WhoamiCommand {
+            user: user.clone(),
+            source,
+            guild: parse_guild(&command).ok(),
+            channel: parse_channel(&command)?,
+            locale: parse_locale(&command)?,
+            permissions: parse_member_permissions(&command).ok(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for WhoamiCommand {
+    async fn run(self, _state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling whoami command: {:?}", self);
+        let locale = &self.locale;
+        let guild = match self.guild {
+            Some(guild) => format!("`{guild}`"),
+            None => message!(locale, "whoami.dm"),
+        };
+        let permissions = match self.permissions {
+            Some(permissions) => format!("`{:#x}`", permissions.bits()),
+            None => message!(locale, "whoami.dm"),
+        };
+        let tag = format!("{}#{:04}", self.user.name, self.user.discriminator);
+        let content = [
+            message!(
+                locale,
+                "whoami.user",
+                tag = tag,
+                id = self.user.id,
+                source = self.source,
+            ),
+            message!(
+                locale,
+                "whoami.created",
+                created = timestamp_of(self.user.id).as_secs(),
+            ),
+            message!(locale, "whoami.guild", guild = guild),
+            message!(locale, "whoami.channel", channel = self.channel),
+            message!(locale, "whoami.locale", locale = locale),
+            message!(locale, "whoami.permissions", permissions = permissions),
+        ];
+        let cb = CallbackDataBuilder::new()
+            .content(content.join("\n"))
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct SyncCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+}
+
+impl ParseCommand for SyncCommand {
+    const COMMAND: &'static str = "sync";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        Ok(SyncCommand {
+            user: parse_user(&command)?,
+            locale: parse_locale(&command)?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for SyncCommand {
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+    // Only the owner gets past the check, and they may need to retry a sync which partly failed.
+    const COST: u32 = 0;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling sync command: {:?}", self);
+        let content = if self.user != state.application.owner.id {
+            message!(&self.locale, "sync.not_owner")
+        } else {
+            state.sync_commands().await?.to_string()
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct AdminCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub action: AdminAction,
+}
+
+#[derive(Debug)]
+pub enum AdminAction {
+    /// Re-register every command, so clients drop any stale cached definitions.
+    ResetCommands,
+}
+
+impl ParseCommand for AdminCommand {
+    const COMMAND: &'static str = "admin";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command)?;
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let action = match options.subcommand()? {
+            (name, _) if name == "reset-commands" => AdminAction::ResetCommands,
+            (name, _) => return Err(CommandError::UnknownSubcommand(name)),
+        };
+        Ok(AdminCommand {
+            user,
+            locale,
+            action,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for AdminCommand {
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+    // Anyone but the owner is turned away by the owner check before anything is re-registered, and
+    // the owner may need to reset the commands again straight after a partial failure.
+    const COST: u32 = 0;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling admin command: {:?}", self);
+        // Re-registering affects every guild the bot is in, so it's up to the bot's owner.
+        let content = if self.user != state.application.owner.id {
+            message!(&self.locale, "admin.not_owner")
+        } else {
+            match self.action {
+                AdminAction::ResetCommands => state.resync_commands().await?.to_string(),
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct BackupCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub action: BackupAction,
+}
+
+#[derive(Debug)]
+pub enum BackupAction {
+    /// Take a backup right away.
+    Now,
+}
+
+impl ParseCommand for BackupCommand {
+    const COMMAND: &'static str = "backup";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command)?;
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let action = match options.subcommand()? {
+            (name, _) if name == "now" => BackupAction::Now,
+            (name, _) => return Err(CommandError::UnknownSubcommand(name)),
+        };
+        Ok(BackupCommand {
+            user,
+            locale,
+            action,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for BackupCommand {
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+    // Free for the owner, the only one who can take a backup; anyone else is turned away before
+    // anything is written.
+    const COST: u32 = 0;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling backup command: {:?}", self);
+        let content = if self.user != state.application.owner.id {
+            message!(&self.locale, "backup.not_owner")
+        } else {
+            match self.action {
+                BackupAction::Now => match state.backup().await {
+                    Ok(backup) => message!(
+                        &self.locale,
+                        "backup.written",
+                        size = backup.size,
+                        path = backup.path.display(),
+                    ),
+                    Err(e) => {
+                        log::error!("backup failed: {e:#}");
+                        message!(&self.locale, "backup.failed", error = format!("{e:#}"))
+                    }
+                },
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct DebugCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    /// The token for sending the stored record as a follow-up message.
+    pub token: String,
+    /// The user whose stored data is shown.
+    pub target: Id<UserMarker>,
+}
+
+impl ParseCommand for DebugCommand {
+    const COMMAND: &'static str = "debug";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let locale = parse_locale(&command)?;
+        let token = command.token.clone();
+        let mut options = Options::new(command.data.options);
+        let target = options.required("user").map(|UserOrMention(target)| target);
+        match (user, target) {
+            (Ok(user), Ok(target)) => Ok(DebugCommand {
+                user,
+                locale,
+                token,
+                target,
+            }),
+            (user, target) => Err(CommandError::collect([user.err(), target.err()])),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for DebugCommand {
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+    // Only the owner can inspect a user's data; everyone else just gets a refusal.
+    const COST: u32 = 0;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling debug command: {:?}", self);
+        let content = if self.user != state.application.owner.id {
+            message!(&self.locale, "debug.not_owner")
+        } else {
+            self.describe(state).await?
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+impl DebugCommand {
+    /// Sends everything stored about the target user as an ephemeral JSON file, and describes
+    /// each of their lists.
+    async fn describe(&self, state: &State) -> anyhow::Result<String> {
+        let data = state.storage.export_user(self.target).await?;
+        let user = format!("<@{}>", self.target);
+        if data.lists.is_empty() && data.preferences.is_empty() {
+            return Ok(message!(&self.locale, "debug.empty", user = user));
+        }
+        let json = storage::to_json(&data)?;
+        let filename = format!("user-{}.json", self.target);
+        let files = [AttachmentFile::from_bytes(&filename, &json)];
+        send_ephemeral_files(state, &self.token, &files).await?;
+        let mut lines = vec![message!(&self.locale, "debug.summary", user = user)];
+        for (&list, tasks) in &data.lists {
+            let count = state.storage.count_tasks(list).await?;
+            let list = match list.guild {
+                Some(guild) => format!("guild `{guild}`"),
+                None => "global".into(),
+            };
+            let line = match count.completed {
+                Some(completed) => message!(
+                    &self.locale,
+                    "debug.list.completed",
+                    list = list,
+                    open = tasks.len(),
+                    completed = completed,
+                ),
+                None => message!(&self.locale, "debug.list", list = list, open = tasks.len()),
+            };
+            lines.push(line);
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+#[derive(Debug)]
+pub struct UsageCommand {
+    pub user: Id<UserMarker>,
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    pub window: UsageWindow,
+}
+
+/// How far back the `usage` command counts from.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum UsageWindow {
+    /// Today, in UTC.
+    Day,
+    /// The last seven days, including today.
+    #[default]
+    Week,
+    /// Since usage was first recorded.
+    All,
+}
+
+impl ParseOption for UsageWindow {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        match &*string {
+            "day" => Ok(UsageWindow::Day),
+            "week" => Ok(UsageWindow::Week),
+            "all" => Ok(UsageWindow::All),
+            _ => Err(OptionError::InvalidValue {
+                value: string,
+                reason: "expected one of `day`, `week`, or `all`".into(),
+            }),
+        }
+    }
+}
+
+impl ParseCommand for UsageCommand {
+    const COMMAND: &'static str = "usage";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let user = parse_user(&command);
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        let window = options.optional("window");
+        match (user, window) {
+            (Ok(user), Ok(window)) => Ok(UsageCommand {
+                user,
+                locale,
+                window: window.unwrap_or_default(),
+            }),
+            (user, window) => Err(CommandError::collect([user.err(), window.err()])),
+        }
+    }
+}
+
+/// The most commands the `usage` command lists.
+const MAX_USAGE_ROWS: usize = 25;
+
+#[async_trait::async_trait]
+impl RunCommand for UsageCommand {
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Deferred { ephemeral: true };
+    // Only the owner can read usage, which is refused to anyone else without reading storage.
+    const COST: u32 = 0;
+
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling usage command: {:?}", self);
+        let content = if self.user != state.application.owner.id {
+            message!(&self.locale, "usage.not_owner")
+        } else {
+            let today = storage::day(SystemTime::now());
+            let (since, header) = match self.window {
+                UsageWindow::Day => (Some(today), message!(&self.locale, "usage.day")),
+                UsageWindow::Week => (Some(today - 6), message!(&self.locale, "usage.week")),
+                UsageWindow::All => (None, message!(&self.locale, "usage.all")),
+            };
+            let mut usage = state
+                .storage
+                .usage(since)
+                .await?
+                .into_iter()
+                .collect::<Vec<_>>();
+            usage.sort_by_key(|(_, usage)| Reverse(usage.invocations));
+            if usage.is_empty() {
+                message!(&self.locale, "usage.empty")
+            } else {
+                let rows = usage.iter().take(MAX_USAGE_ROWS).map(|(command, usage)| {
+                    message!(
+                        &self.locale,
+                        "usage.row",
+                        command = command,
+                        invocations = usage.invocations,
+                        errors = usage.errors,
+                        last_used = usage.last_used / 1000,
+                    )
+                });
+                std::iter::once(header)
+                    .chain(rows)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+#[derive(Debug)]
+pub struct HelpCommand {
+    /// The invoking user's locale, which responses are written in.
+    pub locale: String,
+    /// The command to show details for, or `None` to list every command.
+    pub command: Option<String>,
+}
+
+impl ParseCommand for HelpCommand {
+    const COMMAND: &'static str = "help";
+
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
+        let locale = parse_locale(&command)?;
+        let mut options = Options::new(command.data.options);
+        Ok(HelpCommand {
+            locale,
+            command: options.optional("command")?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RunCommand for HelpCommand {
+    async fn run(self, state: &State) -> anyhow::Result<InteractionResponse> {
+        log::info!("handling help command: {:?}", self);
+        let commands = state.registry.commands();
+        let content = match self.command {
+            // Context menu commands aren't typed, so only slash commands are listed.
+            None => commands
+                .iter()
+                .filter(|command| command.kind == CommandType::ChatInput)
+                .map(|command| format!("`/{}`: {}", command.name, command.description))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Some(name) => {
+                let name = name.trim_start_matches('/');
+                let prefixed = format!("{}{name}", state.registry.prefix());
+                match commands
+                    .iter()
+                    .find(|command| command.name == name || command.name == prefixed)
+                {
+                    Some(command) => command_help(&self.locale, command),
+                    None => message!(&self.locale, "help.no_such_command", name = name),
+                }
+            }
+        };
+        let cb = CallbackDataBuilder::new()
+            .content(content)
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        Ok(InteractionResponse::ChannelMessageWithSource(cb))
+    }
+}
+
+/// Describes a command and each of its options.
+fn command_help(locale: &str, command: &Command) -> String {
+    let mut lines = vec![format!("`/{}`: {}", command.name, command.description)];
+    if command.options.is_empty() {
+        lines.push(message!(locale, "help.no_options"));
+    } else {
+        lines.push(message!(locale, "help.options"));
+        lines.extend(command.options.iter().map(|option| {
+            let (name, description) = option_name_description(option);
+            let required = if option.is_required() {
+                message!(locale, "help.required")
+            } else {
+                message!(locale, "help.optional")
+            };
+            format!(
+                "- `{name}` ({}, {required}): {description}",
+                option.kind().kind(),
+            )
+        }));
+    }
+    lines.join("\n")
+}
+
+fn option_name_description(option: &CommandOption) -> (&str, &str) {
+    match option {
+        CommandOption::SubCommand(data) | CommandOption::SubCommandGroup(data) => {
+            (&data.name, &data.description)
+        }
+        CommandOption::String(data) => (&data.name, &data.description),
+        CommandOption::Integer(data) | CommandOption::Number(data) => {
+            (&data.name, &data.description)
+        }
+        CommandOption::Channel(data) => (&data.name, &data.description),
+        CommandOption::Boolean(data)
+        | CommandOption::User(data)
+        | CommandOption::Role(data)
+        | CommandOption::Mentionable(data)
+        | CommandOption::Attachment(data) => (&data.name, &data.description),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{Error, OptionError};
+    use crate::test_util::InteractionFixture;
+
+    #[test]
+    fn parses_a_task_in_a_guild() {
+        let command = InteractionFixture::new("task")
+            .user(5)
+            .guild(10)
+            .locale("de")
+            .string_option("task", "buy milk")
+            .string_option("emoji", "🥛")
+            .attachment_option("image", 40, "image/png")
+            .bool_option("top", true)
+            .build();
+        let task = TaskCommand::parse(command).unwrap();
+        assert_eq!(task.user, Id::new(5));
+        assert_eq!(task.guild, Some(Id::new(10)));
+        assert_eq!(task.locale, "de");
+        assert_eq!(task.task, "buy milk");
+        assert!(task.emoji.is_some());
+        assert_eq!(
+            task.image_url.as_deref(),
+            Some("https://cdn.example.com/40")
+        );
+        assert!(task.top);
+    }
+
+    #[test]
+    fn a_task_in_a_dm_goes_to_the_global_list_at_the_bottom() {
+        let command = InteractionFixture::new("task")
+            .string_option("task", "buy milk")
+            .build();
+        let task = TaskCommand::parse(command).unwrap();
+        assert_eq!(task.guild, None);
+        assert!(task.emoji.is_none());
+        assert!(task.image_url.is_none());
+        assert!(!task.top);
+    }
+
+    #[test]
+    fn reports_every_invalid_option_of_a_task() {
+        let command = InteractionFixture::new("task")
+            .string_option("task", "  ")
+            .string_option("emoji", "milk")
+            .int_option("top", 1)
+            .build();
+        match TaskCommand::parse(command) {
+            Err(Error::CommandError {
+                command: "task",
+                error: CommandError::Multiple(errors),
+            }) => {
+                assert!(matches!(
+                    errors[..],
+                    [
+                        CommandError::InvalidOption {
+                            option: "task",
+                            error: OptionError::Validation { .. },
+                        },
+                        CommandError::InvalidOption {
+                            option: "emoji",
+                            error: OptionError::InvalidValue { .. },
+                        },
+                        CommandError::InvalidOption {
+                            option: "top",
+                            error: OptionError::InvalidType { .. },
+                        },
+                    ]
+                ));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_task_needs_text() {
+        let command = InteractionFixture::new("task").build();
+        assert!(matches!(
+            TaskCommand::parse(command),
+            Err(Error::CommandError {
+                error: CommandError::MissingOption("task"),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn parses_done() {
+        let command = InteractionFixture::new("done")
+            .guild(10)
+            .int_option("task", 2)
+            .build();
+        let done = DoneCommand::parse(command).unwrap();
+        assert_eq!(done.task, 2);
+        assert_eq!(done.guild, Some(Id::new(10)));
+    }
+
+    #[test]
+    fn done_accepts_the_index_as_a_string() {
+        let command = InteractionFixture::new("done")
+            .string_option("task", "3")
+            .build();
+        assert_eq!(DoneCommand::parse(command).unwrap().task, 3);
+    }
+
+    #[test]
+    fn done_rejects_a_negative_index() {
+        let command = InteractionFixture::new("done")
+            .int_option("task", -1)
+            .build();
+        assert!(matches!(
+            DoneCommand::parse(command),
+            Err(Error::CommandError {
+                error: CommandError::InvalidOption {
+                    option: "task",
+                    error: OptionError::OutOfRange { value: -1, .. },
+                },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn adds_the_text_of_the_target_message() {
+        let command =
+            InteractionFixture::message_command("Add to todo", 30, "  buy milk\n").build();
+        assert_eq!(AddToTodoCommand::parse(command).unwrap().task, "buy milk");
+    }
+
+    #[test]
+    fn rejects_an_unknown_admin_subcommand() {
+        let command = InteractionFixture::new("admin")
+            .subcommand("reset-everything", |sub| sub)
+            .build();
+        assert!(matches!(
+            AdminCommand::parse(command),
+            Err(Error::CommandError {
+                error: CommandError::UnknownSubcommand(name),
+                ..
+            }) if name == "reset-everything"
+        ));
+    }
+
+    /// The option `name` of `command`, as it's registered with Discord.
+    fn registered_option(command: &str, name: &str) -> CommandOption {
+        let registry = crate::commands(String::new()).unwrap();
+        let command = registry
+            .commands()
+            .into_iter()
+            .find(|registered| registered.name == command)
+            .unwrap();
+        command
+            .options
+            .into_iter()
+            .find(|option| option_name_description(option).0 == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn integer_options_are_registered_as_they_are_parsed() {
+        let task = registered_option("done", "task");
+        assert_eq!(task.kind(), usize::KIND);
+        assert!(task.is_required());
+
+        let limit = registered_option("list", "limit");
+        assert_eq!(limit.kind(), usize::KIND);
+        assert!(!limit.is_required());
+    }
+}
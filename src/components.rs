@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_util::future::BoxFuture;
+use tokio::time::MissedTickBehavior;
+use twilight_model::{
+    application::{
+        callback::{CallbackData, InteractionResponse},
+        component::Component,
+        interaction::MessageComponentInteraction,
+    },
+    channel::message::MessageFlags,
+    id::{marker::InteractionMarker, Id},
+};
+use twilight_util::builder::CallbackDataBuilder;
+
+use crate::messages::message;
+use crate::State;
+
+type HandlerResult = anyhow::Result<InteractionResponse>;
+
+/// How far ahead of this instance's clock another instance's may be, so that a component it has
+/// only just sent doesn't look like it was sent in the future.
+const CLOCK_SKEW: Duration = Duration::from_secs(5);
+
+/// How often to check for sent components which have expired.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A kind of message component, such as the buttons of one feature, which knows how to respond
+/// to clicks on itself.
+///
+/// Each component's `custom_id` is its kind's [`PREFIX`](Self::PREFIX), when it was sent (in
+/// seconds since the Unix epoch), and a payload saying which component of that kind it is,
+/// separated by colons, e.g. `forget-me:1700000000:confirm`.
+#[async_trait::async_trait]
+pub trait HandleComponent: Send + Sized {
+    /// The prefix of the `custom_id`s of components of this kind, which mustn't contain a colon.
+    const PREFIX: &'static str;
+
+    /// Decodes the payload of a `custom_id`, returning `None` if it isn't one this kind of
+    /// component uses (any more).
+    fn decode(payload: &str) -> Option<Self>;
+
+    /// Encodes the component as the payload of its `custom_id`.
+    fn encode(&self) -> String;
+
+    /// The `custom_id` to give the component.
+    fn custom_id(&self) -> String {
+        let issued_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format!("{}:{issued_at}:{}", Self::PREFIX, self.encode())
+    }
+
+    async fn handle(self, state: &State, component: &MessageComponentInteraction) -> HandlerResult;
+}
+
+/// Decodes a payload into the component type a handler was registered for, and returns the
+/// handler's future, or `None` if the payload isn't one it knows.
+type Handler = Box<
+    dyn for<'a> Fn(
+            &'a State,
+            &'a MessageComponentInteraction,
+            &str,
+        ) -> Option<BoxFuture<'a, HandlerResult>>
+        + Send
+        + Sync,
+>;
+
+/// The kinds of message component the bot knows about, along with the handler for each.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    handlers: BTreeMap<&'static str, Handler>,
+    /// Responses with components which are still usable, oldest first, so that they can be
+    /// disabled once they expire.
+    sent: Mutex<VecDeque<Sent>>,
+}
+
+/// A response with components, sent in answer to an interaction.
+struct Sent {
+    interaction: Id<InteractionMarker>,
+    /// The interaction's token, which the response can be edited with.
+    token: String,
+    components: Vec<Component>,
+    expires_at: Instant,
+}
+
+/// What to answer a click on a component with.
+pub enum Answer {
+    /// The component handler's response, or one saying the component isn't known.
+    Response(InteractionResponse),
+    /// The component has expired, so the message it's on is updated to disable its components,
+    /// and the user is told why in an ephemeral follow-up.
+    Expired {
+        update: InteractionResponse,
+        notice: CallbackData,
+    },
+}
+
+impl ComponentRegistry {
+    /// Registers the component kind `C`.
+    ///
+    /// Panics if another kind was already registered with the same prefix, since clicks on either
+    /// would go to the wrong handler.
+    pub fn register<C>(&mut self) -> &mut Self
+    where
+        C: HandleComponent + 'static,
+    {
+        assert!(
+            !C::PREFIX.contains(':'),
+            "component prefix `{}` contains a colon",
+            C::PREFIX,
+        );
+        let previous = self.handlers.insert(C::PREFIX, Box::new(handler::<C>));
+        assert!(
+            previous.is_none(),
+            "component prefix `{}` is registered twice",
+            C::PREFIX,
+        );
+        self
+    }
+
+    /// Runs the handler for the clicked component.
+    ///
+    /// A component sent longer than `ttl` ago, or without the time it was sent in its
+    /// `custom_id`, has expired. A component no handler recognizes, such as one on a message sent
+    /// by an older version of the bot, is answered by telling the user that it has expired.
+    pub async fn dispatch(
+        &self,
+        state: &State,
+        component: &MessageComponentInteraction,
+        ttl: Duration,
+    ) -> anyhow::Result<Answer> {
+        let custom_id = &component.data.custom_id;
+        log::info!("handling component interaction: {custom_id:?}");
+        let (prefix, rest) = custom_id.split_once(':').unwrap_or((custom_id, ""));
+        let Some(handler) = self.handlers.get(prefix) else {
+            return Ok(Answer::Response(unknown(component)));
+        };
+        let issued_at = rest.split_once(':').and_then(|(issued_at, payload)| {
+            let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(issued_at.parse().ok()?);
+            Some((issued_at, payload))
+        });
+        let payload = match issued_at {
+            Some((issued_at, payload)) if !has_expired(issued_at, ttl) => payload,
+            _ => {
+                log::info!("expired component {custom_id:?}");
+                self.forget(component);
+                return Ok(expired(component));
+            }
+        };
+        let Some(future) = handler(state, component, payload) else {
+            return Ok(Answer::Response(unknown(component)));
+        };
+        let response = future.await?;
+        if let InteractionResponse::UpdateMessage(CallbackData {
+            components: Some(_),
+            ..
+        }) = &response
+        {
+            // The handler has replaced the components, which mustn't be brought back disabled.
+            self.forget(component);
+        }
+        Ok(Answer::Response(response))
+    }
+
+    /// Keeps track of a response to an interaction, if it has components, so that they're
+    /// disabled once they expire.
+    pub fn track(
+        &self,
+        interaction: Id<InteractionMarker>,
+        token: &str,
+        response: &InteractionResponse,
+        ttl: Duration,
+    ) {
+        let InteractionResponse::ChannelMessageWithSource(CallbackData {
+            components: Some(components),
+            ..
+        }) = response
+        else {
+            return;
+        };
+        if components.is_empty() {
+            return;
+        }
+        self.sent.lock().unwrap().push_back(Sent {
+            interaction,
+            token: token.into(),
+            components: components.clone(),
+            expires_at: Instant::now() + ttl,
+        });
+    }
+
+    /// Stops keeping track of the response a component is on.
+    fn forget(&self, component: &MessageComponentInteraction) {
+        if let Some(interaction) = &component.message.interaction {
+            let mut sent = self.sent.lock().unwrap();
+            sent.retain(|sent| sent.interaction != interaction.id);
+        }
+    }
+
+    /// Takes the tracked responses which have expired.
+    fn take_expired(&self) -> Vec<Sent> {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        // Every response is tracked for as long as the others, so they expire in order.
+        let expired = sent
+            .iter()
+            .take_while(|sent| sent.expires_at <= now)
+            .count();
+        sent.drain(..expired).collect()
+    }
+}
+
+fn handler<'a, C: HandleComponent + 'static>(
+    state: &'a State,
+    component: &'a MessageComponentInteraction,
+    payload: &str,
+) -> Option<BoxFuture<'a, HandlerResult>> {
+    let decoded = C::decode(payload)?;
+    Some(Box::pin(decoded.handle(state, component)))
+}
+
+/// Whether a component sent at `issued_at` has expired, allowing for [`CLOCK_SKEW`] between the
+/// instance which sent it and this one.
+fn has_expired(issued_at: SystemTime, ttl: Duration) -> bool {
+    let now = SystemTime::now();
+    match now.duration_since(issued_at) {
+        Ok(age) => age > ttl + CLOCK_SKEW,
+        Err(e) => e.duration() > CLOCK_SKEW,
+    }
+}
+
+fn unknown(component: &MessageComponentInteraction) -> InteractionResponse {
+    log::info!("unknown component {:?}", component.data.custom_id);
+    let cb = CallbackDataBuilder::new()
+        .content(message!(&component.locale, "error.component_expired"))
+        .flags(MessageFlags::EPHEMERAL)
+        .build();
+    InteractionResponse::ChannelMessageWithSource(cb)
+}
+
+fn expired(component: &MessageComponentInteraction) -> Answer {
+    let update = CallbackDataBuilder::new()
+        .components(disabled(&component.message.components))
+        .build();
+    let notice = CallbackDataBuilder::new()
+        .content(message!(&component.locale, "error.component_expired"))
+        .flags(MessageFlags::EPHEMERAL)
+        .build();
+    Answer::Expired {
+        update: InteractionResponse::UpdateMessage(update),
+        notice,
+    }
+}
+
+/// Copies components, disabling every button and select menu.
+fn disabled(components: &[Component]) -> Vec<Component> {
+    components
+        .iter()
+        .map(|component| match component {
+            Component::ActionRow(row) => {
+                let mut row = row.clone();
+                row.components = disabled(&row.components);
+                Component::ActionRow(row)
+            }
+            Component::Button(button) => {
+                let mut button = button.clone();
+                button.disabled = true;
+                Component::Button(button)
+            }
+            Component::SelectMenu(menu) => {
+                let mut menu = menu.clone();
+                menu.disabled = true;
+                Component::SelectMenu(menu)
+            }
+        })
+        .collect()
+}
+
+/// Disables the components of responses once they expire, so that they don't look like they
+/// still work.
+///
+/// Discord only lets a response be edited for 15 minutes, so components which last longer than
+/// that are left as they are, and only refuse clicks.
+pub async fn run_periodic(state: Arc<State>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        for sent in state.components.take_expired() {
+            let components = disabled(&sent.components);
+            let result = state
+                .discord
+                .update_original_components(&sent.token, &components)
+                .await;
+            if let Err(e) = result {
+                log::debug!(
+                    "failed to disable the components of the response to interaction {}: {e:#}",
+                    sent.interaction,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use tracing_subscriber::filter::LevelFilter;
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+
+use crate::storage::Backend;
+
+const CONFIG_PATH: &str = "config.toml";
+const CONFIG_PATH_VAR: &str = "TODO_BOT_CONFIG";
+const TOKEN_PATH: &str = "token";
+const DB_PATH: &str = "todo.db";
+const JSON_PATH: &str = "todo.json";
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAX_CONCURRENT_INTERACTIONS: usize = 32;
+const MAX_TASKS: usize = 500;
+const MAX_TASK_LENGTH: usize = 1000;
+const BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const BACKUP_RETAIN: usize = 7;
+const COOLDOWN_COMMANDS: u32 = 10;
+const COOLDOWN_PERIOD: Duration = Duration::from_secs(20);
+const PRESENCE_TEMPLATE: &str = "{tasks} tasks across {users} users";
+const PRESENCE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// How long buttons stay usable for by default, which is short enough to still disable them when
+/// they expire.
+const COMPONENT_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Flags which don't take a value, such as `--register-only`.
+const SWITCHES: &[&str] = &["register-only"];
+
+/// Settings for the bot.
+///
+/// These are read from `config.toml` (or the file given by `--config` or `TODO_BOT_CONFIG`), with
+/// any setting overridable by a `TODO_BOT_*` environment variable, and in turn by a command line
+/// flag, e.g. `--db-path` for `db_path`. Secrets can only be set in the file or the environment,
+/// and the token can also be set by `DISCORD_TOKEN`, or failing everything else, read from the
+/// `token` file.
+///
+/// This deliberately doesn't implement `Debug`, so that the secrets in it can't be logged.
+pub struct Config {
+    pub token: String,
+    /// Whether adding a task that is already on the list should be refused.
+    pub dedup_tasks: bool,
+    /// The most tasks a user's list can hold.
+    pub max_tasks: usize,
+    /// The longest a task can be, in characters. Discord doesn't limit the options of commands
+    /// to less than several thousand characters, so this is checked by the bot.
+    pub max_task_length: usize,
+    /// What happens to a task longer than `max_task_length`.
+    pub long_tasks: LongTasks,
+    pub log_level: LevelFilter,
+    /// Whether to register commands at startup even if they haven't changed.
+    pub force_sync: bool,
+    /// Whether to only register commands and then exit, without connecting to the gateway, e.g.
+    /// as a separate deployment step.
+    pub register_only: bool,
+    /// When set, commands are registered only in this guild, where changes show up immediately,
+    /// instead of globally.
+    pub dev_guild: Option<Id<GuildMarker>>,
+    /// Prepended to the name of every command, e.g. `staging_` to register `staging_task`.
+    /// Syncing only deletes stale commands with this prefix, so without one it deletes none,
+    /// since they can't be told apart from the commands of other instances.
+    pub command_prefix: String,
+    /// Where the todo lists are stored.
+    pub storage: Backend,
+    /// The path of the JSON file the todo lists are stored in.
+    pub json_path: String,
+    /// How often the `journal` backend syncs its log to disk, or `None` to sync after every change.
+    pub fsync_interval: Option<Duration>,
+    /// How often the `journal` backend compacts its log into a new snapshot.
+    pub snapshot_interval: Duration,
+    /// How many interactions may be handled at once.
+    pub max_concurrent_interactions: usize,
+    /// The path of the SQLite database the todo lists are stored in.
+    pub db_path: String,
+    /// The URL of a Postgres database to store the todo lists in instead, so that multiple
+    /// instances of the bot can share them.
+    pub database_url: Option<String>,
+    /// The directory backups are written to, or `None` to not take scheduled backups.
+    pub backup_dir: Option<String>,
+    /// How often a scheduled backup is taken.
+    pub backup_interval: Duration,
+    /// How many backups to keep; older ones are deleted.
+    pub backup_retain: usize,
+    /// Whether to DM the owner of the bot when a scheduled backup fails.
+    pub backup_notify_owner: bool,
+    /// Whether to DM the owner of the bot when a command handler panics.
+    pub panic_notify_owner: bool,
+    /// A URL to post every completed task to, e.g. for an external dashboard.
+    pub webhook_url: Option<String>,
+    /// A channel to post operational errors to.
+    pub error_channel: Option<Id<ChannelMarker>>,
+    /// A webhook URL to post operational errors to, instead of `error_channel`.
+    pub error_webhook_url: Option<String>,
+    /// The host (and port) to send Discord API requests to over plain HTTP instead of to Discord,
+    /// e.g. twilight's HTTP proxy, or a mock server for testing.
+    pub api_proxy: Option<String>,
+    /// The address to serve the `/healthz`, `/readyz` and `/metrics` endpoints on, or `None` to
+    /// not serve them.
+    pub health_addr: Option<SocketAddr>,
+    /// The address to receive interactions on over HTTP, as the application's interactions
+    /// endpoint, instead of from the gateway, or `None` to use the gateway. Discord only sends to
+    /// HTTPS URLs, so this needs a proxy in front of it, and without the gateway the bot has no
+    /// status.
+    pub interactions_addr: Option<SocketAddr>,
+    /// How many commands a user can use in a burst; mutating commands count double.
+    pub cooldown_commands: u32,
+    /// How long a user's burst of commands takes to recover.
+    pub cooldown_period: Duration,
+    /// Whether the bot's status shows how many tasks it's keeping track of.
+    pub presence: bool,
+    /// The bot's status, with `{tasks}` and `{users}` replaced by the totals across every list.
+    pub presence_template: String,
+    /// How often the bot's status is updated.
+    pub presence_interval: Duration,
+    /// The response to adding a task, with `{task}` and `{index}` replaced by the task and its
+    /// index, in place of the built-in response in the user's language.
+    pub added_template: Option<String>,
+    /// Whether a todo list too long for one message is sent as a file, rather than over several
+    /// messages.
+    pub long_lists_as_file: bool,
+    /// Whether to send the daily digests users ask for. When several instances share a database,
+    /// only one of them should, or users get one from each.
+    pub digests: bool,
+    /// How long buttons and other message components can be used for after they're sent. Ones
+    /// sent less than 15 minutes ago are disabled once they expire; older ones only refuse clicks,
+    /// since Discord no longer lets the bot edit them.
+    pub component_ttl: Duration,
+}
+
+/// The contents of `config.toml`, where every setting is optional.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    token: Option<String>,
+    dedup_tasks: Option<bool>,
+    max_tasks: Option<usize>,
+    max_task_length: Option<usize>,
+    long_tasks: Option<LongTasks>,
+    log_level: Option<String>,
+    force_sync: Option<bool>,
+    dev_guild: Option<Id<GuildMarker>>,
+    command_prefix: Option<String>,
+    storage: Option<Backend>,
+    json_path: Option<String>,
+    fsync_secs: Option<u64>,
+    snapshot_secs: Option<u64>,
+    max_concurrent_interactions: Option<usize>,
+    db_path: Option<String>,
+    database_url: Option<String>,
+    backup_dir: Option<String>,
+    backup_secs: Option<u64>,
+    backup_retain: Option<usize>,
+    backup_notify_owner: Option<bool>,
+    panic_notify_owner: Option<bool>,
+    webhook_url: Option<String>,
+    error_channel: Option<Id<ChannelMarker>>,
+    error_webhook_url: Option<String>,
+    api_proxy: Option<String>,
+    health_addr: Option<SocketAddr>,
+    interactions_addr: Option<SocketAddr>,
+    cooldown_commands: Option<u32>,
+    cooldown_secs: Option<u64>,
+    presence: Option<bool>,
+    presence_template: Option<String>,
+    presence_secs: Option<u64>,
+    added_template: Option<String>,
+    long_lists_as_file: Option<bool>,
+    digests: Option<bool>,
+    component_ttl_secs: Option<u64>,
+}
+
+impl Config {
+    /// Loads the configuration, with command line flags taking priority over environment
+    /// variables, which take priority over the config file.
+    pub fn load() -> anyhow::Result<Self> {
+        let mut args = Args::parse(std::env::args().skip(1))?;
+        let config_path = args
+            .take("config")
+            .or_else(|| std::env::var(CONFIG_PATH_VAR).ok());
+        let config_path_display = config_path.as_deref().unwrap_or(CONFIG_PATH).to_owned();
+        let mut file = match std::fs::read_to_string(&config_path_display) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("failed to parse `{config_path_display}`"))?,
+            // Only a missing default config file is fine; one that was asked for must exist.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && config_path.is_none() => {
+                ConfigFile::default()
+            }
+            Err(e) => return Err(e).context(format!("failed to read `{config_path_display}`")),
+        };
+
+        // Secrets can't be given on the command line, where other users could see them.
+        override_from_env(&mut file.token, "DISCORD_TOKEN")?;
+        override_from_env(&mut file.token, "TODO_BOT_TOKEN")?;
+        override_from_env(&mut file.database_url, "TODO_BOT_DATABASE_URL")?;
+        override_from_env(&mut file.webhook_url, "TODO_BOT_WEBHOOK_URL")?;
+        override_from_env(&mut file.error_webhook_url, "TODO_BOT_ERROR_WEBHOOK_URL")?;
+        args.apply(&mut file.dedup_tasks, "dedup_tasks")?;
+        args.apply(&mut file.max_tasks, "max_tasks")?;
+        args.apply(&mut file.max_task_length, "max_task_length")?;
+        args.apply(&mut file.long_tasks, "long_tasks")?;
+        args.apply(&mut file.log_level, "log_level")?;
+        args.apply(&mut file.force_sync, "force_sync")?;
+        args.apply(&mut file.dev_guild, "dev_guild")?;
+        args.apply(&mut file.command_prefix, "command_prefix")?;
+        args.apply(&mut file.storage, "storage")?;
+        args.apply(&mut file.json_path, "json_path")?;
+        args.apply(&mut file.fsync_secs, "fsync_secs")?;
+        args.apply(&mut file.snapshot_secs, "snapshot_secs")?;
+        args.apply(
+            &mut file.max_concurrent_interactions,
+            "max_concurrent_interactions",
+        )?;
+        args.apply(&mut file.db_path, "db_path")?;
+        args.apply(&mut file.backup_dir, "backup_dir")?;
+        args.apply(&mut file.backup_secs, "backup_secs")?;
+        args.apply(&mut file.backup_retain, "backup_retain")?;
+        args.apply(&mut file.backup_notify_owner, "backup_notify_owner")?;
+        args.apply(&mut file.panic_notify_owner, "panic_notify_owner")?;
+        args.apply(&mut file.error_channel, "error_channel")?;
+        args.apply(&mut file.api_proxy, "api_proxy")?;
+        args.apply(&mut file.health_addr, "health_addr")?;
+        args.apply(&mut file.interactions_addr, "interactions_addr")?;
+        args.apply(&mut file.cooldown_commands, "cooldown_commands")?;
+        args.apply(&mut file.cooldown_secs, "cooldown_secs")?;
+        args.apply(&mut file.presence, "presence")?;
+        args.apply(&mut file.presence_template, "presence_template")?;
+        args.apply(&mut file.presence_secs, "presence_secs")?;
+        args.apply(&mut file.added_template, "added_template")?;
+        args.apply(&mut file.long_lists_as_file, "long_lists_as_file")?;
+        args.apply(&mut file.digests, "digests")?;
+        args.apply(&mut file.component_ttl_secs, "component_ttl_secs")?;
+        let register_only = args.take("register_only").is_some();
+        args.finish()?;
+        Config::from_file(file, register_only, &config_path_display)
+    }
+
+    /// The default configuration, which tests then adjust as they need.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn for_tests() -> Self {
+        let file = ConfigFile {
+            token: Some("token".into()),
+            ..ConfigFile::default()
+        };
+        Config::from_file(file, false, CONFIG_PATH).expect("the defaults should be valid")
+    }
+
+    /// Fills in the defaults for the settings `file` leaves unset, and checks the ones it sets.
+    fn from_file(
+        file: ConfigFile,
+        register_only: bool,
+        config_path_display: &str,
+    ) -> anyhow::Result<Self> {
+        let token = match file.token {
+            Some(token) => token,
+            None => match std::fs::read_to_string(TOKEN_PATH) {
+                Ok(token) => token.trim().to_owned(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => anyhow::bail!(
+                    "no token configured; checked the `DISCORD_TOKEN` and `TODO_BOT_TOKEN` \
+                     environment variables, `token` in `{config_path_display}`, and the \
+                     `{TOKEN_PATH}` file"
+                ),
+                Err(e) => return Err(e).context(format!("failed to read `{TOKEN_PATH}`")),
+            },
+        };
+        let log_level = match file.log_level {
+            Some(level) => level
+                .parse()
+                .with_context(|| format!("invalid log level `{level}`"))?,
+            None => LevelFilter::INFO,
+        };
+
+        let snapshot_interval = match file.snapshot_secs {
+            Some(0) => anyhow::bail!("`snapshot_secs` must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => SNAPSHOT_INTERVAL,
+        };
+        let max_concurrent_interactions = match file.max_concurrent_interactions {
+            Some(0) => anyhow::bail!("`max_concurrent_interactions` must be positive"),
+            Some(max) => max,
+            None => MAX_CONCURRENT_INTERACTIONS,
+        };
+        let backup_interval = match file.backup_secs {
+            Some(0) => anyhow::bail!("`backup_secs` must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => BACKUP_INTERVAL,
+        };
+        let backup_retain = match file.backup_retain {
+            Some(0) => anyhow::bail!("`backup_retain` must be positive"),
+            Some(retain) => retain,
+            None => BACKUP_RETAIN,
+        };
+
+        if file.error_channel.is_some() && file.error_webhook_url.is_some() {
+            anyhow::bail!("only one of `error_channel` and `error_webhook_url` can be set");
+        }
+
+        let cooldown_commands = match file.cooldown_commands {
+            Some(0) => anyhow::bail!("`cooldown_commands` must be positive"),
+            Some(commands) => commands,
+            None => COOLDOWN_COMMANDS,
+        };
+        let cooldown_period = match file.cooldown_secs {
+            Some(0) => anyhow::bail!("`cooldown_secs` must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => COOLDOWN_PERIOD,
+        };
+        let presence_interval = match file.presence_secs {
+            Some(0) => anyhow::bail!("`presence_secs` must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => PRESENCE_INTERVAL,
+        };
+        let max_task_length = match file.max_task_length {
+            Some(0) => anyhow::bail!("`max_task_length` must be positive"),
+            Some(length) => length,
+            None => MAX_TASK_LENGTH,
+        };
+        let component_ttl = match file.component_ttl_secs {
+            Some(0) => anyhow::bail!("`component_ttl_secs` must be positive"),
+            Some(secs) => Duration::from_secs(secs),
+            None => COMPONENT_TTL,
+        };
+
+        Ok(Config {
+            token,
+            dedup_tasks: file.dedup_tasks.unwrap_or(false),
+            max_tasks: file.max_tasks.unwrap_or(MAX_TASKS),
+            max_task_length,
+            long_tasks: file.long_tasks.unwrap_or_default(),
+            log_level,
+            force_sync: file.force_sync.unwrap_or(false),
+            register_only,
+            dev_guild: file.dev_guild,
+            command_prefix: file.command_prefix.unwrap_or_default(),
+            storage: file.storage.unwrap_or(Backend::Json),
+            json_path: file.json_path.unwrap_or_else(|| JSON_PATH.into()),
+            // Zero means the same as unset: sync after every change.
+            fsync_interval: file
+                .fsync_secs
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            snapshot_interval,
+            max_concurrent_interactions,
+            db_path: file.db_path.unwrap_or_else(|| DB_PATH.into()),
+            database_url: file.database_url,
+            backup_dir: file.backup_dir,
+            backup_interval,
+            backup_retain,
+            backup_notify_owner: file.backup_notify_owner.unwrap_or(false),
+            panic_notify_owner: file.panic_notify_owner.unwrap_or(false),
+            webhook_url: file.webhook_url,
+            error_channel: file.error_channel,
+            error_webhook_url: file.error_webhook_url,
+            api_proxy: file.api_proxy,
+            health_addr: file.health_addr,
+            interactions_addr: file.interactions_addr,
+            cooldown_commands,
+            cooldown_period,
+            presence: file.presence.unwrap_or(true),
+            presence_template: file
+                .presence_template
+                .unwrap_or_else(|| PRESENCE_TEMPLATE.into()),
+            presence_interval,
+            added_template: file.added_template,
+            long_lists_as_file: file.long_lists_as_file.unwrap_or(true),
+            digests: file.digests.unwrap_or(true),
+            component_ttl,
+        })
+    }
+}
+
+/// What happens to a task longer than the maximum length.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LongTasks {
+    /// It isn't added, and the user is told why.
+    #[default]
+    Reject,
+    /// It's cut short, ending with an ellipsis.
+    Truncate,
+}
+
+impl FromStr for LongTasks {
+    type Err = UnknownLongTasks;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(LongTasks::Reject),
+            "truncate" => Ok(LongTasks::Truncate),
+            _ => Err(UnknownLongTasks(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown way of handling long tasks `{0}`, expected `reject` or `truncate`")]
+pub struct UnknownLongTasks(String);
+
+/// Settings given on the command line, as `--name value` or `--name=value`, or as `--name` for
+/// [`SWITCHES`].
+struct Args(HashMap<String, String>);
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut parsed = HashMap::new();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            let flag = arg
+                .strip_prefix("--")
+                .with_context(|| format!("unexpected argument `{arg}`"))?;
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_owned(), value.to_owned()),
+                None if SWITCHES.contains(&flag) => (flag.to_owned(), String::new()),
+                None => {
+                    let value = args
+                        .next()
+                        .with_context(|| format!("missing value for `{arg}`"))?;
+                    (flag.to_owned(), value)
+                }
+            };
+            parsed.insert(name, value);
+        }
+        Ok(Args(parsed))
+    }
+
+    /// Removes the flag for the setting `name`, e.g. `--db-path` for `db_path`.
+    fn take(&mut self, name: &str) -> Option<String> {
+        self.0.remove(&name.replace('_', "-"))
+    }
+
+    /// Overrides `setting` from the environment variable `TODO_BOT_<NAME>`, and then from the
+    /// command line flag for it.
+    fn apply<T>(&mut self, setting: &mut Option<T>, name: &str) -> anyhow::Result<()>
+    where
+        T: FromStr,
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        override_from_env(setting, &format!("TODO_BOT_{}", name.to_uppercase()))?;
+        if let Some(value) = self.take(name) {
+            *setting =
+                Some(value.parse().with_context(|| {
+                    format!("invalid value for `--{}`", name.replace('_', "-"))
+                })?);
+        }
+        Ok(())
+    }
+
+    /// Fails if any flags weren't recognized.
+    fn finish(self) -> anyhow::Result<()> {
+        match self.0.keys().next() {
+            Some(name) => anyhow::bail!("unknown flag `--{name}`"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Replaces `setting` with the value of the environment variable `var`, if it is set.
+fn override_from_env<T>(setting: &mut Option<T>, var: &str) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    if let Ok(value) = std::env::var(var) {
+        *setting = Some(
+            value
+                .parse()
+                .with_context(|| format!("invalid value for `{var}`"))?,
+        );
+    }
+    Ok(())
+}
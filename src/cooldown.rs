@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use twilight_model::id::{marker::UserMarker, Id};
+
+/// Limits how often each user can use commands, with a token bucket per user.
+///
+/// Each user's bucket holds up to `capacity` tokens and refills at `capacity` tokens per
+/// `period`. Using a command takes as many tokens as the command costs, so a user can send a
+/// burst of commands and then keep going at the refill rate.
+pub struct Cooldowns {
+    capacity: f64,
+    /// Tokens added to a bucket per second.
+    refill_rate: f64,
+    /// How long an empty bucket takes to fill; a bucket untouched for that long is full, and so
+    /// can be forgotten.
+    period: Duration,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    buckets: HashMap<Id<UserMarker>, Bucket>,
+    /// When buckets were last checked for ones which can be forgotten.
+    swept_at: Instant,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Cooldowns {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        Cooldowns {
+            capacity: capacity.into(),
+            refill_rate: f64::from(capacity) / period.as_secs_f64(),
+            period,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `cost` tokens from the user's bucket.
+    ///
+    /// If there aren't enough, none are taken, and this fails with how long until there will
+    /// be. A command which costs more than the capacity is always allowed once the bucket is
+    /// full, emptying it.
+    pub fn take(&self, user: Id<UserMarker>, cost: u32) -> Result<(), Duration> {
+        self.take_at(user, cost, Instant::now())
+    }
+
+    fn take_at(&self, user: Id<UserMarker>, cost: u32, now: Instant) -> Result<(), Duration> {
+        if cost == 0 {
+            return Ok(());
+        }
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.swept_at) >= self.period {
+            let period = self.period;
+            buckets
+                .buckets
+                .retain(|_, bucket| now.duration_since(bucket.updated_at) < period);
+            buckets.swept_at = now;
+        }
+        let bucket = buckets.buckets.entry(user).or_insert(Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate).min(self.capacity);
+        bucket.updated_at = now;
+        let cost = f64::from(cost).min(self.capacity);
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            let wait = (cost - bucket.tokens) / self.refill_rate;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn refills_over_time() {
+        let cooldowns = Cooldowns::new(3, PERIOD);
+        let (user, start) = (Id::new(1), Instant::now());
+        for _ in 0..3 {
+            assert_eq!(cooldowns.take_at(user, 1, start), Ok(()));
+        }
+        assert_eq!(
+            cooldowns.take_at(user, 1, start),
+            Err(Duration::from_secs(10))
+        );
+        let later = start + Duration::from_secs(5);
+        assert_eq!(
+            cooldowns.take_at(user, 1, later),
+            Err(Duration::from_secs(5))
+        );
+        let refilled = start + Duration::from_secs(10);
+        assert_eq!(cooldowns.take_at(user, 1, refilled), Ok(()));
+        assert!(cooldowns.take_at(user, 1, refilled).is_err());
+    }
+
+    #[test]
+    fn a_cost_above_the_capacity_takes_a_full_bucket() {
+        let cooldowns = Cooldowns::new(3, PERIOD);
+        let (user, start) = (Id::new(1), Instant::now());
+        assert_eq!(cooldowns.take_at(user, 5, start), Ok(()));
+        assert_eq!(
+            cooldowns.take_at(user, 5, start),
+            Err(Duration::from_secs(30))
+        );
+        assert_eq!(cooldowns.take_at(user, 5, start + PERIOD), Ok(()));
+    }
+
+    #[test]
+    fn free_commands_are_always_allowed() {
+        let cooldowns = Cooldowns::new(1, PERIOD);
+        let (user, start) = (Id::new(1), Instant::now());
+        assert_eq!(cooldowns.take_at(user, 1, start), Ok(()));
+        assert_eq!(cooldowns.take_at(user, 0, start), Ok(()));
+    }
+
+    #[test]
+    fn each_user_has_their_own_bucket() {
+        let cooldowns = Cooldowns::new(1, PERIOD);
+        let start = Instant::now();
+        assert_eq!(cooldowns.take_at(Id::new(1), 1, start), Ok(()));
+        assert!(cooldowns.take_at(Id::new(1), 1, start).is_err());
+        assert_eq!(cooldowns.take_at(Id::new(2), 1, start), Ok(()));
+    }
+
+    #[test]
+    fn concurrent_takes_never_exceed_the_capacity() {
+        // Slow enough to refill that no token comes back during the test.
+        let cooldowns = Arc::new(Cooldowns::new(10, Duration::from_secs(24 * 60 * 60)));
+        let threads = (0..8)
+            .map(|_| {
+                let cooldowns = Arc::clone(&cooldowns);
+                std::thread::spawn(move || {
+                    (0..50)
+                        .filter(|_| cooldowns.take(Id::new(1), 1).is_ok())
+                        .count()
+                })
+            })
+            .collect::<Vec<_>>();
+        let taken: usize = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .sum();
+        assert_eq!(taken, 10);
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::time::MissedTickBehavior;
+use twilight_model::id::{marker::UserMarker, Id};
+
+use crate::http::{HttpError, CANNOT_MESSAGE_USER};
+use crate::messages::message;
+use crate::storage::{self, Digest, ListKey};
+use crate::State;
+
+/// How often to check for digests which are due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// The largest offset from UTC accepted, in minutes; real timezones range from -12:00 to +14:00.
+const MAX_UTC_OFFSET: i32 = 14 * 60;
+
+/// Sends each user who has asked for one a DM of their open tasks, once a day at the time they
+/// chose.
+///
+/// Each check covers the minutes since the last one, so a digest isn't missed if a check runs
+/// late, but one due while the bot wasn't running isn't sent once it's back.
+pub async fn run_periodic(state: Arc<State>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut checked_until = minute(SystemTime::now());
+    loop {
+        interval.tick().await;
+        let now = minute(SystemTime::now());
+        if now <= checked_until {
+            continue;
+        }
+        let subscribers = match state.storage.digest_subscribers().await {
+            Ok(subscribers) => subscribers,
+            Err(e) => {
+                // Tried again at the next check, which covers these minutes too.
+                log::warn!("failed to read who gets a daily digest: {e}");
+                continue;
+            }
+        };
+        for (user, preferences) in subscribers {
+            let Some(digest) = preferences.digest else {
+                continue;
+            };
+            if is_due(&digest, preferences.utc_offset, checked_until, now) {
+                if let Err(e) = send(&state, user, &digest.locale).await {
+                    let code = e.downcast_ref::<HttpError>().and_then(HttpError::code);
+                    if code == Some(CANNOT_MESSAGE_USER) {
+                        log::info!("user {user} doesn't accept DMs from the bot");
+                    } else {
+                        log::warn!("failed to send user {user} their daily digest: {e:#}");
+                    }
+                }
+            }
+        }
+        checked_until = now;
+    }
+}
+
+/// Minutes since the Unix epoch, in UTC.
+fn minute(time: SystemTime) -> i64 {
+    storage::to_millis(time).div_euclid(60 * 1000)
+}
+
+/// Whether a digest falls due in the minutes after `from`, up to and including `to`.
+fn is_due(digest: &Digest, utc_offset: i32, from: i64, to: i64) -> bool {
+    let first = from + 1;
+    let local = first + i64::from(utc_offset);
+    let next = first + (i64::from(digest.at) - local).rem_euclid(MINUTES_PER_DAY);
+    next <= to
+}
+
+/// DMs `user` their open tasks, unless they have none.
+async fn send(state: &State, user: Id<UserMarker>, locale: &str) -> anyhow::Result<()> {
+    let data = state.storage.export_user(user).await?;
+    let mut lines = Vec::new();
+    // The global list sorts first, with no heading.
+    for (list, tasks) in &data.lists {
+        let open = tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| !task.archived)
+            .map(|(idx, task)| {
+                let pin = if task.pinned { "📌 " } else { "" };
+                format!("`{}.` {pin}{task}", idx + 1)
+            })
+            .collect::<Vec<_>>();
+        if open.is_empty() {
+            continue;
+        }
+        if let ListKey {
+            guild: Some(guild), ..
+        } = list
+        {
+            lines.push(String::new());
+            lines.push(message!(locale, "digest.guild", guild = guild));
+        }
+        lines.extend(open);
+    }
+    if lines.is_empty() {
+        log::debug!("not sending user {user} a daily digest, since they have no open tasks");
+        return Ok(());
+    }
+    let content = format!(
+        "{}\n{}\n\n{}",
+        message!(locale, "digest.header"),
+        lines.join("\n"),
+        message!(locale, "digest.footer"),
+    );
+    state.send_dm(user, &content, &[], &[]).await
+}
+
+/// Parses a time of day in 24-hour `HH:MM` format, as minutes after midnight.
+pub fn parse_time(time: &str) -> Option<u16> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes) = (digits(hours)?, digits(minutes)?);
+    (hours < 24 && minutes < 60).then(|| (hours * 60 + minutes) as u16)
+}
+
+pub fn format_time(at: u16) -> String {
+    format!("{:02}:{:02}", at / 60, at % 60)
+}
+
+/// Parses an offset from UTC like `+2`, `-05:30` or `UTC+1`, as minutes.
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let offset = offset.trim();
+    let offset = offset
+        .strip_prefix("UTC")
+        .or_else(|| offset.strip_prefix("utc"))
+        .unwrap_or(offset);
+    if offset.is_empty() || offset == "0" {
+        return Some(0);
+    }
+    let (sign, rest) = if let Some(rest) = offset.strip_prefix('+') {
+        (1, rest)
+    } else {
+        (-1, offset.strip_prefix('-')?)
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (digits(hours)?, digits(minutes)?),
+        None => (digits(rest)?, 0),
+    };
+    let offset = sign * (hours * 60 + minutes);
+    (minutes < 60 && offset.abs() <= MAX_UTC_OFFSET).then_some(offset)
+}
+
+pub fn format_utc_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{sign}{:02}:{:02}", offset / 60, offset % 60)
+}
+
+/// Parses one or two decimal digits, as in each part of a time.
+fn digits(digits: &str) -> Option<i32> {
+    let valid = (1..=2).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit());
+    valid.then(|| digits.parse().ok()).flatten()
+}
@@ -0,0 +1,331 @@
+use twilight_http::{client::InteractionClient, request::AttachmentFile, Client};
+use twilight_model::{
+    application::{
+        callback::{CallbackData, InteractionResponse},
+        command::{Command, CommandType},
+        component::Component,
+    },
+    channel::{
+        embed::Embed,
+        message::{AllowedMentions, MessageFlags},
+    },
+    guild::Guild,
+    id::{
+        marker::{
+            ApplicationMarker, ChannelMarker, CommandMarker, GuildMarker, InteractionMarker,
+            UserMarker,
+        },
+        Id,
+    },
+};
+
+use crate::http::{self, Retry};
+
+/// The requests the bot makes to Discord's HTTP API.
+///
+/// [`TwilightApi`] sends them to Discord; the tests use a fake which records them instead, so
+/// that handlers can be run without Discord. Each request is retried as [`http::send`] does, and
+/// a failed request is reported as an [`HttpError`](http::HttpError).
+#[async_trait::async_trait]
+pub trait DiscordApi: Send + Sync {
+    /// Sends the first response to an interaction.
+    async fn interaction_callback(
+        &self,
+        id: Id<InteractionMarker>,
+        token: &str,
+        response: &InteractionResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Sends a follow-up message to an interaction, which is ephemeral if `data`'s flags say so.
+    async fn create_followup(&self, token: &str, data: &CallbackData) -> anyhow::Result<()>;
+
+    /// Sends files in an ephemeral follow-up message to an interaction.
+    async fn create_followup_files(
+        &self,
+        token: &str,
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()>;
+
+    /// Replaces the original response to an interaction, such as a deferred acknowledgement.
+    async fn update_original(&self, token: &str, data: &CallbackData) -> anyhow::Result<()>;
+
+    /// Replaces only the components of the original response to an interaction.
+    async fn update_original_components(
+        &self,
+        token: &str,
+        components: &[Component],
+    ) -> anyhow::Result<()>;
+
+    /// The DM channel with a user, which is opened if it isn't already.
+    async fn create_private_channel(
+        &self,
+        user: Id<UserMarker>,
+    ) -> anyhow::Result<Id<ChannelMarker>>;
+
+    /// Sends a message to a channel, with no mentions allowed.
+    async fn create_message(
+        &self,
+        channel: Id<ChannelMarker>,
+        content: Option<&str>,
+        embeds: &[Embed],
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()>;
+
+    /// A server's details, such as its owner.
+    async fn guild(&self, guild: Id<GuildMarker>) -> anyhow::Result<Guild>;
+
+    /// The commands registered in `guild`, or globally if it's `None`.
+    async fn commands(&self, guild: Option<Id<GuildMarker>>) -> anyhow::Result<Vec<Command>>;
+
+    /// Registers a command in `guild`, or globally if it's `None`, replacing any existing
+    /// command with the same name.
+    async fn upsert_command(
+        &self,
+        guild: Option<Id<GuildMarker>>,
+        command: &Command,
+    ) -> anyhow::Result<()>;
+
+    /// Unregisters a command from `guild`, or globally if it's `None`.
+    async fn delete_command(
+        &self,
+        guild: Option<Id<GuildMarker>>,
+        id: Id<CommandMarker>,
+    ) -> anyhow::Result<()>;
+}
+
+/// Discord's API, reached through twilight's client.
+pub struct TwilightApi {
+    client: Client,
+    application: Id<ApplicationMarker>,
+}
+
+impl TwilightApi {
+    pub fn new(client: Client, application: Id<ApplicationMarker>) -> Self {
+        TwilightApi {
+            client,
+            application,
+        }
+    }
+
+    fn interaction_client(&self) -> InteractionClient<'_> {
+        self.client.interaction(self.application)
+    }
+}
+
+#[async_trait::async_trait]
+impl DiscordApi for TwilightApi {
+    async fn interaction_callback(
+        &self,
+        id: Id<InteractionMarker>,
+        token: &str,
+        response: &InteractionResponse,
+    ) -> anyhow::Result<()> {
+        http::send(Retry::RateLimited, || {
+            Ok(self
+                .interaction_client()
+                .interaction_callback(id, token, response)
+                .exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn create_followup(&self, token: &str, data: &CallbackData) -> anyhow::Result<()> {
+        let client = self.interaction_client();
+        let ephemeral = data
+            .flags
+            .is_some_and(|flags| flags.contains(MessageFlags::EPHEMERAL));
+        let allowed_mentions = data.allowed_mentions.clone().unwrap_or_default();
+        http::send(Retry::RateLimited, || {
+            let mut request = client
+                .create_followup_message(token)
+                .allowed_mentions(&allowed_mentions)
+                .ephemeral(ephemeral)
+                .content(data.content.as_deref().unwrap_or_default())?;
+            if let Some(embeds) = &data.embeds {
+                request = request.embeds(embeds)?;
+            }
+            if let Some(components) = &data.components {
+                request = request.components(components)?;
+            }
+            Ok(request.exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn create_followup_files(
+        &self,
+        token: &str,
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()> {
+        let client = self.interaction_client();
+        http::send(Retry::RateLimited, || {
+            Ok(client
+                .create_followup_message(token)
+                .ephemeral(true)
+                .attach(files)
+                .exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn update_original(&self, token: &str, data: &CallbackData) -> anyhow::Result<()> {
+        let components = data.components.as_deref();
+        let embeds = data.embeds.as_deref();
+        http::send(Retry::Idempotent, || {
+            Ok(self
+                .interaction_client()
+                .update_interaction_original(token)
+                .allowed_mentions(data.allowed_mentions.clone().unwrap_or_default())
+                .content(data.content.as_deref())?
+                .embeds(embeds)?
+                .components(components)?
+                .exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn update_original_components(
+        &self,
+        token: &str,
+        components: &[Component],
+    ) -> anyhow::Result<()> {
+        http::send(Retry::Idempotent, || {
+            Ok(self
+                .interaction_client()
+                .update_interaction_original(token)
+                .components(Some(components))?
+                .exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn create_private_channel(
+        &self,
+        user: Id<UserMarker>,
+    ) -> anyhow::Result<Id<ChannelMarker>> {
+        let channel = http::send(Retry::Idempotent, || {
+            Ok(self.client.create_private_channel(user).exec())
+        })
+        .await?
+        .model()
+        .await?;
+        Ok(channel.id)
+    }
+
+    async fn create_message(
+        &self,
+        channel: Id<ChannelMarker>,
+        content: Option<&str>,
+        embeds: &[Embed],
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()> {
+        http::send(Retry::RateLimited, || {
+            let mut request = self
+                .client
+                .create_message(channel)
+                .allowed_mentions(AllowedMentions::default())
+                .embeds(embeds)?
+                .attach(files);
+            if let Some(content) = content {
+                request = request.content(content)?;
+            }
+            Ok(request.exec())
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn guild(&self, guild: Id<GuildMarker>) -> anyhow::Result<Guild> {
+        let guild = http::send(Retry::Idempotent, || Ok(self.client.guild(guild).exec()))
+            .await?
+            .model()
+            .await?;
+        Ok(guild)
+    }
+
+    async fn commands(&self, guild: Option<Id<GuildMarker>>) -> anyhow::Result<Vec<Command>> {
+        let client = self.interaction_client();
+        let commands = http::send(Retry::Idempotent, || {
+            Ok(match guild {
+                Some(guild) => client.get_guild_commands(guild).exec(),
+                None => client.get_global_commands().exec(),
+            })
+        })
+        .await?
+        .models()
+        .await?;
+        Ok(commands)
+    }
+
+    async fn upsert_command(
+        &self,
+        guild: Option<Id<GuildMarker>>,
+        command: &Command,
+    ) -> anyhow::Result<()> {
+        let client = self.interaction_client();
+        let default_permission = command.default_permission.unwrap_or(true);
+        http::send(Retry::Idempotent, || {
+            Ok(match guild {
+                Some(guild) => {
+                    let request = client.create_guild_command(guild);
+                    match command.kind {
+                        CommandType::ChatInput => request
+                            .chat_input(&command.name, &command.description)?
+                            .command_options(&command.options)?
+                            .default_permission(default_permission)
+                            .exec(),
+                        CommandType::Message => request
+                            .message(&command.name)?
+                            .default_permission(default_permission)
+                            .exec(),
+                        CommandType::User => request
+                            .user(&command.name)?
+                            .default_permission(default_permission)
+                            .exec(),
+                    }
+                }
+                None => {
+                    let request = client.create_global_command();
+                    match command.kind {
+                        CommandType::ChatInput => request
+                            .chat_input(&command.name, &command.description)?
+                            .command_options(&command.options)?
+                            .default_permission(default_permission)
+                            .exec(),
+                        CommandType::Message => request
+                            .message(&command.name)?
+                            .default_permission(default_permission)
+                            .exec(),
+                        CommandType::User => request
+                            .user(&command.name)?
+                            .default_permission(default_permission)
+                            .exec(),
+                    }
+                }
+            })
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_command(
+        &self,
+        guild: Option<Id<GuildMarker>>,
+        id: Id<CommandMarker>,
+    ) -> anyhow::Result<()> {
+        let client = self.interaction_client();
+        http::send(Retry::Idempotent, || {
+            Ok(match guild {
+                Some(guild) => client.delete_guild_command(guild, id).exec(),
+                None => client.delete_global_command(id).exec(),
+            })
+        })
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use ed25519_dalek::{Signature, VerifyingKey};
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::{mpsc, oneshot};
+use twilight_model::application::{callback::InteractionResponse, interaction::Interaction};
+
+/// The largest request body accepted; interactions are far smaller.
+const MAX_BODY: usize = 1 << 20;
+
+/// An interaction received by the endpoint, with where its response goes.
+pub type Received = (Interaction, Reply);
+
+/// Where the first response to an interaction received by the endpoint goes, to be sent as the
+/// body of the HTTP response to Discord's request.
+pub struct Reply(oneshot::Sender<(InteractionResponse, oneshot::Sender<()>)>);
+
+impl Reply {
+    /// Hands over the response, waiting until it's been passed to the connection, so that
+    /// follow-up messages and edits aren't sent before Discord has it.
+    pub async fn send(self, response: InteractionResponse) -> anyhow::Result<()> {
+        let (sent, written) = oneshot::channel();
+        self.0
+            .send((response, sent))
+            .map_err(|_| anyhow::anyhow!("Discord's request for the interaction has gone away"))?;
+        written
+            .await
+            .context("failed to send the response to the interaction")
+    }
+}
+
+/// Parses the application's public key, which Discord gives in hex.
+pub fn public_key(key: &str) -> anyhow::Result<VerifyingKey> {
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(key, &mut bytes).context("the application's public key isn't valid")?;
+    VerifyingKey::from_bytes(&bytes).context("the application's public key isn't valid")
+}
+
+/// Binds the interactions endpoint to `addr`, failing straight away if the address can't be
+/// used.
+///
+/// Requests which aren't signed with the application's key are rejected with 401, as Discord
+/// requires. Pings are answered straight away; every other interaction is passed on through
+/// `interactions`, and answered with the response its handler gives. Once `shutdown` resolves,
+/// no more connections are accepted, and the server stops once the requests in flight have been
+/// answered.
+pub fn serve(
+    key: VerifyingKey,
+    addr: SocketAddr,
+    interactions: mpsc::UnboundedSender<Received>,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> anyhow::Result<impl Future<Output = ()>> {
+    let key = Arc::new(key);
+    let make_service = make_service_fn(move |_| {
+        let key = Arc::clone(&key);
+        let interactions = interactions.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let key = Arc::clone(&key);
+                let interactions = interactions.clone();
+                async move { Ok::<_, Infallible>(respond(&key, &interactions, request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?
+        .serve(make_service)
+        .with_graceful_shutdown(shutdown);
+    log::info!("serving the interactions endpoint on {addr}");
+    Ok(async move {
+        if let Err(e) = server.await {
+            log::error!("interactions endpoint server failed: {e}");
+        }
+    })
+}
+
+async fn respond(
+    key: &VerifyingKey,
+    interactions: &mpsc::UnboundedSender<Received>,
+    request: Request<Body>,
+) -> Response<Body> {
+    if request.method() != Method::POST {
+        return status(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let (parts, body) = request.into_parts();
+    let body = match read_body(body).await {
+        Ok(body) => body,
+        Err(status_code) => return status(status_code),
+    };
+    if !verify(key, &parts.headers, &body) {
+        return status(StatusCode::UNAUTHORIZED);
+    }
+    let interaction = match serde_json::from_slice::<Interaction>(&body) {
+        Ok(interaction) => interaction,
+        Err(e) => {
+            log::warn!("failed to parse an interaction received over HTTP: {e}");
+            return status(StatusCode::BAD_REQUEST);
+        }
+    };
+    if let Interaction::Ping(_) = interaction {
+        return json(&InteractionResponse::Pong, None);
+    }
+    let (reply, response) = oneshot::channel();
+    if interactions.send((interaction, Reply(reply))).is_err() {
+        // The bot is shutting down.
+        return status(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    match response.await {
+        Ok((response, sent)) => json(&response, Some(sent)),
+        Err(_) => {
+            log::debug!("interaction received over HTTP was handled without a response");
+            status(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Reads a request body, failing if it's larger than [`MAX_BODY`] or doesn't arrive.
+async fn read_body(mut body: Body) -> Result<Vec<u8>, StatusCode> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if bytes.len() + chunk.len() > MAX_BODY {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Whether a request was signed with the application's key, over its timestamp followed by its
+/// body.
+fn verify(key: &VerifyingKey, headers: &HeaderMap, body: &[u8]) -> bool {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (
+        header("X-Signature-Ed25519"),
+        header("X-Signature-Timestamp"),
+    ) else {
+        return false;
+    };
+    let mut signature_bytes = [0; 64];
+    if hex::decode_to_slice(signature, &mut signature_bytes).is_err() {
+        return false;
+    }
+    let signature = Signature::from_bytes(&signature_bytes);
+    let mut message = Vec::with_capacity(timestamp.len() + body.len());
+    message.extend_from_slice(timestamp.as_bytes());
+    message.extend_from_slice(body);
+    key.verify_strict(&message, &signature).is_ok()
+}
+
+/// A JSON response carrying an interaction response, which signals `sent`, if given, once it's
+/// been passed to the connection.
+fn json(response: &InteractionResponse, sent: Option<oneshot::Sender<()>>) -> Response<Body> {
+    let bytes = match serde_json::to_vec(response) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            log::error!("failed to serialize the response to an interaction: {e}");
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let body = match sent {
+        Some(sent) => {
+            let (mut sender, body) = Body::channel();
+            tokio::spawn(async move {
+                let written = async {
+                    sender.send_data(bytes.into()).await?;
+                    // The body is only ready for more once the connection has taken the data.
+                    futures_util::future::poll_fn(|cx| sender.poll_ready(cx)).await
+                };
+                if written.await.is_ok() {
+                    let _ = sent.send(());
+                }
+            });
+            body
+        }
+        None => Body::from(bytes),
+    };
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
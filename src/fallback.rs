@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use twilight_model::{
+    application::callback::CallbackData,
+    channel::message::MessageFlags,
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::CallbackDataBuilder;
+
+use crate::http::{HttpError, CANNOT_MESSAGE_USER};
+use crate::messages::message;
+use crate::State;
+
+/// The servers whose owners have been told that the bot is missing permissions there, so that
+/// each is only told once.
+#[derive(Default)]
+pub struct PermissionNotices {
+    notified: Mutex<HashSet<Id<GuildMarker>>>,
+}
+
+impl PermissionNotices {
+    /// Records that the bot is missing permissions in `guild`, returning whether its owner
+    /// should be told: whether they haven't been already.
+    pub fn record(&self, guild: Id<GuildMarker>) -> bool {
+        self.notified.lock().unwrap().insert(guild)
+    }
+}
+
+/// Delivers a response which couldn't be shown in the channel because the bot is missing
+/// permissions there.
+///
+/// The response is sent to `user` by DM with a note about why, or if they don't accept DMs,
+/// they're told ephemerally what went wrong. The owner of the server is told about the missing
+/// permissions the first time this happens there.
+pub async fn missing_permissions(
+    state: &State,
+    token: &str,
+    user: Id<UserMarker>,
+    guild: Option<Id<GuildMarker>>,
+    locale: &str,
+    data: &CallbackData,
+) -> anyhow::Result<()> {
+    log::info!("missing permissions to respond in the channel, sending the response by DM");
+    if let Some(guild) = guild.filter(|&guild| state.permission_notices.record(guild)) {
+        if let Err(e) = notify_owner(state, guild).await {
+            log::warn!(
+                "failed to tell the owner of guild {guild} about missing permissions: {e:#}"
+            );
+        }
+    }
+    let note = message!(locale, "error.missing_permissions.dm");
+    let content = match &data.content {
+        Some(content) => format!("{content}\n\n{note}"),
+        None => note,
+    };
+    let embeds = data.embeds.as_deref().unwrap_or_default();
+    let e = match state.send_dm(user, &content, embeds, &[]).await {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    let code = e.downcast_ref::<HttpError>().and_then(HttpError::code);
+    if code == Some(CANNOT_MESSAGE_USER) {
+        log::info!("user {user} doesn't accept DMs from the bot");
+    } else {
+        log::warn!("failed to DM user {user}: {e:#}");
+    }
+    let explanation = CallbackDataBuilder::new()
+        .content(message!(locale, "error.missing_permissions"))
+        .flags(MessageFlags::EPHEMERAL)
+        .build();
+    state.discord.create_followup(token, &explanation).await
+}
+
+/// Tells the owner of `guild` that the bot is missing permissions in one of its channels, in the
+/// server's language.
+async fn notify_owner(state: &State, guild: Id<GuildMarker>) -> anyhow::Result<()> {
+    let guild = state.discord.guild(guild).await?;
+    let content = message!(
+        &guild.preferred_locale,
+        "error.missing_permissions.owner",
+        guild = guild.name,
+    );
+    state.send_dm(guild.owner_id, &content, &[], &[]).await
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use twilight_gateway::{shard::Events, Event, EventTypeFlags, Intents, Shard};
+
+/// The events the bot listens for: interactions, and changes to the state of the connection so
+/// that outages show up in the logs.
+const EVENT_TYPES: EventTypeFlags = EventTypeFlags::INTERACTION_CREATE
+    .union(EventTypeFlags::READY)
+    .union(EventTypeFlags::RESUMED)
+    .union(EventTypeFlags::GATEWAY_RECONNECT)
+    .union(EventTypeFlags::GATEWAY_INVALIDATE_SESSION)
+    .union(EventTypeFlags::SHARD_CONNECTED)
+    .union(EventTypeFlags::SHARD_DISCONNECTED)
+    .union(EventTypeFlags::SHARD_RECONNECTING)
+    .union(EventTypeFlags::SHARD_RESUMING);
+
+/// How long to wait before reconnecting the first time the gateway connection is lost. Each
+/// failed attempt after that waits twice as long as the last, up to [`MAX_BACKOFF`].
+pub const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest to wait between attempts to reconnect.
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Close codes with which Discord ends a connection which will never succeed as configured, such
+/// as for an invalid token (4004) or intents (4013 and 4014).
+const FATAL_CLOSE_CODES: &[u16] = &[4004, 4010, 4011, 4012, 4013, 4014];
+
+/// Connects a new shard to the gateway.
+pub async fn connect(token: String) -> anyhow::Result<(Shard, Events)> {
+    let (shard, events) = Shard::builder(token, Intents::empty())
+        .event_types(EVENT_TYPES)
+        .build();
+    shard.start().await?;
+    Ok((shard, events))
+}
+
+/// The wait before the next attempt to reconnect, after waiting `backoff` before the last one.
+pub fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(MAX_BACKOFF)
+}
+
+/// Whether a connection closed with `code` can't be fixed by reconnecting.
+pub fn is_fatal(code: u16) -> bool {
+    FATAL_CLOSE_CODES.contains(&code)
+}
+
+/// Logs a change to the state of the connection.
+pub fn log_connection_event(event: &Event) {
+    match event {
+        Event::Ready(ready) => log::info!(
+            "connected to the gateway as {} (session {})",
+            ready.user.name,
+            ready.session_id,
+        ),
+        Event::Resumed => log::info!("resumed the gateway session"),
+        Event::GatewayReconnect => log::info!("gateway asked the bot to reconnect"),
+        Event::GatewayInvalidateSession(resumable) => {
+            log::info!("gateway invalidated the session (resumable: {resumable})")
+        }
+        Event::ShardConnected(_) => log::info!("connected to the gateway"),
+        Event::ShardDisconnected(disconnected) => log::info!(
+            "disconnected from the gateway (code: {:?}, reason: {:?})",
+            disconnected.code,
+            disconnected.reason,
+        ),
+        Event::ShardReconnecting(_) => log::info!("reconnecting to the gateway"),
+        Event::ShardResuming(resuming) => {
+            log::info!("resuming the gateway session at sequence {}", resuming.seq)
+        }
+        _ => {}
+    }
+}
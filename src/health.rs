@@ -0,0 +1,140 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::Serialize;
+
+use crate::State;
+
+/// How often the storage backend is pinged.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long the storage backend can go without answering a ping before the bot isn't ready.
+const PING_MAX_AGE: Duration = Duration::from_secs(30);
+
+/// What the bot knows about the health of the things it depends on.
+#[derive(Default)]
+pub struct Health {
+    /// Whether the shard is connected and has received `Ready`.
+    gateway_ready: AtomicBool,
+    /// When the storage backend last answered a ping.
+    storage_answered_at: Mutex<Option<Instant>>,
+}
+
+/// The body of a `/readyz` response.
+#[derive(Serialize)]
+struct Readiness {
+    gateway: &'static str,
+    storage: &'static str,
+}
+
+impl Health {
+    pub fn set_gateway_ready(&self, ready: bool) {
+        self.gateway_ready.store(ready, Ordering::Relaxed);
+    }
+
+    fn readiness(&self) -> (bool, Readiness) {
+        let gateway = self.gateway_ready.load(Ordering::Relaxed);
+        let storage = self
+            .storage_answered_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < PING_MAX_AGE);
+        let readiness = Readiness {
+            gateway: if gateway { "ok" } else { "not connected" },
+            storage: if storage { "ok" } else { "not answering" },
+        };
+        (gateway && storage, readiness)
+    }
+}
+
+/// Binds the health endpoints to `addr`, failing straight away if the address can't be used.
+///
+/// `/healthz` answers 200 whenever the process is up. `/readyz` answers 200 only while the
+/// gateway is connected and the storage backend has answered a recent ping, and 503 otherwise,
+/// with a JSON body saying which of them is unhealthy. `/metrics` answers with the bot's
+/// [`Metrics`](crate::metrics::Metrics) in Prometheus's text format.
+pub fn serve(
+    state: Arc<State>,
+    addr: SocketAddr,
+) -> anyhow::Result<impl std::future::Future<Output = ()>> {
+    let make_service = make_service_fn(move |_| {
+        let state = Arc::clone(&state);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let state = Arc::clone(&state);
+                async move { Ok::<_, Infallible>(respond(&state, request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    log::info!("serving health endpoints on {addr}");
+    Ok(async move {
+        if let Err(e) = server.await {
+            log::error!("health endpoint server failed: {e}");
+        }
+    })
+}
+
+async fn respond(state: &State, request: Request<Body>) -> Response<Body> {
+    let (status, body) = match request.uri().path() {
+        "/metrics" => return metrics(state).await,
+        "/healthz" => (StatusCode::OK, "ok".into()),
+        "/readyz" => {
+            let (ready, readiness) = state.health.readiness();
+            let status = if ready {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            let body = serde_json::to_string(&readiness).unwrap_or_default();
+            (status, body)
+        }
+        _ => (StatusCode::NOT_FOUND, "not found".into()),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
+
+/// The response to a scrape of `/metrics`, first bringing the count of active users up to date.
+async fn metrics(state: &State) -> Response<Body> {
+    match state.storage.stats().await {
+        Ok(stats) => state.metrics.set_active_users(stats.users),
+        Err(e) => log::warn!("failed to count active users for metrics: {e}"),
+    }
+    match state.metrics.render() {
+        Ok(rendered) => {
+            let mut response = Response::new(Body::from(rendered));
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(prometheus::TEXT_FORMAT),
+            );
+            response
+        }
+        Err(e) => {
+            log::error!("failed to render metrics: {e}");
+            let mut response = Response::new(Body::from("failed to render metrics"));
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            response
+        }
+    }
+}
+
+/// Pings the storage backend every [`PING_INTERVAL`], recording when it last answered.
+pub async fn ping_storage(state: Arc<State>) {
+    let mut interval = tokio::time::interval(PING_INTERVAL);
+    loop {
+        interval.tick().await;
+        match tokio::time::timeout(PING_INTERVAL, state.storage.ping()).await {
+            Ok(Ok(())) => *state.health.storage_answered_at.lock().unwrap() = Some(Instant::now()),
+            Ok(Err(e)) => log::warn!("storage ping failed: {e}"),
+            Err(_) => log::warn!("storage ping timed out"),
+        }
+    }
+}
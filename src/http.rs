@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use twilight_http::{api_error::ApiError, error::ErrorType, response::ResponseFuture, Response};
+
+/// How many times a request is tried before giving up on it.
+const MAX_ATTEMPTS: u32 = 4;
+
+/// How long to wait before the first retry of a request which failed without being rate limited.
+/// Each retry after that waits twice as long as the last.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Discord's error code for a user who doesn't accept direct messages from the bot.
+pub const CANNOT_MESSAGE_USER: u64 = 50007;
+
+/// Discord's error code for responding to an interaction which has already been responded to.
+pub const ALREADY_ACKNOWLEDGED: u64 = 40060;
+
+/// Discord's error code for a request about something the bot can't see, such as a channel it
+/// has no access to.
+const MISSING_ACCESS: u64 = 50001;
+
+/// Discord's error code for a request the bot lacks the permissions for, such as sending embeds
+/// in a channel where it can't.
+const MISSING_PERMISSIONS: u64 = 50013;
+
+/// A failed request to Discord.
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    /// Discord responded with an error.
+    #[error("error: {error}\nstatus: {status}\nbody: {body:#}")]
+    Response {
+        error: ApiError,
+        status: u16,
+        body: serde_json::Value,
+    },
+    /// The request failed some other way, such as by not reaching Discord at all.
+    #[error(transparent)]
+    Request(twilight_http::Error),
+}
+
+impl HttpError {
+    /// Discord's JSON error code for the failure, such as 50013 for missing permissions, if it
+    /// gave one.
+    pub fn code(&self) -> Option<u64> {
+        match self {
+            HttpError::Response {
+                error: ApiError::General(error),
+                ..
+            } => Some(error.code),
+            _ => None,
+        }
+    }
+
+    /// Whether the request failed because the bot lacks access to or permissions in the
+    /// channel, which only a server administrator can fix.
+    pub fn is_missing_permissions(&self) -> bool {
+        matches!(self.code(), Some(MISSING_ACCESS | MISSING_PERMISSIONS))
+    }
+}
+
+impl From<twilight_http::Error> for HttpError {
+    fn from(e: twilight_http::Error) -> Self {
+        if let ErrorType::Response {
+            body,
+            error,
+            status,
+        } = e.kind()
+        {
+            let body = if let Ok(body) = serde_json::from_slice::<serde_json::Value>(body) {
+                body
+            } else {
+                serde_json::Value::String(String::from_utf8_lossy(body).into())
+            };
+            HttpError::Response {
+                error: error.clone(),
+                status: status.raw(),
+                body,
+            }
+        } else {
+            HttpError::Request(e)
+        }
+    }
+}
+
+/// When a failed request may be sent again.
+#[derive(Clone, Copy, Debug)]
+pub enum Retry {
+    /// Only when Discord rate limited it, and so didn't act on it.
+    ///
+    /// For requests which would take effect twice if sent twice, such as sending a message.
+    RateLimited,
+    /// Also after server errors and timeouts.
+    ///
+    /// For requests with the same effect however many times they're sent, such as edits.
+    Idempotent,
+}
+
+/// Sends a request, retrying it according to `retry` if it fails transiently.
+///
+/// Rate limited requests are retried after the wait Discord asks for, and others with
+/// exponential backoff. `request` builds a fresh request for each attempt, since sending one
+/// consumes it. Failed requests are reported as an [`HttpError`].
+pub async fn send<T: Unpin>(
+    retry: Retry,
+    mut request: impl FnMut() -> anyhow::Result<ResponseFuture<T>>,
+) -> anyhow::Result<Response<T>> {
+    let mut attempt = 1;
+    loop {
+        let e = match request()?.await {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        match retry_delay(retry, &e, attempt) {
+            Some(wait) if attempt < MAX_ATTEMPTS => {
+                log::warn!(
+                    "request failed, retrying in {wait:?} (attempt {attempt}/{MAX_ATTEMPTS}): {e}"
+                );
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+            _ => return Err(HttpError::from(e).into()),
+        }
+    }
+}
+
+/// How long to wait before retrying a request which failed with `e` on the given attempt, or
+/// `None` if it shouldn't be retried.
+fn retry_delay(retry: Retry, e: &twilight_http::Error, attempt: u32) -> Option<Duration> {
+    let backoff = INITIAL_BACKOFF * 2u32.pow(attempt - 1);
+    match (e.kind(), retry) {
+        (
+            ErrorType::Response {
+                error: ApiError::Ratelimited(limited),
+                ..
+            },
+            _,
+        ) => Duration::try_from_secs_f64(limited.retry_after).ok(),
+        (ErrorType::Response { status, .. }, Retry::Idempotent) if status.is_server_error() => {
+            Some(backoff)
+        }
+        (ErrorType::ServiceUnavailable { .. } | ErrorType::RequestTimedOut, Retry::Idempotent) => {
+            Some(backoff)
+        }
+        _ => None,
+    }
+}
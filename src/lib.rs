@@ -0,0 +1,1439 @@
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use futures_util::{FutureExt, StreamExt};
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tracing::Instrument;
+use twilight_http::{request::AttachmentFile, Client};
+use twilight_model::{
+    application::{
+        callback::{CallbackData, InteractionResponse},
+        command::Command,
+        interaction::{ApplicationCommand, Interaction},
+    },
+    channel::{
+        embed::Embed,
+        message::{AllowedMentions, MessageFlags},
+    },
+    gateway::event::Event,
+    id::{
+        marker::{InteractionMarker, UserMarker},
+        Id,
+    },
+    oauth::current_application_info::CurrentApplicationInfo,
+};
+use twilight_util::builder::CallbackDataBuilder;
+
+use crate::backup::Backup;
+use crate::commands::{
+    AddToTodoCommand, AdminCommand, ArchiveCommand, ArchivedCommand, BackupCommand, CountCommand,
+    DebugCommand, DoneCommand, ForgetMeButton, ForgetMeCommand, HelpCommand, ListCommand,
+    MigrateListCommand, PinCommand, PrefsCommand, SyncCommand, TaskBulkCommand, TaskCommand,
+    TransferCommand, UnarchiveCommand, UndoCommand, UnpinCommand, UsageCommand, WhoamiCommand,
+};
+use crate::components::{Answer, ComponentRegistry};
+use crate::config::Config;
+use crate::cooldown::Cooldowns;
+use crate::discord::{DiscordApi, TwilightApi};
+use crate::fallback::PermissionNotices;
+use crate::health::Health;
+use crate::http::{HttpError, Retry, ALREADY_ACKNOWLEDGED};
+use crate::messages::message;
+use crate::metrics::Metrics;
+use crate::panics::PanicReports;
+use crate::parser::{parse_invoker, parse_user};
+use crate::registry::{CommandDiff, CommandRegistry, ResponsePolicy, SyncReport};
+use crate::report::{ErrorReporter, ReportTarget};
+use crate::seen::SeenInteractions;
+use crate::storage::Storage;
+use crate::undo::UndoHistory;
+use crate::usage::UsageRecorder;
+use crate::webhook::CompletionWebhook;
+
+mod backup;
+mod chunks;
+pub mod commands;
+mod components;
+mod config;
+mod cooldown;
+mod digest;
+mod discord;
+mod endpoint;
+mod fallback;
+mod gateway;
+mod health;
+mod http;
+mod messages;
+mod metrics;
+mod panics;
+pub mod parser;
+mod presence;
+mod registry;
+mod report;
+mod seen;
+mod storage;
+mod task;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+mod undo;
+mod usage;
+mod webhook;
+
+pub struct State {
+    discord: Box<dyn DiscordApi>,
+    application: CurrentApplicationInfo,
+    storage: Box<dyn Storage>,
+    registry: CommandRegistry,
+    components: ComponentRegistry,
+    config: Config,
+    /// Discord may deliver the same interaction more than once; only the first is handled.
+    seen: SeenInteractions,
+    /// Limits how many interactions are handled at once.
+    handlers: Semaphore,
+    webhook: Option<CompletionWebhook>,
+    /// Where operational errors are posted, if anywhere.
+    errors: Option<ErrorReporter>,
+    health: Health,
+    cooldowns: Cooldowns,
+    panics: PanicReports,
+    permission_notices: PermissionNotices,
+    usage: UsageRecorder,
+    metrics: Metrics,
+    /// Each user's recent changes, for `/undo`.
+    undo: UndoHistory,
+    /// Fetches files users attach to commands, such as `/task-bulk`'s.
+    downloads: reqwest::Client,
+}
+
+/// How the first response to an interaction reaches Discord.
+enum Delivery {
+    /// By calling the interaction callback endpoint, for interactions from the gateway.
+    Callback,
+    /// In the HTTP response to Discord's request, for interactions received by the interactions
+    /// endpoint. Any response after the first goes by callback, which Discord rejects as already
+    /// acknowledged, just like for interactions from the gateway.
+    Http(Mutex<Option<endpoint::Reply>>),
+}
+
+impl Delivery {
+    /// Takes the reply for the HTTP response, if it hasn't been sent yet.
+    fn take_reply(&self) -> Option<endpoint::Reply> {
+        match self {
+            Delivery::Callback => None,
+            Delivery::Http(reply) => reply.lock().unwrap().take(),
+        }
+    }
+}
+
+impl State {
+    async fn new(config: Config, registry: CommandRegistry) -> anyhow::Result<Arc<Self>> {
+        let mut client = Client::builder().token(config.token.clone());
+        if let Some(proxy) = &config.api_proxy {
+            log::warn!("sending Discord API requests to `{proxy}`");
+            client = client.proxy(proxy.clone(), true);
+        }
+        let client = client.build();
+        let application = init_application(&client).await?;
+        let discord = Box::new(TwilightApi::new(client, application.id));
+        let storage = storage::open(&config).await?;
+        State::from_parts(config, registry, application, discord, storage)
+    }
+
+    /// Sets up the rest of the state around an already connected API and storage.
+    fn from_parts(
+        config: Config,
+        registry: CommandRegistry,
+        application: CurrentApplicationInfo,
+        discord: Box<dyn DiscordApi>,
+        storage: Box<dyn Storage>,
+    ) -> anyhow::Result<Arc<Self>> {
+        let handlers = Semaphore::new(config.max_concurrent_interactions);
+        let cooldowns = Cooldowns::new(config.cooldown_commands, config.cooldown_period);
+        let webhook = config
+            .webhook_url
+            .clone()
+            .map(CompletionWebhook::new)
+            .transpose()?;
+        let errors = match (config.error_channel, &config.error_webhook_url) {
+            (Some(channel), _) => Some(ReportTarget::Channel(channel)),
+            (None, Some(url)) => Some(ReportTarget::Webhook(url.clone())),
+            (None, None) => None,
+        }
+        .map(ErrorReporter::new)
+        .transpose()?;
+        let downloads = reqwest::Client::builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .build()?;
+
+        Ok(Arc::new(State {
+            discord,
+            application,
+            storage,
+            registry,
+            components: components(),
+            config,
+            seen: SeenInteractions::default(),
+            handlers,
+            webhook,
+            errors,
+            health: Health::default(),
+            cooldowns,
+            panics: PanicReports::default(),
+            permission_notices: PermissionNotices::default(),
+            usage: UsageRecorder::default(),
+            metrics: Metrics::new()?,
+            undo: UndoHistory::default(),
+            downloads,
+        }))
+    }
+
+    /// Sends a direct message to a user.
+    async fn send_dm(
+        &self,
+        user: Id<UserMarker>,
+        content: &str,
+        embeds: &[Embed],
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()> {
+        async {
+            let channel = self.discord.create_private_channel(user).await?;
+            let pieces = chunks::split(content, chunks::MAX_CONTENT);
+            let last = pieces.len() - 1;
+            for (i, piece) in pieces.into_iter().enumerate() {
+                // The embeds and files go after all of the content.
+                let (embeds, files) = if i == last {
+                    (embeds, files)
+                } else {
+                    (&[][..], &[][..])
+                };
+                self.discord
+                    .create_message(channel, Some(piece), embeds, files)
+                    .await?;
+            }
+            Ok(())
+        }
+        .instrument(tracing::info_span!("send_dm", %user))
+        .await
+    }
+
+    /// Sends the response to an interaction.
+    ///
+    /// Responses echo text users have typed, such as tasks, so a response which doesn't say which
+    /// mentions it allows is sent with none allowed, rather than pinging everyone it mentions,
+    /// `@everyone` included.
+    ///
+    /// A response too long for one message is continued in follow-up messages. Components on the
+    /// response are disabled once they expire.
+    async fn respond(
+        &self,
+        delivery: &Delivery,
+        id: Id<InteractionMarker>,
+        token: &str,
+        mut response: InteractionResponse,
+    ) -> anyhow::Result<()> {
+        let mut followups = Vec::new();
+        if let InteractionResponse::ChannelMessageWithSource(data)
+        | InteractionResponse::DeferredChannelMessageWithSource(data)
+        | InteractionResponse::UpdateMessage(data) = &mut response
+        {
+            data.allowed_mentions
+                .get_or_insert_with(AllowedMentions::default);
+            followups = chunks::split_response(data);
+        }
+        async {
+            log::info!("responding with response: {response:?}");
+            self.components
+                .track(id, token, &response, self.config.component_ttl);
+            match delivery.take_reply() {
+                Some(reply) => reply.send(response).await?,
+                None => {
+                    self.discord
+                        .interaction_callback(id, token, &response)
+                        .await?
+                }
+            }
+            self.send_followups(token, &followups).await
+        }
+        .instrument(tracing::info_span!("respond", interaction = %id))
+        .await
+    }
+
+    /// Replaces the original response to an interaction, such as a deferred acknowledgement.
+    ///
+    /// A response too long for one message is continued in follow-up messages.
+    async fn edit_original(&self, token: &str, data: &CallbackData) -> anyhow::Result<()> {
+        let mut data = data.clone();
+        let followups = chunks::split_response(&mut data);
+        self.discord
+            .update_original(token, &data)
+            .instrument(tracing::info_span!("edit_original"))
+            .await?;
+        self.send_followups(token, &followups).await
+    }
+
+    /// Sends the rest of a response which was too long for one message.
+    async fn send_followups(&self, token: &str, followups: &[CallbackData]) -> anyhow::Result<()> {
+        for data in followups {
+            self.discord
+                .create_followup(token, data)
+                .instrument(tracing::info_span!("followup"))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Takes a backup to the configured directory, failing if none is configured.
+    async fn backup(&self) -> anyhow::Result<Backup> {
+        let dir = self
+            .config
+            .backup_dir
+            .as_deref()
+            .context("no `backup_dir` is configured")?;
+        backup::backup(&*self.storage, Path::new(dir), self.config.backup_retain).await
+    }
+
+    /// Reports an operational error, if reporting is configured.
+    fn report_error(&self, what: &str, error: &anyhow::Error, context: Option<String>) {
+        if let Some(errors) = &self.errors {
+            errors.report(what, error, context);
+        }
+    }
+
+    /// Registers commands, reporting any failure straight away, since it ends the process.
+    async fn init_commands(&self) -> anyhow::Result<()> {
+        let result = self.init_commands_inner().await;
+        if let (Err(e), Some(errors)) = (&result, &self.errors) {
+            errors.report("Registering commands failed", e, None);
+            errors.flush(self).await;
+        }
+        result
+    }
+
+    async fn init_commands_inner(&self) -> anyhow::Result<()> {
+        if let Some(guild) = self.config.dev_guild {
+            log::warn!("development mode: registering commands in guild {guild} only");
+            self.clear_global_commands().await?;
+        }
+        let report = if self.config.force_sync {
+            log::info!("forcing command registration");
+            self.resync_commands().await?
+        } else {
+            self.sync_commands().await?
+        };
+        if report.failed.is_empty() {
+            log::info!("synced commands:\n{report}");
+        } else {
+            log::error!("failed to sync some commands:\n{report}");
+            let e = anyhow::anyhow!("failed to sync some commands:\n{report}");
+            self.report_error("Syncing commands failed", &e, None);
+        }
+        Ok(())
+    }
+
+    /// Brings the registered commands in line with the defined ones, one command at a time.
+    ///
+    /// A failure to register one command doesn't prevent the others from being registered;
+    /// failures are instead collected in the returned report.
+    async fn sync_commands(&self) -> anyhow::Result<SyncReport> {
+        self.sync_commands_inner(false).await
+    }
+
+    /// Like [`sync_commands`](Self::sync_commands), but re-registers the unchanged commands too,
+    /// which bumps their versions and so makes clients drop cached copies.
+    async fn resync_commands(&self) -> anyhow::Result<SyncReport> {
+        self.sync_commands_inner(true).await
+    }
+
+    async fn sync_commands_inner(&self, force: bool) -> anyhow::Result<SyncReport> {
+        let commands = self.registry.commands();
+        let registered = self.registered_commands().await?;
+        let diff = CommandDiff::new(&commands, &registered, self.registry.prefix());
+        let mut report = SyncReport::default();
+        if force {
+            for command in diff.unchanged {
+                match self.upsert_command(command).await {
+                    Ok(()) => report.updated.push(command.name.clone()),
+                    Err(e) => report.failed.push((command.name.clone(), e)),
+                }
+            }
+        } else {
+            report.unchanged = diff.unchanged.iter().map(|c| c.name.clone()).collect();
+        }
+        for command in diff.created {
+            match self.upsert_command(command).await {
+                Ok(()) => report.created.push(command.name.clone()),
+                Err(e) => report.failed.push((command.name.clone(), e)),
+            }
+        }
+        for command in diff.updated {
+            match self.upsert_command(command).await {
+                Ok(()) => report.updated.push(command.name.clone()),
+                Err(e) => report.failed.push((command.name.clone(), e)),
+            }
+        }
+        for command in diff.deleted {
+            match self.delete_command(command).await {
+                Ok(()) => report.deleted.push(command.name.clone()),
+                Err(e) => report.failed.push((command.name.clone(), e)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Registers a single command, replacing any existing command with the same name.
+    async fn upsert_command(&self, command: &Command) -> anyhow::Result<()> {
+        self.discord
+            .upsert_command(self.config.dev_guild, command)
+            .await
+    }
+
+    /// Unregisters a single registered command.
+    async fn delete_command(&self, command: &Command) -> anyhow::Result<()> {
+        let id = command
+            .id
+            .ok_or_else(|| anyhow::anyhow!("registered command has no id"))?;
+        self.discord.delete_command(self.config.dev_guild, id).await
+    }
+
+    /// The commands currently registered, either globally or in the development guild.
+    async fn registered_commands(&self) -> anyhow::Result<Vec<Command>> {
+        self.discord.commands(self.config.dev_guild).await
+    }
+
+    /// Removes this instance's global commands, so they don't show up alongside the development
+    /// guild's.
+    ///
+    /// As with [`CommandDiff`], only commands with this instance's prefix are removed, so without
+    /// a prefix nothing is, and a warning says so.
+    async fn clear_global_commands(&self) -> anyhow::Result<()> {
+        let prefix = self.registry.prefix();
+        if prefix.is_empty() {
+            log::warn!(
+                "no command prefix is set, so any global commands are kept, and may show up \
+                 alongside the development guild's"
+            );
+            return Ok(());
+        }
+        let global = self.discord.commands(None).await?;
+        let ours = global
+            .iter()
+            .filter(|command| command.name.starts_with(prefix))
+            .collect::<Vec<_>>();
+        if !ours.is_empty() {
+            log::warn!("clearing {} global commands", ours.len());
+        }
+        for command in ours {
+            let id = command
+                .id
+                .ok_or_else(|| anyhow::anyhow!("registered command has no id"))?;
+            self.discord.delete_command(None, id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the bot until it's asked to stop.
+pub async fn run() -> anyhow::Result<()> {
+    let config = Config::load()?;
+
+    // Initialize the tracing subscriber.
+    tracing_subscriber::fmt()
+        .with_max_level(config.log_level)
+        .init();
+
+    let registry = commands(config.command_prefix.clone())?;
+    let state = State::new(config, registry).await?;
+    if state.config.register_only {
+        state.init_commands().await?;
+        log::info!("registered commands, exiting");
+        return Ok(());
+    }
+    let health = match state.config.health_addr {
+        Some(addr) => {
+            let server = health::serve(Arc::clone(&state), addr)?;
+            Some([
+                tokio::spawn(server),
+                tokio::spawn(health::ping_storage(Arc::clone(&state))),
+            ])
+        }
+        None => None,
+    };
+    state.init_commands().await?;
+
+    let errors = state
+        .errors
+        .is_some()
+        .then(|| tokio::spawn(report::run_periodic(Arc::clone(&state))));
+    let usage = tokio::spawn(usage::run_periodic(Arc::clone(&state)));
+    let digests = state
+        .config
+        .digests
+        .then(|| tokio::spawn(digest::run_periodic(Arc::clone(&state))));
+    let sweep = tokio::spawn(components::run_periodic(Arc::clone(&state)));
+    let backups = state.config.backup_dir.is_some().then(|| {
+        tokio::spawn(backup::run_periodic(
+            Arc::clone(&state),
+            state.config.backup_interval,
+        ))
+    });
+
+    let mut responders = JoinSet::new();
+    let fatal = match state.config.interactions_addr {
+        Some(addr) => {
+            run_endpoint(&state, addr, &mut responders).await?;
+            None
+        }
+        None => run_gateway(&state, &mut responders).await?,
+    };
+
+    log::info!(
+        "waiting up to {SHUTDOWN_GRACE_PERIOD:?} for {} in-flight interactions",
+        responders.len(),
+    );
+    let finished = tokio::select! {
+        finished = tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, async {
+            while responders.join_next().await.is_some() {}
+        }) => finished.is_ok(),
+        _ = shutdown_signal() => {
+            log::warn!("received a second shutdown signal, exiting immediately");
+            std::process::exit(1);
+        }
+    };
+    if !finished {
+        log::warn!(
+            "abandoning {} interactions still in flight",
+            responders.len()
+        );
+        responders.shutdown().await;
+    }
+
+    if let Some(backups) = backups {
+        backups.abort();
+    }
+    if let Some(digests) = digests {
+        digests.abort();
+    }
+    for task in health.into_iter().flatten() {
+        task.abort();
+    }
+    if let Some(errors) = errors {
+        errors.abort();
+    }
+    if let Some(errors) = &state.errors {
+        errors.flush(&state).await;
+    }
+    sweep.abort();
+    usage.abort();
+    state.usage.flush(&*state.storage).await;
+    log::info!("flushing storage");
+    state.storage.flush().await?;
+    log::info!("shutdown complete");
+    match fatal {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Receives interactions from the gateway until the process is asked to stop, returning why the
+/// connection was lost instead if reconnecting can't help.
+async fn run_gateway(
+    state: &Arc<State>,
+    responders: &mut JoinSet<()>,
+) -> anyhow::Result<Option<anyhow::Error>> {
+    let (mut shard, mut events) = gateway::connect(state.config.token.clone()).await?;
+
+    // The latest status worked out from the task totals, which is set again whenever the shard
+    // reconnects.
+    let (presence_sender, mut presence) = watch::channel(None);
+    let presence_task = state
+        .config
+        .presence
+        .then(|| tokio::spawn(presence::run_periodic(Arc::clone(state), presence_sender)));
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    // When to next try to reconnect to the gateway, while the connection is lost.
+    let mut reconnect_at = None;
+    let mut backoff = gateway::INITIAL_BACKOFF;
+    // The close code of the last disconnection, which says whether reconnecting could help.
+    let mut close_code = None;
+    let mut fatal = None;
+    loop {
+        tokio::select! {
+            event = events.next(), if reconnect_at.is_none() => match event {
+                Some(Event::InteractionCreate(interaction)) => {
+                    responders.spawn(interaction_responder(
+                        Arc::clone(state),
+                        interaction.0,
+                        Delivery::Callback,
+                    ));
+                }
+                Some(event) => {
+                    gateway::log_connection_event(&event);
+                    match event {
+                        Event::Ready(_) => {
+                            backoff = gateway::INITIAL_BACKOFF;
+                            state.health.set_gateway_ready(true);
+                            let update = presence.borrow().clone();
+                            presence::send(&shard, update).await;
+                        }
+                        Event::Resumed => state.health.set_gateway_ready(true),
+                        Event::ShardDisconnected(disconnected) => {
+                            close_code = disconnected.code;
+                            state.health.set_gateway_ready(false);
+                        }
+                        _ => {}
+                    }
+                }
+                None => {
+                    state.health.set_gateway_ready(false);
+                    match close_code.filter(|&code| gateway::is_fatal(code)) {
+                        Some(code) => {
+                            fatal = Some(anyhow::anyhow!(
+                                "the gateway closed the connection with code {code}, which can't \
+                                 be fixed by reconnecting"
+                            ));
+                            break;
+                        }
+                        None => {
+                            log::warn!("gateway event stream ended, reconnecting in {backoff:?}");
+                            reconnect_at = Some(Instant::now() + backoff);
+                            backoff = gateway::next_backoff(backoff);
+                        }
+                    }
+                }
+            },
+            _ = tokio::time::sleep_until(reconnect_at.unwrap_or_else(Instant::now)),
+                if reconnect_at.is_some() =>
+            {
+                match gateway::connect(state.config.token.clone()).await {
+                    Ok(connected) => {
+                        (shard, events) = connected;
+                        reconnect_at = None;
+                        close_code = None;
+                    }
+                    Err(e) => {
+                        log::warn!(
+                            "failed to reconnect to the gateway, retrying in {backoff:?}: {e:#}"
+                        );
+                        reconnect_at = Some(Instant::now() + backoff);
+                        backoff = gateway::next_backoff(backoff);
+                    }
+                }
+            }
+            Ok(()) = presence.changed(), if presence_task.is_some() => {
+                let update = presence.borrow_and_update().clone();
+                presence::send(&shard, update).await;
+            }
+            // Reap finished responders, so the set doesn't grow forever.
+            Some(_) = responders.join_next(), if !responders.is_empty() => {}
+            result = &mut shutdown => {
+                result?;
+                log::info!("received shutdown signal, closing the gateway connection");
+                break;
+            }
+        }
+    }
+    shard.shutdown();
+    if let Some(presence_task) = presence_task {
+        presence_task.abort();
+    }
+    Ok(fatal)
+}
+
+/// Receives interactions over HTTP on `addr` until the process is asked to stop.
+///
+/// There's no gateway connection, so the bot counts as connected for `/readyz` throughout.
+async fn run_endpoint(
+    state: &Arc<State>,
+    addr: SocketAddr,
+    responders: &mut JoinSet<()>,
+) -> anyhow::Result<()> {
+    let key = endpoint::public_key(&state.application.verify_key)?;
+    let (sender, mut interactions) = mpsc::unbounded_channel();
+    let (stop, stopped) = oneshot::channel();
+    let server = endpoint::serve(key, addr, sender, async {
+        let _ = stopped.await;
+    })?;
+    tokio::spawn(server);
+    state.health.set_gateway_ready(true);
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            Some((interaction, reply)) = interactions.recv() => {
+                let delivery = Delivery::Http(Mutex::new(Some(reply)));
+                responders.spawn(interaction_responder(Arc::clone(state), interaction, delivery));
+            }
+            // Reap finished responders, so the set doesn't grow forever.
+            Some(_) = responders.join_next(), if !responders.is_empty() => {}
+            result = &mut shutdown => {
+                result?;
+                log::info!("received shutdown signal, no longer accepting interactions");
+                break;
+            }
+        }
+    }
+    // Requests in flight are still answered, while any new ones are turned away.
+    let _ = stop.send(());
+    drop(interactions);
+    Ok(())
+}
+
+/// How long an interaction waits for a free handler before being turned away.
+///
+/// Discord only waits three seconds for the initial response to an interaction.
+const HANDLER_WAIT: Duration = Duration::from_secs(2);
+
+/// How long to wait for in-flight interactions to finish when shutting down.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How long to wait for a file attached to a command to download.
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves when the process is asked to stop, by Ctrl-C or `SIGTERM`.
+async fn shutdown_signal() -> anyhow::Result<()> {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        signal(SignalKind::terminate())?.recv().await;
+        Ok::<_, std::io::Error>(())
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<std::io::Result<()>>();
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        result = terminate => result?,
+    }
+    Ok(())
+}
+
+fn commands(prefix: String) -> anyhow::Result<CommandRegistry> {
+    let mut registry = CommandRegistry::load("commands.yaml", prefix)?;
+    registry
+        .register::<TaskCommand>()?
+        .register::<TaskBulkCommand>()?
+        .register::<AddToTodoCommand>()?
+        .register::<DoneCommand>()?
+        .register::<PinCommand>()?
+        .register::<UnpinCommand>()?
+        .register::<ArchiveCommand>()?
+        .register::<UnarchiveCommand>()?
+        .register::<ArchivedCommand>()?
+        .register::<ListCommand>()?
+        .register::<CountCommand>()?
+        .register::<TransferCommand>()?
+        .register::<PrefsCommand>()?
+        .register::<UndoCommand>()?
+        .register::<MigrateListCommand>()?
+        .register::<ForgetMeCommand>()?
+        .register::<WhoamiCommand>()?
+        .register::<SyncCommand>()?
+        .register::<AdminCommand>()?
+        .register::<BackupCommand>()?
+        .register::<DebugCommand>()?
+        .register::<UsageCommand>()?
+        .register::<HelpCommand>()?;
+    Ok(registry)
+}
+
+fn components() -> ComponentRegistry {
+    let mut registry = ComponentRegistry::default();
+    registry.register::<ForgetMeButton>();
+    registry
+}
+
+/// Fields of the current application which the bot relies on.
+const REQUIRED_APPLICATION_FIELDS: &[&str] = &["id", "owner"];
+
+#[derive(Debug, thiserror::Error)]
+enum ApplicationError {
+    #[error("failed to fetch the current application")]
+    Request(#[source] anyhow::Error),
+    #[error("failed to read the current application")]
+    Body(#[from] twilight_http::response::DeserializeBodyError),
+    #[error("the current application is missing the `{0}` field")]
+    MissingField(&'static str),
+    #[error("the current application is malformed")]
+    Malformed(#[from] serde_json::Error),
+}
+
+async fn init_application(client: &Client) -> Result<CurrentApplicationInfo, ApplicationError> {
+    let body = http::send(Retry::Idempotent, || {
+        Ok(client.current_user_application().exec())
+    })
+    .await
+    .map_err(ApplicationError::Request)?
+    .bytes()
+    .await?;
+    let application = serde_json::from_slice::<serde_json::Value>(&body)?;
+    for &field in REQUIRED_APPLICATION_FIELDS {
+        if application
+            .get(field)
+            .is_none_or(serde_json::Value::is_null)
+        {
+            return Err(ApplicationError::MissingField(field));
+        }
+    }
+
+    Ok(serde_json::from_value(application)?)
+}
+
+async fn interaction_responder(state: Arc<State>, interaction: Interaction, delivery: Delivery) {
+    let span = interaction_span(&interaction);
+    let start = Instant::now();
+    let id = interaction.id();
+    let reply_to = interaction_reply_to(&interaction)
+        .map(|(token, locale)| (String::from(token), String::from(locale)));
+    let summary = interaction_summary(&interaction);
+    let command = match &interaction {
+        Interaction::ApplicationCommand(command) => Some(command.data.name.clone()),
+        _ => None,
+    };
+    // A panicking handler would otherwise silently end the task, leaving the user with no
+    // response.
+    let result = AssertUnwindSafe(interaction_responder_inner(
+        Arc::clone(&state),
+        interaction,
+        &delivery,
+    ))
+    .catch_unwind()
+    .instrument(span.clone())
+    .await;
+    let elapsed = start.elapsed();
+    let outcome = match &result {
+        Ok(Ok(())) => "ok",
+        Ok(Err(e)) if e.downcast_ref::<HttpError>().is_some() => "http error",
+        Ok(Err(_)) => "handler error",
+        Err(_) => "panic",
+    };
+    span.record("outcome", outcome);
+    span.record("duration_ms", elapsed.as_millis() as u64);
+    if let Some(command) = command {
+        let command = command
+            .strip_prefix(state.registry.prefix())
+            .unwrap_or(&command);
+        state.usage.record(command, outcome == "ok");
+        state.metrics.record(command, outcome, elapsed);
+    }
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            let _entered = span.enter();
+            log::error!("Error responding to interaction {e}\n{e:?}");
+            state.report_error("Handling an interaction failed", &e, Some(summary));
+        }
+        Err(payload) => {
+            let message = panics::message(&*payload);
+            let e = anyhow::anyhow!("{message}");
+            state.report_error("A handler panicked", &e, Some(summary.clone()));
+            let reply_to = reply_to
+                .as_ref()
+                .map(|(token, locale)| (&**token, &**locale));
+            handle_panic(&state, &delivery, id, reply_to, &summary, message)
+                .instrument(span.clone())
+                .await;
+        }
+    }
+    let _entered = span.enter();
+    log::info!(
+        "handled interaction in {} ms: {outcome}",
+        elapsed.as_millis()
+    );
+}
+
+/// Apologizes to the user for a handler which panicked, and tells the owner of the bot about it
+/// if `panic_notify_owner` is set.
+async fn handle_panic(
+    state: &State,
+    delivery: &Delivery,
+    id: Id<InteractionMarker>,
+    reply_to: Option<(&str, &str)>,
+    summary: &str,
+    message: &str,
+) {
+    log::error!("handler panicked handling {summary}: {message}");
+    if let Some((token, locale)) = reply_to {
+        let cb = CallbackDataBuilder::new()
+            .content(message!(locale, "error.panicked"))
+            .flags(MessageFlags::EPHEMERAL)
+            .build();
+        let response = InteractionResponse::ChannelMessageWithSource(cb.clone());
+        let result = match state.respond(delivery, id, token, response).await {
+            // The handler panicked after acknowledging the interaction, so the acknowledgement
+            // is replaced instead.
+            Err(e)
+                if e.downcast_ref::<HttpError>().and_then(HttpError::code)
+                    == Some(ALREADY_ACKNOWLEDGED) =>
+            {
+                state.edit_original(token, &cb).await
+            }
+            result => result,
+        };
+        if let Err(e) = result {
+            log::warn!("failed to apologize for the panic: {e:#}");
+        }
+    }
+    if state.config.panic_notify_owner && state.panics.record(message) {
+        let content = format!("A handler panicked handling {summary}:\n```\n{message}\n```");
+        if let Err(e) = state
+            .send_dm(state.application.owner.id, &content, &[], &[])
+            .await
+        {
+            log::warn!("failed to DM the owner about the panic: {e:#}");
+        }
+    }
+}
+
+/// The token for responding to an interaction and the locale to respond in, if it can be
+/// responded to.
+fn interaction_reply_to(interaction: &Interaction) -> Option<(&str, &str)> {
+    match interaction {
+        Interaction::ApplicationCommand(command) => Some((&command.token, &command.locale)),
+        Interaction::MessageComponent(component) => Some((&component.token, &component.locale)),
+        _ => None,
+    }
+}
+
+/// A short description of an interaction for reporting errors, e.g. "`done` (interaction 123)
+/// from <@456>".
+fn interaction_summary(interaction: &Interaction) -> String {
+    let (command, user) = interaction_source(interaction);
+    let mut summary = match command {
+        Some(command) => format!("`{command}` (interaction {})", interaction.id()),
+        None => format!("interaction {}", interaction.id()),
+    };
+    if let Some(user) = user {
+        summary.push_str(&format!(" from <@{user}>"));
+    }
+    if let Some(guild) = interaction.guild_id() {
+        summary.push_str(&format!(" in guild {guild}"));
+    }
+    summary
+}
+
+/// The name of the command (or the custom id of the component) an interaction is for, and the
+/// user who used it.
+fn interaction_source(interaction: &Interaction) -> (Option<&str>, Option<Id<UserMarker>>) {
+    match interaction {
+        Interaction::ApplicationCommand(command) => {
+            (Some(&*command.data.name), parse_user(command).ok())
+        }
+        Interaction::ApplicationCommandAutocomplete(command) => {
+            let user = command
+                .member
+                .as_ref()
+                .and_then(|member| member.user.as_ref())
+                .or(command.user.as_ref())
+                .map(|user| user.id);
+            (Some(&*command.data.name), user)
+        }
+        Interaction::MessageComponent(component) => {
+            (Some(&*component.data.custom_id), component.author_id())
+        }
+        _ => (None, None),
+    }
+}
+
+/// A span for handling an interaction, identifying the interaction and who it came from.
+///
+/// The outcome and how long handling took are recorded once it's finished. A command which
+/// fails to parse is answered with the error, so it finishes as `ok`, but is marked by
+/// `parse_error`.
+fn interaction_span(interaction: &Interaction) -> tracing::Span {
+    let (command, user) = interaction_source(interaction);
+    tracing::info_span!(
+        "interaction",
+        id = %interaction.id(),
+        command,
+        user = user.map(tracing::field::display),
+        guild = interaction.guild_id().map(tracing::field::display),
+        outcome = tracing::field::Empty,
+        parse_error = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+    )
+}
+
+async fn interaction_responder_inner(
+    state: Arc<State>,
+    interaction: Interaction,
+    delivery: &Delivery,
+) -> anyhow::Result<()> {
+    match interaction {
+        Interaction::ApplicationCommand(command) => {
+            if !state.seen.insert(command.id) {
+                log::debug!("dropping duplicate delivery of interaction {}", command.id);
+                return Ok(());
+            }
+            let cost = state.registry.cost(&command.data.name);
+            if let Ok(user) = parse_user(&command) {
+                if let Err(wait) = state.cooldowns.take(user, cost) {
+                    log::info!("user {user} is over the cooldown, rejecting {}", command.id);
+                    let cb = CallbackDataBuilder::new()
+                        .content(message!(
+                            &command.locale,
+                            "error.cooldown",
+                            secs = wait.as_secs_f64().ceil(),
+                        ))
+                        .flags(MessageFlags::EPHEMERAL)
+                        .build();
+                    let response = InteractionResponse::ChannelMessageWithSource(cb);
+                    return state
+                        .respond(delivery, command.id, &command.token, response)
+                        .await;
+                }
+            }
+            log::trace!("command payload: {:#}", serde_json::to_value(&command)?);
+            let interaction_id = command.id;
+            let interaction_token = command.token.clone();
+            let locale = command.locale.clone();
+            // Held until the response has been sent.
+            let permit = match tokio::time::timeout(HANDLER_WAIT, state.handlers.acquire()).await {
+                Ok(permit) => Some(permit?),
+                Err(_) => None,
+            };
+            let (response, result) = match &permit {
+                Some(_) => match state.registry.response_policy(&command.data.name) {
+                    ResponsePolicy::Immediate => {
+                        match state.registry.dispatch(Arc::clone(&state), *command).await {
+                            Ok(response) => (response, Ok(())),
+                            // Left unanswered, the interaction would only show Discord's own
+                            // error, so the user is told it failed before the error is returned
+                            // to be logged, as in `respond_deferred`.
+                            Err(e) => {
+                                let cb = CallbackDataBuilder::new()
+                                    .content(message!(&locale, "error.failed"))
+                                    .flags(MessageFlags::EPHEMERAL)
+                                    .build();
+                                (InteractionResponse::ChannelMessageWithSource(cb), Err(e))
+                            }
+                        }
+                    }
+                    ResponsePolicy::Deferred { ephemeral } => {
+                        return respond_deferred(&state, delivery, *command, ephemeral).await;
+                    }
+                },
+                None => {
+                    log::warn!("too many interactions in flight, rejecting {interaction_id}");
+                    let cb = CallbackDataBuilder::new()
+                        .content(message!(&locale, "error.busy"))
+                        .flags(MessageFlags::EPHEMERAL)
+                        .build();
+                    (InteractionResponse::ChannelMessageWithSource(cb), Ok(()))
+                }
+            };
+            state
+                .respond(delivery, interaction_id, &interaction_token, response)
+                .await?;
+            result?;
+        }
+        Interaction::MessageComponent(component) => {
+            if !state.seen.insert(component.id) {
+                log::debug!(
+                    "dropping duplicate delivery of interaction {}",
+                    component.id
+                );
+                return Ok(());
+            }
+            let answer = state
+                .components
+                .dispatch(&state, &component, state.config.component_ttl)
+                .await?;
+            match answer {
+                Answer::Response(response) => {
+                    state
+                        .respond(delivery, component.id, &component.token, response)
+                        .await?;
+                }
+                Answer::Expired { update, notice } => {
+                    state
+                        .respond(delivery, component.id, &component.token, update)
+                        .await?;
+                    state.send_followups(&component.token, &[notice]).await?;
+                }
+            }
+        }
+        Interaction::ApplicationCommandAutocomplete(command) => {
+            log::trace!(
+                "command autocomplete payload: {:#}",
+                serde_json::to_value(command)?,
+            );
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Acknowledges a command straight away, and then replaces the acknowledgement with the
+/// response once the handler has finished.
+async fn respond_deferred(
+    state: &Arc<State>,
+    delivery: &Delivery,
+    command: ApplicationCommand,
+    ephemeral: bool,
+) -> anyhow::Result<()> {
+    let id = command.id;
+    let token = command.token.clone();
+    let locale = command.locale.clone();
+    let guild = command.guild_id;
+    let deferred = CallbackData {
+        allowed_mentions: None,
+        components: None,
+        content: None,
+        embeds: None,
+        flags: ephemeral.then_some(MessageFlags::EPHEMERAL),
+        tts: None,
+    };
+    let deferred = InteractionResponse::DeferredChannelMessageWithSource(deferred);
+    state.respond(delivery, id, &token, deferred).await?;
+    // Only needed to fall back to a DM, so that a malformed interaction is still acknowledged,
+    // and then answered by the handler failing to parse it.
+    let user = parse_invoker(&command).map(|user| user.id);
+    let (data, result) = match state.registry.dispatch(Arc::clone(state), command).await {
+        Ok(response) => (callback_data(response)?, Ok(())),
+        // Discord shows the acknowledgement until it's replaced, so errors have to be reported
+        // there too, before being returned to be logged.
+        Err(e) => {
+            let data = CallbackDataBuilder::new()
+                .content(message!(&locale, "error.failed"))
+                .build();
+            (data, Err(e))
+        }
+    };
+    match state.edit_original(&token, &data).await {
+        Err(e)
+            if e.downcast_ref::<HttpError>()
+                .is_some_and(HttpError::is_missing_permissions) =>
+        {
+            fallback::missing_permissions(state, &token, user?, guild, &locale, &data).await?;
+        }
+        edited => edited?,
+    }
+    result
+}
+
+/// The message in a response which has one.
+fn callback_data(response: InteractionResponse) -> anyhow::Result<CallbackData> {
+    match response {
+        InteractionResponse::ChannelMessageWithSource(data)
+        | InteractionResponse::DeferredChannelMessageWithSource(data)
+        | InteractionResponse::UpdateMessage(data) => Ok(data),
+        response => anyhow::bail!("response has no message: {response:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request as HttpRequest, Response, Server, StatusCode};
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::storage::{Backend, ListKey, Placement};
+    use crate::test_util::{
+        self, application_json, FakeDiscord, InteractionFixture, Request, APPLICATION_ID,
+    };
+
+    async fn run(state: &Arc<State>, command: InteractionFixture) {
+        let interaction = Interaction::ApplicationCommand(Box::new(command.build()));
+        interaction_responder_inner(Arc::clone(state), interaction, &Delivery::Callback)
+            .await
+            .unwrap();
+    }
+
+    /// Feeds the recorded interaction `tests/fixtures/interactions/{name}.json` to the bot, and
+    /// checks that its only request to Discord was the response recorded next to it, in
+    /// `{name}.response.json`.
+    async fn replay(name: &str) -> Arc<State> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/interactions");
+        let read = |file: String| {
+            std::fs::read_to_string(dir.join(&file))
+                .unwrap_or_else(|e| panic!("failed to read `{file}`: {e}"))
+        };
+        let interaction = test_util::parse_interaction(&read(format!("{name}.json")));
+        let expected: serde_json::Value =
+            serde_json::from_str(&read(format!("{name}.response.json"))).unwrap();
+
+        let discord = FakeDiscord::default();
+        let state = test_util::state(&discord);
+        interaction_responder_inner(Arc::clone(&state), interaction, &Delivery::Callback)
+            .await
+            .unwrap();
+        match &discord.take_requests()[..] {
+            [Request::Callback(response)] => {
+                assert_eq!(serde_json::to_value(response).unwrap(), expected)
+            }
+            other => panic!("expected a single response, got {other:?}"),
+        }
+        state
+    }
+
+    /// The content of the only message the bot sent in response.
+    fn response_content(requests: Vec<Request>) -> String {
+        match &requests[..] {
+            [Request::Callback(InteractionResponse::ChannelMessageWithSource(data))] => {
+                data.content.clone().unwrap()
+            }
+            other => panic!("expected a single response, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn adds_and_completes_a_task() {
+        let discord = FakeDiscord::default();
+        let state = test_util::state(&discord);
+        let list = ListKey::global(Id::new(1));
+
+        run(
+            &state,
+            InteractionFixture::new("task").string_option("task", "buy milk"),
+        )
+        .await;
+        assert_eq!(
+            response_content(discord.take_requests()),
+            "Added \"buy milk\" at index 1",
+        );
+        let tasks = state.storage.list_tasks(list).await.unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].text, "buy milk");
+
+        run(
+            &state,
+            InteractionFixture::new("done")
+                .id(701)
+                .int_option("task", 1),
+        )
+        .await;
+        assert_eq!(
+            response_content(discord.take_requests()),
+            "Completed \"buy milk\"",
+        );
+        assert!(state.storage.list_tasks(list).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn handles_a_redelivered_command_once() {
+        let discord = FakeDiscord::default();
+        let state = test_util::state(&discord);
+        let command = InteractionFixture::new("task").string_option("task", "buy milk");
+
+        run(&state, command.clone()).await;
+        run(&state, command).await;
+        assert_eq!(discord.take_requests().len(), 1);
+        let tasks = state
+            .storage
+            .list_tasks(ListKey::global(Id::new(1)))
+            .await
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn completing_a_missing_task_changes_nothing() {
+        let discord = FakeDiscord::default();
+        let state = test_util::state(&discord);
+        let list = ListKey::global(Id::new(1));
+        state
+            .storage
+            .add_task(
+                list,
+                &test_util::task("buy milk"),
+                Placement::Bottom,
+                false,
+                10,
+            )
+            .await
+            .unwrap();
+
+        run(
+            &state,
+            InteractionFixture::new("done").int_option("task", 2),
+        )
+        .await;
+        assert_eq!(discord.take_requests().len(), 1);
+        assert_eq!(state.storage.list_tasks(list).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn syncing_registers_the_commands_once() {
+        let discord = FakeDiscord::default();
+        let state = test_util::state(&discord);
+        let defined = state.registry.commands().len();
+
+        let report = state.sync_commands().await.unwrap();
+        assert_eq!(report.created.len(), defined);
+        assert_eq!(discord.commands(None).await.unwrap().len(), defined);
+
+        let report = state.sync_commands().await.unwrap();
+        assert!(report.created.is_empty());
+        assert!(report.updated.is_empty());
+        assert_eq!(report.unchanged.len(), defined);
+    }
+
+    /// A request the mock API received, with its body parsed as JSON.
+    #[derive(Debug)]
+    struct Received {
+        method: Method,
+        path: String,
+        body: Value,
+    }
+
+    /// Serves just enough of Discord's API over plain HTTP for the bot to start up and respond to
+    /// commands, keeping every request it receives.
+    fn mock_discord() -> (SocketAddr, Arc<Mutex<Vec<Received>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        let make_service = make_service_fn(move |_| {
+            let log = Arc::clone(&log);
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |request| {
+                    let log = Arc::clone(&log);
+                    async move { Ok::<_, hyper::Error>(reply(&log, request).await) }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        (addr, received)
+    }
+
+    /// Answers a request to the mock API, and records it.
+    async fn reply(log: &Mutex<Vec<Received>>, request: HttpRequest<Body>) -> Response<Body> {
+        let method = request.method().clone();
+        let path = request.uri().path().to_owned();
+        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+        let body = match body.is_empty() {
+            true => Value::Null,
+            false => serde_json::from_slice(&body).unwrap(),
+        };
+        let commands = format!("/api/v9/applications/{APPLICATION_ID}/commands");
+        let (status, response) = match (&method, path.as_str()) {
+            (&Method::GET, "/api/v9/oauth2/applications/@me") => {
+                (StatusCode::OK, Some(application_json()))
+            }
+            (&Method::GET, path) if path == commands => (StatusCode::OK, Some(json!([]))),
+            (&Method::POST, path) if path == commands => {
+                let mut command = body.clone();
+                command["id"] = "1000".into();
+                command["application_id"] = APPLICATION_ID.to_string().into();
+                command["version"] = "1".into();
+                (StatusCode::CREATED, Some(command))
+            }
+            (&Method::POST, path) if path.starts_with("/api/v9/interactions/") => {
+                (StatusCode::NO_CONTENT, None)
+            }
+            _ => (
+                StatusCode::NOT_FOUND,
+                Some(json!({ "code": 0, "message": "404" })),
+            ),
+        };
+        log.lock().unwrap().push(Received { method, path, body });
+        let body = response.map_or_else(Body::empty, |value| value.to_string().into());
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(body)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn registers_commands_and_responds_through_the_api_proxy() {
+        let (addr, received) = mock_discord();
+        let mut config = Config::for_tests();
+        config.api_proxy = Some(addr.to_string());
+        config.storage = Backend::Memory;
+        let registry = commands(String::new()).unwrap();
+        let state = State::new(config, registry).await.unwrap();
+        state.init_commands().await.unwrap();
+
+        let registered = received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == Method::POST)
+            .map(|request| request.body.clone())
+            .collect::<Vec<_>>();
+        let commands = state.registry.commands();
+        assert_eq!(registered.len(), commands.len());
+        let task = commands
+            .iter()
+            .find(|command| command.name == "task")
+            .unwrap();
+        let body = registered
+            .iter()
+            .find(|body| body["name"] == "task")
+            .unwrap();
+        assert_eq!(
+            body,
+            &json!({
+                "application_id": APPLICATION_ID.to_string(),
+                "name": "task",
+                "description": task.description,
+                "options": task.options,
+                "default_permission": true,
+                "type": 1,
+            }),
+        );
+
+        let count = InteractionFixture::new("count").to_json();
+        let count = serde_json::from_value(count).unwrap();
+        interaction_responder_inner(Arc::clone(&state), count, &Delivery::Callback)
+            .await
+            .unwrap();
+        let received = received.lock().unwrap();
+        let callback = received.last().unwrap();
+        assert_eq!(
+            callback.path,
+            "/api/v9/interactions/700/fixture-token/callback"
+        );
+        assert_eq!(
+            callback.body,
+            json!({
+                "type": 4,
+                "data": {
+                    "allowed_mentions": { "parse": [] },
+                    "content": "You have 0 open tasks",
+                    "flags": 64,
+                },
+            }),
+        );
+    }
+
+    #[tokio::test]
+    async fn replays_a_task_added_in_a_guild() {
+        let state = replay("task_in_guild").await;
+        let tasks = state
+            .storage
+            .list_tasks(ListKey::global(Id::new(5)))
+            .await
+            .unwrap();
+        assert_eq!(tasks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn replays_a_task_added_in_a_dm() {
+        replay("task_in_dm").await;
+    }
+
+    #[tokio::test]
+    async fn replays_an_unknown_command() {
+        replay("unknown_command").await;
+    }
+
+    #[tokio::test]
+    async fn replays_an_option_of_the_wrong_type() {
+        replay("done_wrong_type").await;
+    }
+}
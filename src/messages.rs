@@ -0,0 +1,338 @@
+use std::fmt::{Display, Write};
+
+/// Looks up a response in the invoking user's locale and fills in its placeholders, e.g.
+/// `message!(locale, "done.completed", task = task)`.
+macro_rules! message {
+    ($locale:expr, $key:literal $(, $name:ident = $value:expr)* $(,)?) => {
+        $crate::messages::get(
+            $locale,
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*],
+        )
+    };
+}
+
+pub(crate) use message;
+
+/// A catalog of the responses in one language, as templates by key.
+type Catalog = fn(&str) -> Option<&'static str>;
+
+/// Looks up the template for `key` in `locale`, falling back to English if the locale has no
+/// catalog or its catalog is missing the key, and fills in its `{name}` placeholders from
+/// `args`.
+pub fn get(locale: &str, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = catalog(locale)
+        .and_then(|catalog| catalog(key))
+        .or_else(|| en(key));
+    match template {
+        Some(template) => render(template, args),
+        None => {
+            log::error!("no message `{key}`");
+            key.into()
+        }
+    }
+}
+
+/// The catalog for a Discord locale, such as `de` or `en-US`, if there is one besides English.
+fn catalog(locale: &str) -> Option<Catalog> {
+    let language = locale.split('-').next().unwrap_or(locale);
+    match language {
+        "de" => Some(de),
+        _ => None,
+    }
+}
+
+/// Fills in the `{name}` placeholders in `template`.
+///
+/// Values are inserted as they are, so placeholders in them (e.g. in a task's text) are left
+/// alone. A placeholder with no value is kept as it is.
+pub fn render(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let (_, value) = args.iter().find(|(name, _)| *name == &rest[1..end])?;
+            Some((end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                let _ = write!(rendered, "{value}");
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+fn en(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "error.cooldown" => "Slow down! Try again in {secs}s",
+        "error.busy" => "The bot is busy right now, please try again in a moment",
+        "error.component_expired" => "This button has expired; use the command again",
+        "error.failed" => "Something went wrong handling that command",
+        "error.panicked" => "Something went wrong handling that command, and it's been reported",
+        "error.missing_permissions" => {
+            "I'm missing permissions to post in this channel, and couldn't DM you instead; ask a \
+             server administrator to let me send messages and embeds here"
+        }
+        "error.missing_permissions.dm" => {
+            "(I couldn't post this in the channel you used the command in, because I'm missing \
+             permissions there.)"
+        }
+        "error.missing_permissions.owner" => {
+            "I'm missing permissions to post in a channel in **{guild}**, so responses to commands \
+             used there are being sent to users by DM instead. Please let me send messages and \
+             embeds in the channels where commands are used."
+        }
+        "task.added" => "Added \"{task}\" at index {index}",
+        "task.duplicate" => "\"{task}\" already exists at index {index}",
+        "task.list_full" => {
+            "Your todo list already has the maximum of {limit} tasks; complete some with `/done` \
+             first"
+        }
+        "task.no_such_task" => "There is no task at index {index}",
+        "task.too_long" => "That task is longer than the maximum of {max} characters",
+        "add_to_todo.empty" => "That message has no text to add as a task",
+        "task_bulk.added" => "Added {count} tasks:",
+        "task_bulk.duplicate" => "`{index}.` {task} (already on the list)",
+        "task_bulk.empty" => "That file has no tasks in it; put each task on a line of its own",
+        "task_bulk.too_many" => {
+            "That file has {count} tasks, but at most {max} can be added at once"
+        }
+        "task_bulk.list_full" => {
+            "Adding {count} tasks would take your todo list past its maximum of {limit} tasks, so \
+             none were added"
+        }
+        "task_bulk.too_large" => "That file is too large; it can be at most {max} KiB",
+        "task_bulk.not_text" => "That file isn't UTF-8 text",
+        "task_bulk.too_long" => {
+            "Task {number} in that file is longer than the maximum of {max} characters, so none \
+             were added"
+        }
+        "done.completed" => "Completed \"{task}\"",
+        "pin.pinned" => "Pinned \"{task}\"",
+        "pin.unpinned" => "Unpinned \"{task}\"",
+        "archive.archived" => "Archived \"{task}\"; see it with `/archived`",
+        "archive.unarchived" => "Put \"{task}\" back on your list",
+        "archive.empty" => "You have no archived tasks",
+        "list.empty" => "Your todo list is empty",
+        "list.sent" => "Sent your todo list to your DMs",
+        "list.attached" => "Your todo list is too long for a message, so it's attached",
+        "list.more" => "…and {count} more",
+        "list.dm_failed" => "Couldn't DM you, so here it is:\n{list}",
+        "count.one" => "You have {open} open task",
+        "count.other" => "You have {open} open tasks",
+        "count.completed.one" => "You have {open} open task, and have completed {completed}",
+        "count.completed.other" => "You have {open} open tasks, and have completed {completed}",
+        "transfer.to_self" => "You can't transfer your list to yourself",
+        "transfer.done.one" => "Transferred {count} task to {user}",
+        "transfer.done.other" => "Transferred {count} tasks to {user}",
+        "prefs.current" => "Your preferences:\n- {delivery}\n- {lists}\n- {digest}",
+        "prefs.updated" => "Updated your preferences:\n- {delivery}\n- {lists}\n- {digest}",
+        "prefs.delivery.ephemeral" => "Your todo list is sent as a reply only you can see",
+        "prefs.delivery.dm" => "Your todo list is sent by DM",
+        "prefs.lists.global" => "You use the same todo list in every server",
+        "prefs.lists.guild" => "You keep a separate todo list in each server",
+        "prefs.digest.off" => "You don't get a daily digest of your open tasks",
+        "prefs.digest.at" => "You get a DM of your open tasks every day at {time} (UTC{offset})",
+        "digest.header" => "Your open tasks for today:",
+        "digest.guild" => "On your list in server `{guild}`:",
+        "digest.footer" => "Turn this off with `/prefs digest: off`",
+        "undo.nothing" => "There's nothing to undo",
+        "undo.added" => "Removed \"{task}\", which you'd just added",
+        "undo.completed" => "Put \"{task}\" back on your list at index {index}",
+        "undo.gone" => "\"{task}\" is no longer on your list, so that can't be undone",
+        "migrate.not_in_guild" => "Use this in the server you want to move your global list to",
+        "migrate.moved.one" => "Moved {count} task from your global list to your list here",
+        "migrate.moved.other" => "Moved {count} tasks from your global list to your list here",
+        "migrate.still_global" => {
+            "Your commands still use your global list; choose per-server lists with `/prefs` to \
+             use this one"
+        }
+        "forget_me.confirm" => {
+            "This permanently deletes all of your todo lists, completed tasks and preferences. Are \
+             you sure?"
+        }
+        "forget_me.button.confirm" => "Delete everything",
+        "forget_me.button.cancel" => "Cancel",
+        "forget_me.deleted" => "Deleted {tasks} tasks from your lists",
+        "forget_me.deleted_completed" => "Deleted {completed} completed tasks",
+        "forget_me.deleted_preferences" => "Deleted your preferences",
+        "forget_me.cancelled" => "Cancelled; nothing was deleted",
+        "whoami.user" => "User: `{tag}` (`{id}`, resolved from `{source}`)",
+        "whoami.created" => "Account created: <t:{created}:f>",
+        "whoami.guild" => "Guild: {guild}",
+        "whoami.channel" => "Channel: `{channel}`",
+        "whoami.locale" => "Locale: `{locale}`",
+        "whoami.permissions" => "Permissions: {permissions}",
+        "whoami.dm" => "none (direct message)",
+        "sync.not_owner" => "Only the owner of the bot can sync commands",
+        "admin.not_owner" => "Only the owner of the bot can use admin commands",
+        "backup.not_owner" => "Only the owner of the bot can take backups",
+        "backup.written" => "Wrote a backup of {size} bytes to `{path}`",
+        "backup.failed" => "Backup failed: {error}",
+        "debug.not_owner" => "Only the owner of the bot can inspect stored data",
+        "debug.empty" => "Nothing is stored about {user}",
+        "debug.summary" => "Everything stored about {user} is attached. Their lists:",
+        "debug.list" => "- {list}: {open} tasks",
+        "debug.list.completed" => "- {list}: {open} tasks, {completed} completed",
+        "usage.not_owner" => "Only the owner of the bot can see command usage",
+        "usage.empty" => "No commands have been used in that time",
+        "usage.day" => "Most used commands today (UTC):",
+        "usage.week" => "Most used commands in the last 7 days:",
+        "usage.all" => "Most used commands of all time:",
+        "usage.row" => {
+            "- `/{command}`: {invocations} uses, {errors} failed, last <t:{last_used}:R>"
+        }
+        "help.no_such_command" => "There is no `/{name}` command",
+        "help.no_options" => "This command has no options",
+        "help.options" => "Options:",
+        "help.required" => "required",
+        "help.optional" => "optional",
+        _ => return None,
+    })
+}
+
+/// German. Commands only the owner of the bot can use aren't translated.
+fn de(key: &str) -> Option<&'static str> {
+    Some(match key {
+        "error.cooldown" => "Nicht so schnell! Versuche es in {secs}s erneut",
+        "error.busy" => "Der Bot ist gerade beschäftigt, bitte versuche es gleich noch einmal",
+        "error.component_expired" => "Dieser Button ist abgelaufen; benutze den Befehl noch einmal",
+        "error.failed" => "Beim Ausführen dieses Befehls ist etwas schiefgelaufen",
+        "error.panicked" => {
+            "Beim Ausführen dieses Befehls ist etwas schiefgelaufen, und es wurde gemeldet"
+        }
+        "error.missing_permissions" => {
+            "Mir fehlen die Berechtigungen, in diesem Kanal zu schreiben, und ich konnte dir auch \
+             keine Direktnachricht schicken; bitte eine Server-Administration, mir hier \
+             Nachrichten und Einbettungen zu erlauben"
+        }
+        "error.missing_permissions.dm" => {
+            "(Ich konnte das nicht in dem Kanal posten, in dem du den Befehl benutzt hast, weil \
+             mir dort Berechtigungen fehlen.)"
+        }
+        "error.missing_permissions.owner" => {
+            "Mir fehlen die Berechtigungen, in einem Kanal auf **{guild}** zu schreiben, deshalb \
+             werden Antworten auf Befehle von dort per Direktnachricht geschickt. Bitte erlaube \
+             mir, in den Kanälen, in denen Befehle benutzt werden, Nachrichten und Einbettungen \
+             zu senden."
+        }
+        "task.added" => "„{task}“ an Position {index} hinzugefügt",
+        "task.duplicate" => "„{task}“ steht schon an Position {index}",
+        "task.list_full" => {
+            "Deine Todo-Liste hat schon die maximale Anzahl von {limit} Aufgaben; erledige zuerst \
+             welche mit `/done`"
+        }
+        "task.no_such_task" => "An Position {index} steht keine Aufgabe",
+        "task.too_long" => "Diese Aufgabe ist länger als das Maximum von {max} Zeichen",
+        "add_to_todo.empty" => "Diese Nachricht hat keinen Text, der eine Aufgabe werden könnte",
+        "task_bulk.added" => "{count} Aufgaben hinzugefügt:",
+        "task_bulk.duplicate" => "`{index}.` {task} (steht schon auf der Liste)",
+        "task_bulk.empty" => {
+            "Diese Datei enthält keine Aufgaben; schreib jede Aufgabe in eine eigene Zeile"
+        }
+        "task_bulk.too_many" => {
+            "Diese Datei enthält {count} Aufgaben, aber es können höchstens {max} auf einmal \
+             hinzugefügt werden"
+        }
+        "task_bulk.list_full" => {
+            "Mit {count} weiteren Aufgaben hätte deine Todo-Liste mehr als das Maximum von {limit} \
+             Aufgaben, deshalb wurde keine hinzugefügt"
+        }
+        "task_bulk.too_large" => "Diese Datei ist zu groß; sie darf höchstens {max} KiB groß sein",
+        "task_bulk.not_text" => "Diese Datei ist kein UTF-8-Text",
+        "task_bulk.too_long" => {
+            "Aufgabe {number} in dieser Datei ist länger als das Maximum von {max} Zeichen, deshalb \
+             wurde keine hinzugefügt"
+        }
+        "done.completed" => "„{task}“ erledigt",
+        "pin.pinned" => "„{task}“ angeheftet",
+        "pin.unpinned" => "„{task}“ losgelöst",
+        "archive.archived" => "„{task}“ archiviert; du findest es mit `/archived`",
+        "archive.unarchived" => "„{task}“ ist wieder auf deiner Liste",
+        "archive.empty" => "Du hast keine archivierten Aufgaben",
+        "list.empty" => "Deine Todo-Liste ist leer",
+        "list.sent" => "Deine Todo-Liste wurde dir per Direktnachricht geschickt",
+        "list.attached" => "Deine Todo-Liste ist zu lang für eine Nachricht, deshalb hängt sie an",
+        "list.more" => "…und {count} weitere",
+        "list.dm_failed" => {
+            "Ich konnte dir keine Direktnachricht schicken, deshalb ist sie hier:\n{list}"
+        }
+        "count.one" => "Du hast {open} offene Aufgabe",
+        "count.other" => "Du hast {open} offene Aufgaben",
+        "count.completed.one" => "Du hast {open} offene Aufgabe und {completed} erledigt",
+        "count.completed.other" => "Du hast {open} offene Aufgaben und {completed} erledigt",
+        "transfer.to_self" => "Du kannst deine Liste nicht an dich selbst übertragen",
+        "transfer.done.one" => "{count} Aufgabe an {user} übertragen",
+        "transfer.done.other" => "{count} Aufgaben an {user} übertragen",
+        "prefs.current" => "Deine Einstellungen:\n- {delivery}\n- {lists}\n- {digest}",
+        "prefs.updated" => {
+            "Deine Einstellungen wurden geändert:\n- {delivery}\n- {lists}\n- {digest}"
+        }
+        "prefs.delivery.ephemeral" => {
+            "Deine Todo-Liste wird als Antwort geschickt, die nur du sehen kannst"
+        }
+        "prefs.delivery.dm" => "Deine Todo-Liste wird per Direktnachricht geschickt",
+        "prefs.lists.global" => "Du benutzt auf jedem Server dieselbe Todo-Liste",
+        "prefs.lists.guild" => "Du hast auf jedem Server eine eigene Todo-Liste",
+        "prefs.digest.off" => "Du bekommst keine tägliche Übersicht deiner offenen Aufgaben",
+        "prefs.digest.at" => {
+            "Du bekommst jeden Tag um {time} (UTC{offset}) eine Direktnachricht mit deinen offenen \
+             Aufgaben"
+        }
+        "digest.header" => "Deine offenen Aufgaben für heute:",
+        "digest.guild" => "Auf deiner Liste auf dem Server `{guild}`:",
+        "digest.footer" => "Abschalten kannst du das mit `/prefs digest: off`",
+        "undo.nothing" => "Es gibt nichts rückgängig zu machen",
+        "undo.added" => "„{task}“ wieder entfernt",
+        "undo.completed" => "„{task}“ ist wieder an Position {index} auf deiner Liste",
+        "undo.gone" => {
+            "„{task}“ ist nicht mehr auf deiner Liste, deshalb kann das nicht rückgängig gemacht \
+             werden"
+        }
+        "migrate.not_in_guild" => {
+            "Benutze das auf dem Server, auf den du deine globale Liste verschieben willst"
+        }
+        "migrate.moved.one" => {
+            "{count} Aufgabe von deiner globalen Liste auf deine Liste hier verschoben"
+        }
+        "migrate.moved.other" => {
+            "{count} Aufgaben von deiner globalen Liste auf deine Liste hier verschoben"
+        }
+        "migrate.still_global" => {
+            "Deine Befehle benutzen noch deine globale Liste; wähle mit `/prefs` eigene Listen \
+             pro Server, um diese zu benutzen"
+        }
+        "forget_me.confirm" => {
+            "Das löscht dauerhaft alle deine Todo-Listen, erledigten Aufgaben und Einstellungen. \
+             Bist du sicher?"
+        }
+        "forget_me.button.confirm" => "Alles löschen",
+        "forget_me.button.cancel" => "Abbrechen",
+        "forget_me.deleted" => "{tasks} Aufgaben von deinen Listen gelöscht",
+        "forget_me.deleted_completed" => "{completed} erledigte Aufgaben gelöscht",
+        "forget_me.deleted_preferences" => "Deine Einstellungen gelöscht",
+        "forget_me.cancelled" => "Abgebrochen; es wurde nichts gelöscht",
+        "whoami.user" => "Benutzer: `{tag}` (`{id}`, ermittelt aus `{source}`)",
+        "whoami.created" => "Konto erstellt: <t:{created}:f>",
+        "whoami.guild" => "Server: {guild}",
+        "whoami.channel" => "Kanal: `{channel}`",
+        "whoami.locale" => "Sprache: `{locale}`",
+        "whoami.permissions" => "Berechtigungen: {permissions}",
+        "whoami.dm" => "keine (Direktnachricht)",
+        "help.no_such_command" => "Es gibt keinen Befehl `/{name}`",
+        "help.no_options" => "Dieser Befehl hat keine Optionen",
+        "help.options" => "Optionen:",
+        "help.required" => "erforderlich",
+        "help.optional" => "optional",
+        _ => return None,
+    })
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+/// Metrics for operators, served at `/metrics` in Prometheus's text format.
+pub struct Metrics {
+    registry: Registry,
+    /// Commands handled, by command and outcome.
+    commands: IntCounterVec,
+    /// How long commands took to handle, by command.
+    durations: HistogramVec,
+    /// Users with at least one open task, as of the last scrape.
+    active_users: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("todo_bot".into()), None)?;
+        let commands = IntCounterVec::new(
+            Opts::new("commands_total", "Commands handled, by command and outcome"),
+            &["command", "outcome"],
+        )?;
+        let durations = HistogramVec::new(
+            HistogramOpts::new(
+                "command_duration_seconds",
+                "How long commands took to handle, including sending the response",
+            ),
+            &["command"],
+        )?;
+        let active_users = IntGauge::new("active_users", "Users with at least one open task")?;
+        registry.register(Box::new(commands.clone()))?;
+        registry.register(Box::new(durations.clone()))?;
+        registry.register(Box::new(active_users.clone()))?;
+        Ok(Metrics {
+            registry,
+            commands,
+            durations,
+            active_users,
+        })
+    }
+
+    /// Records a handled command, with its outcome as recorded on its span, e.g. `ok`.
+    pub fn record(&self, command: &str, outcome: &str, elapsed: Duration) {
+        self.commands.with_label_values(&[command, outcome]).inc();
+        self.durations
+            .with_label_values(&[command])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn set_active_users(&self, users: usize) {
+        self.active_users.set(users as i64);
+    }
+
+    /// Every metric, in Prometheus's text format.
+    pub fn render(&self) -> prometheus::Result<Vec<u8>> {
+        let mut rendered = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut rendered)?;
+        Ok(rendered)
+    }
+}
@@ -0,0 +1,42 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long after a panic is reported that the same panic isn't reported again.
+const REPORT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Keeps track of which panics have been reported to the owner of the bot recently, so that a
+/// handler which panics every time it's used doesn't flood them with messages.
+#[derive(Default)]
+pub struct PanicReports {
+    /// When each panic message was last reported.
+    reported_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl PanicReports {
+    /// Records a panic with the given message, returning whether it should be reported: whether
+    /// the same panic hasn't already been reported within the last [`REPORT_WINDOW`].
+    pub fn record(&self, message: &str) -> bool {
+        let now = Instant::now();
+        let mut reported_at = self.reported_at.lock().unwrap();
+        reported_at.retain(|_, &mut at| now.duration_since(at) < REPORT_WINDOW);
+        if reported_at.contains_key(message) {
+            false
+        } else {
+            reported_at.insert(message.into(), now);
+            true
+        }
+    }
+}
+
+/// The message a panic was started with, if it has one.
+pub fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
@@ -1,9 +1,21 @@
 use twilight_model::{
     application::{
         command::CommandOptionType,
-        interaction::{application_command::CommandOptionValue, ApplicationCommand},
+        interaction::{
+            application_command::{
+                CommandDataOption, CommandInteractionDataResolved, CommandOptionValue,
+            },
+            ApplicationCommand,
+        },
     },
-    id::{marker::UserMarker, Id},
+    channel::{Attachment, Message},
+    datetime::Timestamp,
+    guild::Permissions,
+    id::{
+        marker::{AttachmentMarker, ChannelMarker, GuildMarker, UserMarker},
+        Id,
+    },
+    user::User,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -22,40 +34,94 @@ pub enum Error {
 pub enum CommandError {
     #[error("missing user")]
     MissingUser,
+    #[error("missing guild")]
+    MissingGuild,
+    #[error("missing member permissions")]
+    MissingPermissions,
+    #[error("missing the message the command was used on")]
+    MissingTargetMessage,
     #[error("missing the `{0}` option")]
     MissingOption(&'static str),
-    #[error("`{option}` is of wrong type: expected `{expected:?}`, got `{actual:?}`")]
-    InvalidType {
+    #[error("missing subcommand")]
+    MissingSubcommand,
+    /// A subcommand was given where the command's own options were expected, e.g. from a client
+    /// with a copy of the command from before or after it was split into subcommands.
+    #[error("expected the `{option}` option, but got the `{subcommand}` subcommand instead")]
+    UnexpectedSubcommand {
+        option: &'static str,
+        subcommand: String,
+    },
+    #[error("unknown subcommand `{0}`")]
+    UnknownSubcommand(String),
+    #[error("invalid `{option}` option: {error}")]
+    InvalidOption {
         option: &'static str,
+        #[source]
+        error: OptionError,
+    },
+    #[error("{}", DisplayErrors(.0))]
+    Multiple(Vec<CommandError>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OptionError {
+    #[error("expected `{expected:?}`, got `{actual:?}`")]
+    InvalidType {
         expected: CommandOptionType,
         actual: CommandOptionType,
+        /// The value received, rendered with `Debug`.
+        ///
+        /// This is left out of the message to keep it short, but shows up in logged errors, for
+        /// diagnosing clients with an out of date copy of a command.
+        value: String,
     },
+    /// The option had the right type, but its value isn't acceptable.
+    #[error("{reason} (got `{value}`)")]
+    InvalidValue { value: String, reason: String },
+    /// An integer option doesn't fit in the type it's parsed as.
+    #[error("`{value}` is out of range for `{ty}`")]
+    OutOfRange { value: i64, ty: &'static str },
+    /// The option was parsed, but was rejected by the command's validator for it.
+    #[error("{message}")]
+    Validation { message: String },
 }
 
-#[derive(Debug)]
-pub enum TodoCommand {
-    Task(TaskCommand),
-    Done(DoneCommand),
+impl CommandError {
+    /// Combines the errors from every field of a command into one error.
+    ///
+    /// This must only be called when at least one of `errors` is `Some`.
+    pub fn collect(errors: impl IntoIterator<Item = Option<CommandError>>) -> Self {
+        let mut errors = errors.into_iter().flatten().collect::<Vec<_>>();
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            CommandError::Multiple(errors)
+        }
+    }
 }
 
-impl TodoCommand {
-    pub fn parse(command: ApplicationCommand) -> Result<Self, Error> {
-        match &*command.data.name {
-            TaskCommand::COMMAND => TaskCommand::parse(command).map(TodoCommand::Task),
-            DoneCommand::COMMAND => DoneCommand::parse(command).map(TodoCommand::Done),
-            _ => Err(Error::InvalidCommand(command.data.name)),
+struct DisplayErrors<'a>(&'a [CommandError]);
+
+impl std::fmt::Display for DisplayErrors<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} errors:", self.0.len())?;
+        for error in self.0 {
+            write!(f, "\n- {error}")?;
         }
+        Ok(())
     }
 }
 
-#[derive(Debug)]
-pub struct TaskCommand {
-    pub user: Id<UserMarker>,
-    pub task: String,
-}
+/// A command which can be parsed from an interaction.
+///
+/// Only invoked commands are parsed. Autocomplete requests arrive as a separate interaction type,
+/// `ApplicationCommandAutocomplete`, whose option values are the user's partial input as strings
+/// rather than typed values, so they never reach this parser.
+pub trait ParseCommand: Sized {
+    /// The name the command is registered under.
+    const COMMAND: &'static str;
 
-impl TaskCommand {
-    const COMMAND: &'static str = "task";
+    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError>;
 
     fn parse(command: ApplicationCommand) -> Result<Self, Error> {
         Self::parse_inner(command).map_err(|error| Error::CommandError {
@@ -63,50 +129,831 @@ impl TaskCommand {
             error,
         })
     }
+}
 
-    fn parse_inner(command: ApplicationCommand) -> Result<Self, CommandError> {
-        let user = command
-            .member
-            .and_then(|mem| mem.user)
-            .ok_or(CommandError::MissingUser)?
-            .id;
-        let task = command
-            .data
-            .options
-            .into_iter()
-            .find(|opt| opt.name == "task")
-            .ok_or(CommandError::MissingOption("task"))?
-            .value
-            .to_string()
-            .map_err(|val| CommandError::InvalidType {
-                option: "task",
-                expected: CommandOptionType::String,
-                actual: val.kind(),
-            })?;
-        Ok(TaskCommand { user, task })
+/// Which part of the interaction payload the invoking user was read from.
+#[derive(Clone, Copy, Debug)]
+pub enum UserSource {
+    /// `member.user`, present for invocations in a guild.
+    Member,
+    /// The top-level `user`, present for invocations in a DM.
+    User,
+}
+
+impl std::fmt::Display for UserSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserSource::Member => f.write_str("member"),
+            UserSource::User => f.write_str("user"),
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct DoneCommand;
+/// Extracts the id of the user who invoked the command, in either a guild or a DM.
+pub fn parse_user(command: &ApplicationCommand) -> Result<Id<UserMarker>, CommandError> {
+    parse_invoker_ref(command).map(|user| user.id)
+}
+
+/// Extracts the full user object of the invoker, in either a guild or a DM.
+pub fn parse_invoker(command: &ApplicationCommand) -> Result<User, CommandError> {
+    parse_invoker_ref(command).cloned()
+}
 
-impl DoneCommand {
-    const COMMAND: &'static str = "done";
+/// Like [`parse_invoker`], but borrows the user from the interaction.
+pub fn parse_invoker_ref(command: &ApplicationCommand) -> Result<&User, CommandError> {
+    parse_invoker_with_source(command).map(|(user, _)| user)
+}
 
-    fn parse(_command: ApplicationCommand) -> Result<Self, Error> {
-        Ok(DoneCommand)
+pub fn parse_invoker_with_source(
+    command: &ApplicationCommand,
+) -> Result<(&User, UserSource), CommandError> {
+    if let Some(user) = command.member.as_ref().and_then(|mem| mem.user.as_ref()) {
+        Ok((user, UserSource::Member))
+    } else if let Some(user) = &command.user {
+        Ok((user, UserSource::User))
+    } else {
+        Err(CommandError::MissingUser)
     }
 }
 
-trait CommandOptionValueExt: Sized {
-    fn to_string(self) -> Result<String, Self>;
+/// Extracts the guild the command was invoked in; fails in a DM.
+pub fn parse_guild(command: &ApplicationCommand) -> Result<Id<GuildMarker>, CommandError> {
+    command.guild_id.ok_or(CommandError::MissingGuild)
 }
 
-impl CommandOptionValueExt for CommandOptionValue {
-    fn to_string(self) -> Result<String, Self> {
-        match self {
+/// Extracts the channel the command was invoked in.
+pub fn parse_channel(command: &ApplicationCommand) -> Result<Id<ChannelMarker>, CommandError> {
+    Ok(command.channel_id)
+}
+
+/// Extracts the locale of the invoking user.
+pub fn parse_locale(command: &ApplicationCommand) -> Result<String, CommandError> {
+    Ok(command.locale.clone())
+}
+
+/// Extracts the permissions of the invoking member in the channel; fails in a DM.
+pub fn parse_member_permissions(command: &ApplicationCommand) -> Result<Permissions, CommandError> {
+    command
+        .member
+        .as_ref()
+        .and_then(|mem| mem.permissions)
+        .ok_or(CommandError::MissingPermissions)
+}
+
+/// Looks up an attachment option's attachment in the interaction's resolved data, requiring it
+/// to be an image.
+pub fn resolve_image<'a>(
+    resolved: Option<&'a CommandInteractionDataResolved>,
+    option: &'static str,
+    id: Id<AttachmentMarker>,
+) -> Result<&'a Attachment, CommandError> {
+    resolve_attachment(resolved, option, id, "image/", "expected an image")
+}
+
+/// Looks up an attachment option's attachment in the interaction's resolved data, requiring it
+/// to be a text file.
+pub fn resolve_text_file<'a>(
+    resolved: Option<&'a CommandInteractionDataResolved>,
+    option: &'static str,
+    id: Id<AttachmentMarker>,
+) -> Result<&'a Attachment, CommandError> {
+    resolve_attachment(resolved, option, id, "text/", "expected a text file")
+}
+
+/// Looks up an attachment option's attachment in the interaction's resolved data, requiring its
+/// content type to start with `kind`.
+fn resolve_attachment<'a>(
+    resolved: Option<&'a CommandInteractionDataResolved>,
+    option: &'static str,
+    id: Id<AttachmentMarker>,
+    kind: &str,
+    expected: &str,
+) -> Result<&'a Attachment, CommandError> {
+    let invalid = |reason: &str| CommandError::InvalidOption {
+        option,
+        error: OptionError::InvalidValue {
+            value: id.to_string(),
+            reason: reason.into(),
+        },
+    };
+    let attachment = resolved
+        .and_then(|resolved| resolved.attachments.get(&id))
+        .ok_or_else(|| invalid("attachment missing from the interaction"))?;
+    match &attachment.content_type {
+        Some(content_type) if content_type.starts_with(kind) => Ok(attachment),
+        _ => Err(invalid(expected)),
+    }
+}
+
+/// Looks up the message a message context menu command was used on in the interaction's
+/// resolved data.
+pub fn parse_target_message(command: &ApplicationCommand) -> Result<&Message, CommandError> {
+    let target = command
+        .data
+        .target_id
+        .ok_or(CommandError::MissingTargetMessage)?;
+    command
+        .data
+        .resolved
+        .as_ref()
+        .and_then(|resolved| resolved.messages.get(&target.cast()))
+        .ok_or(CommandError::MissingTargetMessage)
+}
+
+/// Milliseconds from the Unix epoch to the start of 2015, which snowflakes count from.
+const DISCORD_EPOCH: u64 = 1_420_070_400_000;
+
+/// When the object with a snowflake ID, such as a user or an interaction, was created, which is
+/// kept in the top 42 bits of the ID.
+pub fn timestamp_of<T>(id: Id<T>) -> Timestamp {
+    let millis = (id.get() >> 22) + DISCORD_EPOCH;
+    // 42 bits of milliseconds only reach 2154, well within what a timestamp can hold.
+    Timestamp::from_micros(millis as i64 * 1000).expect("snowflake timestamps are in range")
+}
+
+/// The options of a command, removed one at a time as the fields are parsed.
+pub struct Options(Vec<CommandDataOption>);
+
+impl Options {
+    pub fn new(options: Vec<CommandDataOption>) -> Self {
+        Options(options)
+    }
+
+    /// Takes the subcommand the command was invoked with, returning its name and options.
+    ///
+    /// A subcommand in a group is named by the group and subcommand names separated by a space,
+    /// e.g. `group sub`, as Discord shows it.
+    pub fn subcommand(&mut self) -> Result<(String, Options), CommandError> {
+        let idx = self
+            .0
+            .iter()
+            .position(|opt| {
+                matches!(
+                    opt.value,
+                    CommandOptionValue::SubCommand(_) | CommandOptionValue::SubCommandGroup(_)
+                )
+            })
+            .ok_or(CommandError::MissingSubcommand)?;
+        let option = self.0.swap_remove(idx);
+        match option.value {
+            CommandOptionValue::SubCommand(options) => Ok((option.name, Options(options))),
+            CommandOptionValue::SubCommandGroup(options) => {
+                let (name, options) = Options(options).subcommand()?;
+                Ok((format!("{} {name}", option.name), options))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn take(&mut self, name: &str) -> Option<CommandOptionValue> {
+        let idx = self.0.iter().position(|opt| opt.name == name)?;
+        Some(self.0.swap_remove(idx).value)
+    }
+
+    /// Takes a required option.
+    ///
+    /// If it's missing but the command was invoked with a subcommand instead, the error says so,
+    /// since that means the command's structure doesn't match the one the client has.
+    pub fn required<T: ParseOption>(&mut self, name: &'static str) -> Result<T, CommandError> {
+        self.required_with(name, |_| Ok(()))
+    }
+
+    /// Takes a required option, which must then pass `validate`.
+    pub fn required_with<T: ParseOption>(
+        &mut self,
+        name: &'static str,
+        validate: impl FnOnce(&T) -> Result<(), String>,
+    ) -> Result<T, CommandError> {
+        self.optional_with(name, validate)?
+            .ok_or_else(|| self.missing(name))
+    }
+
+    /// The error for the required option `name` being missing.
+    fn missing(&self, name: &'static str) -> CommandError {
+        let subcommand = self.0.iter().find(|opt| {
+            matches!(
+                opt.value,
+                CommandOptionValue::SubCommand(_) | CommandOptionValue::SubCommandGroup(_)
+            )
+        });
+        match subcommand {
+            Some(subcommand) => CommandError::UnexpectedSubcommand {
+                option: name,
+                subcommand: subcommand.name.clone(),
+            },
+            None => CommandError::MissingOption(name),
+        }
+    }
+
+    pub fn optional<T: ParseOption>(
+        &mut self,
+        name: &'static str,
+    ) -> Result<Option<T>, CommandError> {
+        self.optional_with(name, |_| Ok(()))
+    }
+
+    /// Takes an optional option, which must then pass `validate` if it was given.
+    ///
+    /// The validator sees the parsed value, and its message is reported as
+    /// [`OptionError::Validation`].
+    pub fn optional_with<T: ParseOption>(
+        &mut self,
+        name: &'static str,
+        validate: impl FnOnce(&T) -> Result<(), String>,
+    ) -> Result<Option<T>, CommandError> {
+        self.take(name)
+            .map(|value| {
+                let value = T::parse_option(value)?;
+                validate(&value).map_err(|message| OptionError::Validation { message })?;
+                Ok(value)
+            })
+            .transpose()
+            .map_err(|error| CommandError::InvalidOption {
+                option: name,
+                error,
+            })
+    }
+}
+
+pub trait ParseOption: Sized {
+    const KIND: CommandOptionType;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError>;
+
+    fn invalid_type(value: &CommandOptionValue) -> OptionError {
+        OptionError::InvalidType {
+            expected: Self::KIND,
+            actual: value.kind(),
+            value: format!("{value:?}"),
+        }
+    }
+}
+
+impl ParseOption for String {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
             CommandOptionValue::String(string) => Ok(string),
-            _ => Err(self),
+            _ => Err(Self::invalid_type(&value)),
+        }
+    }
+}
+
+impl ParseOption for bool {
+    const KIND: CommandOptionType = CommandOptionType::Boolean;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::Boolean(boolean) => Ok(boolean),
+            _ => Err(Self::invalid_type(&value)),
+        }
+    }
+}
+
+impl ParseOption for i64 {
+    const KIND: CommandOptionType = CommandOptionType::Integer;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::Integer(integer) => Ok(integer),
+            _ => Err(Self::invalid_type(&value)),
+        }
+    }
+}
+
+/// Implements [`ParseOption`] for integer types other than `i64`, rejecting values which don't
+/// fit rather than truncating them.
+macro_rules! parse_integer_option {
+    ($($ty:ty),*) => {$(
+        impl ParseOption for $ty {
+            const KIND: CommandOptionType = CommandOptionType::Integer;
+
+            fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+                let integer = i64::parse_option(value)?;
+                <$ty>::try_from(integer).map_err(|_| OptionError::OutOfRange {
+                    value: integer,
+                    ty: stringify!($ty),
+                })
+            }
+        }
+    )*};
+}
+
+parse_integer_option!(i8, i16, i32, u8, u16, u32, u64, usize);
+
+/// An integer option which is also accepted as a string of digits, as some clients send for
+/// options whose choices are numbers.
+///
+/// Only options parsed as `LenientInteger` accept strings, so a string given for any other integer
+/// option is still rejected as the wrong type.
+#[derive(Clone, Copy, Debug)]
+pub struct LenientInteger<T>(pub T);
+
+impl<T: ParseOption> ParseOption for LenientInteger<T> {
+    const KIND: CommandOptionType = T::KIND;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::String(string) => match string.trim().parse() {
+                Ok(integer) => T::parse_option(CommandOptionValue::Integer(integer)).map(Self),
+                Err(_) => Err(OptionError::InvalidValue {
+                    value: string,
+                    reason: "expected an integer".into(),
+                }),
+            },
+            value => T::parse_option(value).map(Self),
+        }
+    }
+}
+
+impl ParseOption for Id<AttachmentMarker> {
+    const KIND: CommandOptionType = CommandOptionType::Attachment;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::Attachment(id) => Ok(id),
+            _ => Err(Self::invalid_type(&value)),
+        }
+    }
+}
+
+impl ParseOption for Id<UserMarker> {
+    const KIND: CommandOptionType = CommandOptionType::User;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::User(id) => Ok(id),
+            _ => Err(Self::invalid_type(&value)),
+        }
+    }
+}
+
+/// A user given either as a user option, or as a mention (`<@id>` or `<@!id>`) in a string
+/// option.
+#[derive(Clone, Copy, Debug)]
+pub struct UserOrMention(pub Id<UserMarker>);
+
+impl UserOrMention {
+    /// Parses a user mention, in either the `<@id>` or the older nickname `<@!id>` format.
+    pub fn parse_mention(mention: &str) -> Option<Id<UserMarker>> {
+        let id = mention.trim().strip_prefix("<@")?.strip_suffix('>')?;
+        let id = id.strip_prefix('!').unwrap_or(id);
+        id.parse().ok()
+    }
+}
+
+impl ParseOption for UserOrMention {
+    const KIND: CommandOptionType = CommandOptionType::User;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        match value {
+            CommandOptionValue::User(id) => Ok(UserOrMention(id)),
+            CommandOptionValue::String(string) => match Self::parse_mention(&string) {
+                Some(id) => Ok(UserOrMention(id)),
+                None => Err(OptionError::InvalidValue {
+                    value: string,
+                    reason: "expected a user mention like `<@123>`".into(),
+                }),
+            },
+            _ => Err(Self::invalid_type(&value)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{application_command, InteractionFixture};
+
+    fn options_of(fixture: InteractionFixture) -> Options {
+        Options::new(fixture.build().data.options)
+    }
+
+    #[test]
+    fn reads_the_user_from_the_member_in_a_guild() {
+        let command = InteractionFixture::new("task").user(5).guild(10).build();
+        let (user, source) = parse_invoker_with_source(&command).unwrap();
+        assert_eq!(user.id, Id::new(5));
+        assert!(matches!(source, UserSource::Member));
+        assert_eq!(parse_invoker(&command).unwrap().name, "user5");
+    }
+
+    #[test]
+    fn reads_the_user_from_the_top_level_in_a_dm() {
+        let command = InteractionFixture::new("task").user(5).build();
+        let (user, source) = parse_invoker_with_source(&command).unwrap();
+        assert_eq!(user.id, Id::new(5));
+        assert!(matches!(source, UserSource::User));
+        assert_eq!(parse_user(&command).unwrap(), Id::new(5));
+    }
+
+    #[test]
+    fn falls_back_to_the_top_level_user_when_the_member_has_none() {
+        let mut json = InteractionFixture::new("task").user(5).guild(10).to_json();
+        json["member"].as_object_mut().unwrap().remove("user");
+        json["user"] = InteractionFixture::new("task").user(6).to_json()["user"].take();
+        let command = application_command(&json);
+        let (user, source) = parse_invoker_with_source(&command).unwrap();
+        assert_eq!(user.id, Id::new(6));
+        assert!(matches!(source, UserSource::User));
+    }
+
+    #[test]
+    fn rejects_a_command_without_a_user() {
+        let mut json = InteractionFixture::new("task").to_json();
+        json.as_object_mut().unwrap().remove("user");
+        let command = application_command(&json);
+        assert!(matches!(
+            parse_user(&command),
+            Err(CommandError::MissingUser)
+        ));
+    }
+
+    #[test]
+    fn reads_the_guild_channel_and_locale() {
+        let command = InteractionFixture::new("whoami")
+            .guild(10)
+            .locale("de")
+            .build();
+        assert_eq!(parse_guild(&command).unwrap(), Id::new(10));
+        assert_eq!(parse_channel(&command).unwrap(), Id::new(20));
+        assert_eq!(parse_locale(&command).unwrap(), "de");
+
+        let command = InteractionFixture::new("whoami").build();
+        assert!(matches!(
+            parse_guild(&command),
+            Err(CommandError::MissingGuild)
+        ));
+        assert_eq!(parse_channel(&command).unwrap(), Id::new(20));
+        assert_eq!(parse_locale(&command).unwrap(), "en-US");
+    }
+
+    #[test]
+    fn member_permissions_are_only_present_in_a_guild() {
+        let command = InteractionFixture::new("transfer")
+            .guild(10)
+            .permissions(Permissions::MANAGE_GUILD.bits())
+            .build();
+        assert_eq!(
+            parse_member_permissions(&command).unwrap(),
+            Permissions::MANAGE_GUILD
+        );
+
+        let command = InteractionFixture::new("transfer").build();
+        assert!(matches!(
+            parse_member_permissions(&command),
+            Err(CommandError::MissingPermissions)
+        ));
+    }
+
+    fn parse_integer<T: ParseOption>(value: i64) -> Result<T, OptionError> {
+        T::parse_option(CommandOptionValue::Integer(value))
+    }
+
+    fn assert_out_of_range<T: ParseOption + std::fmt::Debug>(value: i64, expected_ty: &str) {
+        match parse_integer::<T>(value) {
+            Err(OptionError::OutOfRange { value: got, ty }) => {
+                assert_eq!(got, value);
+                assert_eq!(ty, expected_ty);
+            }
+            other => panic!("expected `{value}` to be out of range, got {other:?}"),
+        }
+    }
+
+    /// Tests that an integer type narrower than `i64` accepts its own bounds, and rejects the
+    /// integers just past them.
+    macro_rules! narrow_integer_bounds {
+        ($($name:ident: $ty:ty),*) => {$(
+            #[test]
+            fn $name() {
+                let (min, max) = (i64::from(<$ty>::MIN), i64::from(<$ty>::MAX));
+                assert_out_of_range::<$ty>(min - 1, stringify!($ty));
+                assert_eq!(parse_integer::<$ty>(min).unwrap(), <$ty>::MIN);
+                assert_eq!(parse_integer::<$ty>(max).unwrap(), <$ty>::MAX);
+                assert_out_of_range::<$ty>(max + 1, stringify!($ty));
+            }
+        )*};
+    }
+
+    narrow_integer_bounds!(
+        i8_bounds: i8,
+        i16_bounds: i16,
+        i32_bounds: i32,
+        u8_bounds: u8,
+        u16_bounds: u16,
+        u32_bounds: u32
+    );
+
+    // Discord's integers are `i64`s, so the unsigned 64-bit types can't be given anything past
+    // their maximum; `i64::MAX` is the largest they'll see.
+    #[test]
+    fn u64_bounds() {
+        assert_out_of_range::<u64>(-1, "u64");
+        assert_eq!(parse_integer::<u64>(0).unwrap(), 0);
+        assert_eq!(parse_integer::<u64>(i64::MAX).unwrap(), i64::MAX as u64);
+    }
+
+    #[test]
+    fn usize_bounds() {
+        assert_out_of_range::<usize>(-1, "usize");
+        assert_eq!(parse_integer::<usize>(0).unwrap(), 0);
+        assert_eq!(
+            parse_integer::<usize>(i64::MAX).unwrap(),
+            usize::try_from(i64::MAX).unwrap()
+        );
+    }
+
+    #[test]
+    fn i64_bounds() {
+        assert_eq!(parse_integer::<i64>(i64::MIN).unwrap(), i64::MIN);
+        assert_eq!(parse_integer::<i64>(i64::MAX).unwrap(), i64::MAX);
+    }
+
+    /// Feeds pseudo-random integers of every magnitude to each integer type, checking that
+    /// exactly the ones in its range are accepted, and unchanged.
+    #[test]
+    fn random_integers_are_accepted_only_in_range() {
+        fn check<T>(value: i64, min: i128, max: i128)
+        where
+            T: ParseOption + std::fmt::Debug + Into<i128>,
+        {
+            match parse_integer::<T>(value) {
+                Ok(parsed) => assert_eq!(parsed.into(), i128::from(value)),
+                Err(OptionError::OutOfRange { .. }) => {
+                    assert!(
+                        !(min..=max).contains(&i128::from(value)),
+                        "rejected {value}"
+                    )
+                }
+                Err(e) => panic!("unexpected error for {value}: {e}"),
+            }
+        }
+        macro_rules! check_all {
+            ($value:expr, $($ty:ty),*) => {$(
+                check::<$ty>($value, <$ty>::MIN.into(), <$ty>::MAX.into());
+            )*};
+        }
+
+        // xorshift64, with a fixed seed so that failures are repeatable.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Shifting by a random amount covers small and large magnitudes alike.
+            let value = (state as i64) >> (state % 64);
+            check_all!(value, i8, i16, i32, i64, u8, u16, u32, u64);
+        }
+    }
+
+    #[test]
+    fn an_integer_type_rejects_other_option_types() {
+        let value = CommandOptionValue::String("3".into());
+        assert!(matches!(
+            u8::parse_option(value),
+            Err(OptionError::InvalidType {
+                expected: CommandOptionType::Integer,
+                actual: CommandOptionType::String,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn a_user_can_be_given_as_a_mention() {
+        let mut options = options_of(
+            InteractionFixture::new("transfer")
+                .user_option("to", 5)
+                .string_option("nickname", "<@!6>")
+                .string_option("plain", "<@6>")
+                .string_option("name", "@someone")
+                .string_option("not_a_number", "<@abc>")
+                .string_option("unclosed", "<@6"),
+        );
+        assert_eq!(
+            options.required::<UserOrMention>("to").unwrap().0,
+            Id::new(5)
+        );
+        assert_eq!(
+            options.required::<UserOrMention>("nickname").unwrap().0,
+            Id::new(6)
+        );
+        assert_eq!(
+            options.required::<UserOrMention>("plain").unwrap().0,
+            Id::new(6)
+        );
+        for malformed in ["name", "not_a_number", "unclosed"] {
+            assert!(
+                matches!(
+                    options.required::<UserOrMention>(malformed),
+                    Err(CommandError::InvalidOption {
+                        error: OptionError::InvalidValue { .. },
+                        ..
+                    })
+                ),
+                "{malformed} should be rejected",
+            );
+        }
+    }
+
+    #[test]
+    fn finds_the_target_message_of_a_context_menu_command() {
+        let command = InteractionFixture::message_command("Add to todo", 30, "buy milk").build();
+        assert_eq!(parse_target_message(&command).unwrap().content, "buy milk");
+
+        let command = InteractionFixture::new("Add to todo").build();
+        assert!(matches!(
+            parse_target_message(&command),
+            Err(CommandError::MissingTargetMessage)
+        ));
+    }
+
+    #[test]
+    fn a_missing_option_is_named() {
+        let mut options = options_of(InteractionFixture::new("done"));
+        assert!(matches!(
+            options.required::<i64>("task"),
+            Err(CommandError::MissingOption("task"))
+        ));
+        assert!(matches!(options.optional::<i64>("task"), Ok(None)));
+    }
+
+    #[test]
+    fn a_subcommand_in_place_of_an_option_is_reported() {
+        let mut options = options_of(InteractionFixture::new("done").subcommand("now", |sub| sub));
+        match options.required::<i64>("task") {
+            Err(CommandError::UnexpectedSubcommand { option, subcommand }) => {
+                assert_eq!(option, "task");
+                assert_eq!(subcommand, "now");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn takes_the_subcommand_and_its_options() {
+        let mut options = options_of(
+            InteractionFixture::new("backup")
+                .subcommand("restore", |sub| sub.bool_option("confirm", true)),
+        );
+        let (name, mut sub) = options.subcommand().unwrap();
+        assert_eq!(name, "restore");
+        assert!(sub.required::<bool>("confirm").unwrap());
+
+        let mut options = options_of(InteractionFixture::new("backup"));
+        assert!(matches!(
+            options.subcommand(),
+            Err(CommandError::MissingSubcommand)
+        ));
+    }
+
+    #[test]
+    fn names_a_subcommand_in_a_group_by_both() {
+        let mut options = options_of(
+            InteractionFixture::new("admin").subcommand_group("commands", |group| {
+                group.subcommand("reset", |sub| sub.bool_option("confirm", true))
+            }),
+        );
+        let (name, mut sub) = options.subcommand().unwrap();
+        assert_eq!(name, "commands reset");
+        assert!(sub.required::<bool>("confirm").unwrap());
+    }
+
+    #[test]
+    fn an_option_of_the_wrong_type_is_invalid() {
+        let mut options =
+            options_of(InteractionFixture::new("done").string_option("task", "three"));
+        match options.required::<i64>("task") {
+            Err(CommandError::InvalidOption {
+                option: "task",
+                error:
+                    OptionError::InvalidType {
+                        expected, actual, ..
+                    },
+            }) => {
+                assert_eq!(expected, CommandOptionType::Integer);
+                assert_eq!(actual, CommandOptionType::String);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn a_lenient_integer_accepts_a_string_of_digits() {
+        let mut options = options_of(
+            InteractionFixture::new("done")
+                .string_option("task", " 3 ")
+                .int_option("other", 4)
+                .string_option("bad", "three"),
+        );
+        let LenientInteger(task) = options.required::<LenientInteger<usize>>("task").unwrap();
+        assert_eq!(task, 3);
+        let LenientInteger(other) = options.required::<LenientInteger<usize>>("other").unwrap();
+        assert_eq!(other, 4);
+        assert!(matches!(
+            options.required::<LenientInteger<usize>>("bad"),
+            Err(CommandError::InvalidOption {
+                option: "bad",
+                error: OptionError::InvalidValue { .. },
+            })
+        ));
+    }
+
+    #[test]
+    fn a_validator_can_reject_an_option() {
+        let mut options = options_of(InteractionFixture::new("task").string_option("task", " "));
+        let task = options.required_with("task", |task: &String| {
+            if task.trim().is_empty() {
+                Err("blank".into())
+            } else {
+                Ok(())
+            }
+        });
+        assert!(matches!(
+            task,
+            Err(CommandError::InvalidOption {
+                error: OptionError::Validation { message },
+                ..
+            }) if message == "blank"
+        ));
+    }
+
+    #[test]
+    fn an_attachment_must_have_the_expected_type() {
+        let command = InteractionFixture::new("task")
+            .attachment_option("image", 40, "image/png")
+            .attachment_option("notes", 41, "text/plain")
+            .build();
+        let resolved = command.data.resolved.as_ref();
+        assert!(resolve_image(resolved, "image", Id::new(40)).is_ok());
+        assert!(resolve_text_file(resolved, "notes", Id::new(41)).is_ok());
+        assert!(matches!(
+            resolve_image(resolved, "notes", Id::new(41)),
+            Err(CommandError::InvalidOption {
+                error: OptionError::InvalidValue { .. },
+                ..
+            })
+        ));
+        assert!(resolve_image(resolved, "image", Id::new(42)).is_err());
+    }
+
+    #[test]
+    fn collects_one_error_as_itself_and_several_as_multiple() {
+        let one = CommandError::collect([None, Some(CommandError::MissingUser)]);
+        assert!(matches!(one, CommandError::MissingUser));
+
+        let several = CommandError::collect([
+            Some(CommandError::MissingUser),
+            None,
+            Some(CommandError::MissingOption("task")),
+        ]);
+        match several {
+            CommandError::Multiple(errors) => assert_eq!(errors.len(), 2),
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    /// Checks that `T` is an integer option, which parses when it's given, is `None` when it's
+    /// optional and missing, and is named in the error when it's required and missing.
+    fn assert_integer_option<T>()
+    where
+        T: ParseOption + TryFrom<i64> + PartialEq + std::fmt::Debug,
+        <T as TryFrom<i64>>::Error: std::fmt::Debug,
+    {
+        assert_eq!(T::KIND, CommandOptionType::Integer);
+        let mut options = options_of(InteractionFixture::new("done").int_option("task", 7));
+        assert_eq!(
+            options.optional::<T>("task").unwrap(),
+            Some(T::try_from(7).unwrap())
+        );
+        assert!(matches!(options.optional::<T>("task"), Ok(None)));
+        assert!(matches!(
+            options.required::<T>("task"),
+            Err(CommandError::MissingOption("task"))
+        ));
+    }
+
+    #[test]
+    fn integer_options_may_be_optional_or_required() {
+        assert_integer_option::<i8>();
+        assert_integer_option::<i16>();
+        assert_integer_option::<i32>();
+        assert_integer_option::<i64>();
+        assert_integer_option::<u8>();
+        assert_integer_option::<u16>();
+        assert_integer_option::<u32>();
+        assert_integer_option::<u64>();
+        assert_integer_option::<usize>();
+    }
+
+    #[test]
+    fn each_option_type_declares_its_kind() {
+        assert_eq!(String::KIND, CommandOptionType::String);
+        assert_eq!(bool::KIND, CommandOptionType::Boolean);
+        assert_eq!(Id::<AttachmentMarker>::KIND, CommandOptionType::Attachment);
+        assert_eq!(Id::<UserMarker>::KIND, CommandOptionType::User);
+        assert_eq!(UserOrMention::KIND, CommandOptionType::User);
+        assert_eq!(LenientInteger::<usize>::KIND, CommandOptionType::Integer);
+    }
+}
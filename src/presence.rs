@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+use twilight_gateway::Shard;
+use twilight_model::gateway::{
+    payload::outgoing::{update_presence::UpdatePresenceError, UpdatePresence},
+    presence::{ActivityType, MinimalActivity, Status},
+};
+
+use crate::messages;
+use crate::storage::Stats;
+use crate::State;
+
+/// Works out the bot's status from the totals across every list every `presence_interval`,
+/// starting straight away, and sends it to the gateway loop through `presence`.
+///
+/// Failures are only logged at debug level, since the status is cosmetic.
+pub async fn run_periodic(state: Arc<State>, presence: watch::Sender<Option<UpdatePresence>>) {
+    let mut interval = tokio::time::interval(state.config.presence_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let stats = match state.storage.stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                log::debug!("failed to count tasks for the bot's status: {e}");
+                continue;
+            }
+        };
+        match update(&state.config.presence_template, &stats) {
+            Ok(update) => {
+                presence.send_replace(Some(update));
+            }
+            Err(e) => log::debug!("failed to build the bot's status: {e}"),
+        }
+    }
+}
+
+/// Sets the bot's status on the shard, if it's been worked out yet.
+pub async fn send(shard: &Shard, update: Option<UpdatePresence>) {
+    if let Some(update) = update {
+        if let Err(e) = shard.command(&update).await {
+            log::debug!("failed to update the bot's status: {e}");
+        }
+    }
+}
+
+/// The status for the given totals, e.g. "Watching 1,284 tasks across 93 users".
+fn update(template: &str, stats: &Stats) -> Result<UpdatePresence, UpdatePresenceError> {
+    let name = messages::render(
+        template,
+        &[
+            ("tasks", &separated(stats.tasks)),
+            ("users", &separated(stats.users)),
+        ],
+    );
+    let activity = MinimalActivity {
+        kind: ActivityType::Watching,
+        name,
+        url: None,
+    };
+    UpdatePresence::new(vec![activity.into()], false, None, Status::Online)
+}
+
+/// Formats a number with commas between each group of three digits, e.g. `1,284`.
+fn separated(n: usize) -> String {
+    let digits = n.to_string();
+    let mut separated = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            separated.push(',');
+        }
+        separated.push(digit);
+    }
+    separated
+}
@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use futures_util::future::BoxFuture;
+use twilight_model::{
+    application::{
+        callback::InteractionResponse,
+        command::{Command, CommandOption, CommandOptionChoice, CommandType},
+        interaction::application_command::ApplicationCommand,
+    },
+    channel::message::MessageFlags,
+    id::Id,
+};
+use twilight_util::builder::CallbackDataBuilder;
+
+use crate::parser::{Error, ParseCommand};
+use crate::State;
+
+type HandlerResult = anyhow::Result<InteractionResponse>;
+
+/// A command which knows how to respond to itself.
+///
+/// Responses are sent with no mentions allowed, so that echoing what a user typed can't ping
+/// anyone. A command which is meant to ping someone, such as a specific user, says so by setting
+/// `allowed_mentions` on its response, which is then left alone.
+#[async_trait::async_trait]
+pub trait RunCommand: Send + Sized {
+    /// How the command's response is delivered.
+    const RESPONSE: ResponsePolicy = ResponsePolicy::Immediate;
+
+    /// How many tokens using the command takes from the user's cooldown bucket, or zero for a
+    /// command which isn't rate limited.
+    const COST: u32 = 1;
+
+    async fn run(self, state: &State) -> HandlerResult;
+}
+
+/// How a command's response is delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponsePolicy {
+    /// The response is sent as soon as the handler returns, in a single round trip.
+    Immediate,
+    /// The interaction is acknowledged before the handler runs, and its response then replaces
+    /// the acknowledgement, for handlers which may not finish within Discord's three seconds.
+    ///
+    /// Whether the response is ephemeral has to be decided when acknowledging, so any flags on
+    /// the handler's response are ignored.
+    Deferred { ephemeral: bool },
+}
+
+/// Parses an interaction into the command type a handler was registered for, and returns the
+/// handler's future.
+type Handler = Box<
+    dyn Fn(Arc<State>, ApplicationCommand) -> Result<BoxFuture<'static, HandlerResult>, Error>
+        + Send
+        + Sync,
+>;
+
+/// A registered command's handler, along with the settings its type declares.
+struct Registered {
+    response: ResponsePolicy,
+    cost: u32,
+    handler: Handler,
+}
+
+/// The set of commands the bot knows about, along with the handler for each.
+///
+/// Both the definitions registered with Discord and the dispatch of incoming interactions are
+/// driven from the registry, so the two can't drift apart.
+pub struct CommandRegistry {
+    definitions: BTreeMap<String, Command>,
+    handlers: BTreeMap<&'static str, Registered>,
+    /// Prepended to every command name when registering, and stripped again when dispatching.
+    prefix: String,
+}
+
+impl CommandRegistry {
+    /// Creates an empty registry, with command definitions loaded from the given YAML file.
+    ///
+    /// Commands are registered with Discord under their names with `prefix` prepended, so that
+    /// multiple instances of the bot can coexist.
+    ///
+    /// Fails if any definition would be rejected by Discord for its choices, or for giving a
+    /// context menu command a description or options.
+    pub fn load(path: &str, prefix: String) -> anyhow::Result<Self> {
+        let definitions: Vec<Command> = serde_yaml::from_reader(std::fs::File::open(path)?)?;
+        for command in &definitions {
+            validate_context_menu(command)?;
+            validate_choices(&command.name, &command.options)?;
+        }
+        Ok(CommandRegistry {
+            definitions: definitions
+                .into_iter()
+                .map(|command| (command.name.clone(), command))
+                .collect(),
+            handlers: BTreeMap::new(),
+            prefix,
+        })
+    }
+
+    /// Registers the command `C`.
+    ///
+    /// Fails if no definition for `C` was loaded.
+    pub fn register<C>(&mut self) -> anyhow::Result<&mut Self>
+    where
+        C: ParseCommand + RunCommand + 'static,
+    {
+        if !self.definitions.contains_key(C::COMMAND) {
+            anyhow::bail!("no definition for the `{}` command", C::COMMAND);
+        }
+        let handler = |state: Arc<State>, command| {
+            let command = C::parse(command).map_err(|e| {
+                // The `Debug` form has details which the message leaves out, such as the value of
+                // an option with the wrong type.
+                log::warn!("failed to parse command: {e:?}");
+                e
+            })?;
+            Ok(Box::pin(async move { command.run(&state).await }) as BoxFuture<_>)
+        };
+        self.handlers.insert(
+            C::COMMAND,
+            Registered {
+                response: C::RESPONSE,
+                cost: C::COST,
+                handler: Box::new(handler),
+            },
+        );
+        Ok(self)
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// How the response to the named command is delivered.
+    pub fn response_policy(&self, name: &str) -> ResponsePolicy {
+        name.strip_prefix(&self.prefix)
+            .and_then(|name| self.handlers.get(name))
+            .map_or(ResponsePolicy::Immediate, |registered| registered.response)
+    }
+
+    /// How many tokens the named command takes from the user's cooldown bucket.
+    pub fn cost(&self, name: &str) -> u32 {
+        name.strip_prefix(&self.prefix)
+            .and_then(|name| self.handlers.get(name))
+            .map_or(1, |registered| registered.cost)
+    }
+
+    /// The definitions of every command with a registered handler.
+    pub fn commands(&self) -> Vec<Command> {
+        self.definitions
+            .values()
+            .filter(|command| self.handlers.contains_key(&*command.name))
+            .map(|command| Command {
+                name: format!("{}{}", self.prefix, command.name),
+                ..command.clone()
+            })
+            .collect()
+    }
+
+    /// Parses the command and runs its handler.
+    ///
+    /// Errors parsing the command are reported back to the user rather than returned.
+    pub async fn dispatch(&self, state: Arc<State>, command: ApplicationCommand) -> HandlerResult {
+        let handler = command
+            .data
+            .name
+            .strip_prefix(&self.prefix)
+            .and_then(|name| self.handlers.get(name));
+        let future = match handler {
+            Some(registered) => (registered.handler)(state, command),
+            None => Err(Error::InvalidCommand(command.data.name)),
+        };
+        match future {
+            Ok(future) => future.await,
+            Err(error) => {
+                tracing::Span::current().record("parse_error", true);
+                log::warn!("{error}");
+                let cb = CallbackDataBuilder::new()
+                    .content(error.to_string())
+                    .flags(MessageFlags::EPHEMERAL)
+                    .build();
+                Ok(InteractionResponse::ChannelMessageWithSource(cb))
+            }
+        }
+    }
+}
+
+/// Checks that a context menu command, which is used on a user or a message rather than typed,
+/// has neither a description nor options, which Discord doesn't allow for them.
+fn validate_context_menu(command: &Command) -> anyhow::Result<()> {
+    let kind = match command.kind {
+        CommandType::ChatInput => return Ok(()),
+        CommandType::User => "user",
+        CommandType::Message => "message",
+    };
+    if !command.description.is_empty() {
+        anyhow::bail!(
+            "`{}` is a {kind} command, so it can't have a description",
+            command.name
+        );
+    }
+    if !command.options.is_empty() {
+        anyhow::bail!(
+            "`{}` is a {kind} command, so it can't have options",
+            command.name
+        );
+    }
+    Ok(())
+}
+
+/// The most choices Discord allows on a single option.
+const MAX_CHOICES: usize = 25;
+
+/// Checks that no option, including those of subcommands, has more choices than Discord allows,
+/// or the same choice value more than once.
+fn validate_choices(command: &str, options: &[CommandOption]) -> anyhow::Result<()> {
+    for option in options {
+        let (name, choices) = match option {
+            CommandOption::SubCommand(data) | CommandOption::SubCommandGroup(data) => {
+                validate_choices(&format!("{command} {}", data.name), &data.options)?;
+                continue;
+            }
+            CommandOption::String(data) => (&data.name, &data.choices),
+            CommandOption::Integer(data) | CommandOption::Number(data) => {
+                (&data.name, &data.choices)
+            }
+            _ => continue,
+        };
+        if choices.len() > MAX_CHOICES {
+            anyhow::bail!(
+                "option `{name}` of `/{command}` has {} choices, but at most {MAX_CHOICES} are \
+                 allowed",
+                choices.len(),
+            );
+        }
+        let mut values = HashSet::new();
+        for choice in choices {
+            let value = match choice {
+                CommandOptionChoice::String { value, .. } => value.clone(),
+                CommandOptionChoice::Int { value, .. } => value.to_string(),
+                CommandOptionChoice::Number { value, .. } => value.0.to_string(),
+            };
+            if !values.insert(value.clone()) {
+                anyhow::bail!("option `{name}` of `/{command}` has the choice `{value}` twice");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether two command definitions are the same, ignoring the fields Discord assigns when a
+/// command is registered.
+pub fn commands_equivalent(a: &Command, b: &Command) -> bool {
+    normalize(a) == normalize(b)
+}
+
+fn normalize(command: &Command) -> Command {
+    Command {
+        application_id: None,
+        guild_id: None,
+        id: None,
+        version: Id::new(1),
+        // Discord fills in the default when it's left unset.
+        default_permission: Some(command.default_permission.unwrap_or(true)),
+        ..command.clone()
+    }
+}
+
+/// How the commands registered with Discord differ from the ones the bot defines.
+pub struct CommandDiff<'a> {
+    pub created: Vec<&'a Command>,
+    pub updated: Vec<&'a Command>,
+    pub unchanged: Vec<&'a Command>,
+    /// Registered commands which are no longer defined.
+    pub deleted: Vec<&'a Command>,
+}
+
+impl<'a> CommandDiff<'a> {
+    /// Compares the defined `commands` with the `registered` ones.
+    ///
+    /// Only registered commands whose names start with `prefix` are considered for deletion, so
+    /// that instances of the bot with different prefixes leave each other's commands alone. Every
+    /// name starts with an empty prefix, so an instance without one deletes nothing, and instead
+    /// warns about each command it no longer defines, which has to be deleted by hand.
+    pub fn new(commands: &'a [Command], registered: &'a [Command], prefix: &str) -> Self {
+        let mut diff = CommandDiff {
+            created: Vec::new(),
+            updated: Vec::new(),
+            unchanged: Vec::new(),
+            deleted: Vec::new(),
+        };
+        for command in commands {
+            match registered.iter().find(|other| other.name == command.name) {
+                Some(other) if commands_equivalent(command, other) => diff.unchanged.push(command),
+                Some(_) => diff.updated.push(command),
+                None => diff.created.push(command),
+            }
+        }
+        let stale = registered
+            .iter()
+            .filter(|other| !commands.iter().any(|command| command.name == other.name));
+        if prefix.is_empty() {
+            for command in stale {
+                log::warn!(
+                    "`{}` is registered but no longer defined; without a command prefix it isn't \
+                     deleted, so delete it by hand",
+                    command.name,
+                );
+            }
+            return diff;
+        }
+        diff.deleted = stale
+            .filter(|other| other.name.starts_with(prefix))
+            .collect();
+        diff
+    }
+}
+
+/// The outcome of syncing the registered commands with the defined ones.
+#[derive(Default)]
+pub struct SyncReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub deleted: Vec<String>,
+    pub failed: Vec<(String, anyhow::Error)>,
+}
+
+impl std::fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let groups = [
+            ("Created", &self.created),
+            ("Updated", &self.updated),
+            ("Unchanged", &self.unchanged),
+            ("Deleted", &self.deleted),
+        ];
+        let mut lines = groups
+            .iter()
+            .filter(|(_, names)| !names.is_empty())
+            .map(|(label, names)| format!("{label}: {}", names.join(", ")))
+            .collect::<Vec<_>>();
+        if !self.failed.is_empty() {
+            lines.push("Failed:".into());
+            lines.extend(
+                self.failed
+                    .iter()
+                    .map(|(name, error)| format!("- {name}: {error:#}")),
+            );
+        }
+        if lines.is_empty() {
+            lines.push("No commands defined".into());
+        }
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(name: &str) -> Command {
+        Command {
+            application_id: None,
+            default_permission: None,
+            description: "a command".into(),
+            guild_id: None,
+            id: None,
+            kind: CommandType::ChatInput,
+            name: name.into(),
+            options: Vec::new(),
+            version: Id::new(1),
+        }
+    }
+
+    fn names(commands: &[&Command]) -> Vec<String> {
+        commands
+            .iter()
+            .map(|command| command.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn only_deletes_commands_with_its_own_prefix() {
+        let commands = [command("dev-task"), command("dev-list")];
+        let registered = [
+            command("dev-task"),
+            command("dev-done"),
+            command("task"),
+            command("done"),
+            command("prod-task"),
+            command("prod-done"),
+        ];
+        let diff = CommandDiff::new(&commands, &registered, "dev-");
+        assert_eq!(names(&diff.unchanged), ["dev-task"]);
+        assert_eq!(names(&diff.created), ["dev-list"]);
+        assert_eq!(names(&diff.deleted), ["dev-done"]);
+    }
+
+    #[test]
+    fn deletes_nothing_without_a_prefix() {
+        let commands = [command("task")];
+        let registered = [command("task"), command("done"), command("dev-done")];
+        let diff = CommandDiff::new(&commands, &registered, "");
+        assert!(diff.deleted.is_empty());
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+use twilight_model::{
+    channel::{
+        embed::{Embed, EmbedField},
+        message::AllowedMentions,
+    },
+    datetime::Timestamp,
+    id::{marker::ChannelMarker, Id},
+};
+
+use crate::State;
+
+/// How often reported errors are posted, so that a burst of them ends up in a single message.
+const BATCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long after an error is posted that the same error isn't posted again.
+const DEDUP_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// The most errors posted at once, which is the most embeds Discord allows on a message. Any
+/// more are only counted.
+const MAX_BATCH: usize = 10;
+
+/// How long to wait for the webhook to respond.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest embed description Discord allows.
+const MAX_DESCRIPTION: usize = 4096;
+
+/// Where operational errors are posted.
+pub enum ReportTarget {
+    /// A channel the bot can post in.
+    Channel(Id<ChannelMarker>),
+    /// A webhook URL, e.g. for a channel in another server.
+    Webhook(String),
+}
+
+/// Posts operational errors, such as failed commands or scheduled backups, somewhere the owner
+/// of the bot will see them.
+///
+/// Errors are collected and posted in batches every [`BATCH_INTERVAL`] by [`run_periodic`].
+/// Repeats of an error are counted rather than posted again, and an error which was posted
+/// within the last [`DEDUP_WINDOW`] isn't posted again at all.
+pub struct ErrorReporter {
+    target: ReportTarget,
+    client: reqwest::Client,
+    pending: Mutex<Pending>,
+}
+
+#[derive(Default)]
+struct Pending {
+    reports: Vec<ErrorReport>,
+    /// When each error was last posted, by fingerprint.
+    posted_at: HashMap<String, Instant>,
+    /// How many errors didn't fit in the batch.
+    dropped: usize,
+}
+
+struct ErrorReport {
+    /// Identifies repeats of the same error: what failed, and the root cause.
+    fingerprint: String,
+    what: String,
+    /// The error and its causes, outermost first.
+    chain: Vec<String>,
+    context: Option<String>,
+    at: SystemTime,
+    count: usize,
+}
+
+/// The JSON body posted to a webhook.
+#[derive(Serialize)]
+struct WebhookMessage<'a> {
+    content: Option<String>,
+    embeds: &'a [Embed],
+    allowed_mentions: AllowedMentions,
+}
+
+impl ErrorReporter {
+    pub fn new(target: ReportTarget) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+        Ok(ErrorReporter {
+            target,
+            client,
+            pending: Mutex::new(Pending::default()),
+        })
+    }
+
+    /// Queues an error to be posted with the next batch.
+    ///
+    /// `what` says what failed, e.g. "scheduled backup failed", and `context` can describe
+    /// what the bot was doing at the time, such as the command being handled.
+    pub fn report(&self, what: &str, error: &anyhow::Error, context: Option<String>) {
+        let fingerprint = format!("{what}: {}", error.root_cause());
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending
+            .posted_at
+            .retain(|_, &mut at| now.duration_since(at) < DEDUP_WINDOW);
+        if pending.posted_at.contains_key(&fingerprint) {
+            log::debug!("not reporting an error which was reported recently: {fingerprint}");
+            return;
+        }
+        if let Some(report) = pending
+            .reports
+            .iter_mut()
+            .find(|report| report.fingerprint == fingerprint)
+        {
+            report.count += 1;
+            return;
+        }
+        if pending.reports.len() >= MAX_BATCH {
+            pending.dropped += 1;
+            return;
+        }
+        pending.reports.push(ErrorReport {
+            fingerprint,
+            what: what.into(),
+            chain: error.chain().map(ToString::to_string).collect(),
+            context,
+            at: SystemTime::now(),
+            count: 1,
+        });
+    }
+
+    /// Posts the errors reported since the last batch, if there are any.
+    ///
+    /// Failures are only logged, never reported, so that a broken target can't feed itself.
+    pub async fn flush(&self, state: &State) {
+        let (reports, dropped) = {
+            let mut pending = self.pending.lock().unwrap();
+            let now = Instant::now();
+            let reports = std::mem::take(&mut pending.reports);
+            for report in &reports {
+                pending.posted_at.insert(report.fingerprint.clone(), now);
+            }
+            (reports, std::mem::take(&mut pending.dropped))
+        };
+        if reports.is_empty() {
+            return;
+        }
+        let embeds = reports.iter().map(ErrorReport::embed).collect::<Vec<_>>();
+        let content = (dropped > 0).then(|| format!("{dropped} more errors weren't reported"));
+        let result = match &self.target {
+            ReportTarget::Channel(channel) => {
+                state
+                    .discord
+                    .create_message(*channel, content.as_deref(), &embeds, &[])
+                    .await
+            }
+            ReportTarget::Webhook(url) => self
+                .client
+                .post(url)
+                .json(&WebhookMessage {
+                    content,
+                    embeds: &embeds,
+                    allowed_mentions: AllowedMentions::default(),
+                })
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map(drop)
+                // The URL holds a secret, so it's left out of the message.
+                .map_err(|e| e.without_url().into()),
+        };
+        if let Err(e) = result {
+            log::warn!("failed to post {} error reports: {e:#}", reports.len());
+        }
+    }
+}
+
+impl ErrorReport {
+    fn embed(&self) -> Embed {
+        let mut description = String::new();
+        for (i, cause) in self.chain.iter().enumerate() {
+            let line = if i == 0 {
+                format!("{cause}\n")
+            } else {
+                format!("caused by: {cause}\n")
+            };
+            if description.len() + line.len() > MAX_DESCRIPTION {
+                break;
+            }
+            description.push_str(&line);
+        }
+        let mut fields = Vec::new();
+        if let Some(context) = &self.context {
+            fields.push(EmbedField {
+                inline: false,
+                name: "Context".into(),
+                value: context.clone(),
+            });
+        }
+        if self.count > 1 {
+            fields.push(EmbedField {
+                inline: true,
+                name: "Occurrences".into(),
+                value: self.count.to_string(),
+            });
+        }
+        let secs = self
+            .at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Embed {
+            author: None,
+            color: Some(0xed4245),
+            description: Some(description),
+            fields,
+            footer: None,
+            image: None,
+            kind: "rich".into(),
+            provider: None,
+            thumbnail: None,
+            timestamp: Timestamp::from_secs(secs as i64).ok(),
+            title: Some(self.what.clone()),
+            url: None,
+            video: None,
+        }
+    }
+}
+
+/// Posts reported errors every [`BATCH_INTERVAL`].
+pub async fn run_periodic(state: Arc<State>) {
+    let Some(errors) = &state.errors else {
+        return;
+    };
+    let mut interval = tokio::time::interval(BATCH_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        errors.flush(&state).await;
+    }
+}
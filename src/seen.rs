@@ -0,0 +1,89 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use twilight_model::id::{marker::InteractionMarker, Id};
+
+/// How long an interaction is remembered for.
+///
+/// Discord only accepts a response within 15 minutes of an interaction being created, so a
+/// redelivery after that couldn't be answered anyway.
+const TTL: Duration = Duration::from_secs(15 * 60);
+
+/// The most interactions remembered at once; beyond this, the oldest are forgotten early.
+const CAPACITY: usize = 10_000;
+
+/// The interactions received recently, so that duplicate deliveries of the same interaction can
+/// be dropped.
+#[derive(Default)]
+pub struct SeenInteractions {
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    ids: HashSet<Id<InteractionMarker>>,
+    /// The same interactions as `ids`, oldest first, along with when each was received.
+    order: VecDeque<(Id<InteractionMarker>, Instant)>,
+}
+
+impl SeenInteractions {
+    /// Records the interaction as seen.
+    ///
+    /// Returns `false` if it had already been seen within the TTL.
+    pub fn insert(&self, id: Id<InteractionMarker>) -> bool {
+        self.insert_at(id, Instant::now())
+    }
+
+    fn insert_at(&self, id: Id<InteractionMarker>, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        while let Some(&(oldest, seen_at)) = seen.order.front() {
+            if now.duration_since(seen_at) < TTL && seen.order.len() < CAPACITY {
+                break;
+            }
+            seen.order.pop_front();
+            seen.ids.remove(&oldest);
+        }
+        if !seen.ids.insert(id) {
+            return false;
+        }
+        seen.order.push_back((id, now));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_a_redelivery() {
+        let seen = SeenInteractions::default();
+        let now = Instant::now();
+        assert!(seen.insert_at(Id::new(1), now));
+        assert!(!seen.insert_at(Id::new(1), now + TTL / 2));
+        assert!(seen.insert_at(Id::new(2), now + TTL / 2));
+    }
+
+    #[test]
+    fn forgets_an_interaction_after_the_ttl() {
+        let seen = SeenInteractions::default();
+        let now = Instant::now();
+        seen.insert_at(Id::new(1), now);
+        assert!(!seen.insert_at(Id::new(1), now + TTL - Duration::from_secs(1)));
+        assert!(seen.insert_at(Id::new(1), now + TTL));
+    }
+
+    #[test]
+    fn forgets_the_oldest_interaction_beyond_the_capacity() {
+        let seen = SeenInteractions::default();
+        let now = Instant::now();
+        for id in 1..=CAPACITY as u64 {
+            assert!(seen.insert_at(Id::new(id), now));
+        }
+        assert!(seen.insert_at(Id::new(CAPACITY as u64 + 1), now));
+        assert!(seen.insert_at(Id::new(1), now));
+        assert!(!seen.insert_at(Id::new(CAPACITY as u64), now));
+        assert!(seen.seen.lock().unwrap().order.len() <= CAPACITY);
+    }
+}
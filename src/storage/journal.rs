@@ -0,0 +1,694 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use super::json::{backend, load_snapshot, save_snapshot, StoredTask};
+use super::{
+    add_usage, batch_added, digest_subscribers, plan_batch, remove_if_empty, sum_usage, two_lists,
+    AddTask, Data, DeletedUser, ListKey, Placement, Preferences, Stats, Storage, StorageError,
+    TransferMode, Usage, UsageCounts,
+};
+use crate::task::{same_task, Task};
+
+/// Persistent storage of every user's todo list, kept in memory and persisted as a snapshot plus
+/// a journal: a log of the changes made since the snapshot was taken.
+///
+/// Each change is appended to the log before it's acknowledged, and the log is periodically
+/// compacted into a new snapshot. The snapshot is in the same format as [`JsonFileStorage`]'s
+/// file, so the two backends can be switched between.
+///
+/// [`JsonFileStorage`]: super::JsonFileStorage
+pub struct JournalStorage {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    snapshot_path: PathBuf,
+    data: Data,
+    log: File,
+    /// The sequence number of the last event written to the log.
+    last_event: u64,
+    /// Whether events have been written since the log was last synced to disk.
+    unsynced: bool,
+    /// Whether to sync the log after every event, rather than periodically.
+    sync_every_write: bool,
+}
+
+/// A change to the lists, as recorded in the log.
+///
+/// The guilds are left out for global lists, as they were before lists could belong to a guild.
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Event {
+    Add {
+        user: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        guild: Option<Id<GuildMarker>>,
+        task: StoredTask,
+        /// Missing from logs written before tasks could be added to the top of a list.
+        #[serde(default)]
+        placement: Placement,
+    },
+    Complete {
+        user: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        guild: Option<Id<GuildMarker>>,
+        index: usize,
+    },
+    SetPinned {
+        user: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        guild: Option<Id<GuildMarker>>,
+        index: usize,
+        pinned: bool,
+    },
+    SetArchived {
+        user: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        guild: Option<Id<GuildMarker>>,
+        index: usize,
+        archived: bool,
+    },
+    Transfer {
+        from: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        from_guild: Option<Id<GuildMarker>>,
+        to: Id<UserMarker>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        to_guild: Option<Id<GuildMarker>>,
+        mode: TransferMode,
+    },
+    SetPreferences {
+        user: Id<UserMarker>,
+        preferences: Preferences,
+    },
+    DeleteUser {
+        user: Id<UserMarker>,
+    },
+    RecordUsage {
+        usage: Vec<LoggedUsage>,
+    },
+}
+
+/// One command's usage on one day, as logged.
+///
+/// [`UsageCounts`] can't be logged as it is, since a map keyed by numbers can't be read back
+/// from an event, which is flattened into its [`Record`].
+#[derive(Deserialize, Serialize)]
+struct LoggedUsage {
+    command: String,
+    day: i64,
+    usage: Usage,
+}
+
+/// A line of the log.
+#[derive(Deserialize, Serialize)]
+struct Record {
+    seq: u64,
+    #[serde(flatten)]
+    event: Event,
+}
+
+impl JournalStorage {
+    /// Loads the snapshot at `snapshot_path` and replays the log next to it.
+    ///
+    /// The log is synced to disk after every event if `fsync_interval` is `None`, and otherwise
+    /// at that interval. A new snapshot is taken every `snapshot_interval`.
+    pub async fn open(
+        snapshot_path: impl Into<PathBuf>,
+        fsync_interval: Option<Duration>,
+        snapshot_interval: Duration,
+    ) -> anyhow::Result<Self> {
+        let snapshot_path = snapshot_path.into();
+        let log_path = log_path(&snapshot_path);
+        let (mut data, mut last_event) = load_snapshot(&snapshot_path).await?;
+        let replayed = replay(&log_path, &mut data, &mut last_event)
+            .await
+            .with_context(|| format!("failed to replay `{}`", log_path.display()))?;
+        if replayed > 0 {
+            log::info!("replayed {replayed} events from `{}`", log_path.display());
+        }
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .await
+            .with_context(|| format!("failed to open `{}`", log_path.display()))?;
+        let mut inner = Inner {
+            snapshot_path,
+            data,
+            log,
+            last_event,
+            unsynced: false,
+            sync_every_write: fsync_interval.is_none(),
+        };
+        // Start from a fresh snapshot, so that the log doesn't grow across restarts.
+        inner.compact().await?;
+
+        let inner = Arc::new(Mutex::new(inner));
+        if let Some(interval) = fsync_interval {
+            spawn_periodic(Arc::downgrade(&inner), interval, Maintenance::Sync);
+        }
+        spawn_periodic(
+            Arc::downgrade(&inner),
+            snapshot_interval,
+            Maintenance::Compact,
+        );
+        Ok(JournalStorage { inner })
+    }
+}
+
+impl Inner {
+    /// Appends an event to the log.
+    async fn record(&mut self, event: Event) -> Result<(), StorageError> {
+        let record = Record {
+            seq: self.last_event + 1,
+            event,
+        };
+        let mut line = serde_json::to_vec(&record).map_err(backend)?;
+        line.push(b'\n');
+        self.log.write_all(&line).await.map_err(backend)?;
+        self.log.flush().await.map_err(backend)?;
+        self.last_event = record.seq;
+        self.unsynced = true;
+        if self.sync_every_write {
+            self.sync().await?;
+        }
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> Result<(), StorageError> {
+        if self.unsynced {
+            self.log.sync_data().await.map_err(backend)?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+
+    /// Writes a new snapshot and empties the log.
+    ///
+    /// The snapshot records the last event it includes, so if the log can't be emptied after it
+    /// is written, the events in it aren't applied twice.
+    async fn compact(&mut self) -> Result<(), StorageError> {
+        save_snapshot(&self.snapshot_path, &self.data, self.last_event).await?;
+        self.log.set_len(0).await.map_err(backend)?;
+        self.unsynced = false;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for JournalStorage {
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let tasks = inner.data.lists.get(&list).map_or(&[][..], Vec::as_slice);
+        if dedup {
+            if let Some(idx) = tasks
+                .iter()
+                .position(|existing| same_task(&existing.text, &task.text))
+            {
+                return Ok(AddTask::Duplicate(idx + 1));
+            }
+        }
+        if tasks.len() >= limit {
+            return Err(StorageError::ListFull(limit));
+        }
+        let event = Event::Add {
+            user: list.user,
+            guild: list.guild,
+            task: StoredTask::new(task),
+            placement,
+        };
+        inner.record(event).await?;
+        let tasks = inner.data.lists.entry(list).or_default();
+        Ok(AddTask::Added(placement.insert(tasks, task.clone())))
+    }
+
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        batch: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let tasks = inner.data.lists.get(&list).map_or(&[][..], Vec::as_slice);
+        let outcomes = plan_batch(tasks, batch, dedup, limit)?;
+        for task in batch_added(batch, &outcomes) {
+            let event = Event::Add {
+                user: list.user,
+                guild: list.guild,
+                task: StoredTask::new(task),
+                placement: Placement::Bottom,
+            };
+            inner.record(event).await?;
+            inner.data.lists.entry(list).or_default().push(task.clone());
+        }
+        Ok(outcomes)
+    }
+
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let len = inner.data.lists.get(&list).map_or(0, Vec::len);
+        if !(1..=len).contains(&index) {
+            return Err(StorageError::NoSuchTask(index));
+        }
+        let event = Event::Complete {
+            user: list.user,
+            guild: list.guild,
+            index,
+        };
+        inner.record(event).await?;
+        let tasks = inner.data.lists.entry(list).or_default();
+        let task = tasks.remove(index - 1);
+        remove_if_empty(&mut inner.data.lists, list);
+        Ok(task)
+    }
+
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let len = inner.data.lists.get(&list).map_or(0, Vec::len);
+        if !(1..=len).contains(&index) {
+            return Err(StorageError::NoSuchTask(index));
+        }
+        let event = Event::SetPinned {
+            user: list.user,
+            guild: list.guild,
+            index,
+            pinned,
+        };
+        inner.record(event).await?;
+        let task = &mut inner.data.lists.entry(list).or_default()[index - 1];
+        task.pinned = pinned;
+        Ok(task.clone())
+    }
+
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let len = inner.data.lists.get(&list).map_or(0, Vec::len);
+        if !(1..=len).contains(&index) {
+            return Err(StorageError::NoSuchTask(index));
+        }
+        let event = Event::SetArchived {
+            user: list.user,
+            guild: list.guild,
+            index,
+            archived,
+        };
+        inner.record(event).await?;
+        let task = &mut inner.data.lists.entry(list).or_default()[index - 1];
+        task.archived = archived;
+        Ok(task.clone())
+    }
+
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError> {
+        Ok(self
+            .inner
+            .lock()
+            .await
+            .data
+            .lists
+            .get(&list)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        Ok(Stats::from_lists(&self.inner.lock().await.data.lists))
+    }
+
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        Ok(digest_subscribers(
+            &self.inner.lock().await.data.preferences,
+        ))
+    }
+
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError> {
+        let mut inner = self.inner.lock().await;
+        let event = Event::Transfer {
+            from: from.user,
+            from_guild: from.guild,
+            to: to.user,
+            to_guild: to.guild,
+            mode,
+        };
+        inner.record(event).await?;
+        let (from_tasks, to_tasks) = two_lists(&mut inner.data.lists, from, to);
+        let count = mode.apply(from_tasks, to_tasks);
+        remove_if_empty(&mut inner.data.lists, from);
+        remove_if_empty(&mut inner.data.lists, to);
+        Ok(count)
+    }
+
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError> {
+        Ok(self
+            .inner
+            .lock()
+            .await
+            .data
+            .preferences
+            .get(&user)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError> {
+        let mut inner = self.inner.lock().await;
+        let preferences = preferences.clone();
+        inner
+            .record(Event::SetPreferences {
+                user,
+                preferences: preferences.clone(),
+            })
+            .await?;
+        inner.data.preferences.insert(user, preferences);
+        Ok(())
+    }
+
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError> {
+        let mut inner = self.inner.lock().await;
+        inner.record(Event::DeleteUser { user }).await?;
+        let deleted = DeletedUser::from_data(&mut inner.data, user);
+        // Compact straight away, so the deleted tasks don't linger in the log.
+        inner.compact().await?;
+        Ok(deleted)
+    }
+
+    async fn export_all(&self) -> Result<Data, StorageError> {
+        Ok(self.inner.lock().await.data.clone())
+    }
+
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError> {
+        let mut inner = self.inner.lock().await;
+        let logged = usage
+            .iter()
+            .flat_map(|(command, days)| {
+                days.iter().map(|(&day, &usage)| LoggedUsage {
+                    command: command.clone(),
+                    day,
+                    usage,
+                })
+            })
+            .collect();
+        inner.record(Event::RecordUsage { usage: logged }).await?;
+        add_usage(&mut inner.data.usage, usage);
+        Ok(())
+    }
+
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError> {
+        Ok(sum_usage(&self.inner.lock().await.data.usage, since))
+    }
+
+    async fn flush(&self) -> Result<(), StorageError> {
+        self.inner.lock().await.compact().await
+    }
+}
+
+/// Applies the events in the log after `last_event` to `data`, returning how many there were.
+///
+/// A final line which is cut short was being written when the bot stopped, and so was never
+/// acknowledged; it's ignored.
+async fn replay(log_path: &Path, data: &mut Data, last_event: &mut u64) -> anyhow::Result<usize> {
+    let file = match File::open(log_path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut lines = BufReader::new(file).lines();
+    let mut replayed = 0;
+    while let Some(line) = lines.next_line().await? {
+        let record = match serde_json::from_str::<Record>(&line) {
+            Ok(record) => record,
+            Err(e) if e.is_eof() => {
+                log::warn!(
+                    "ignoring incomplete final event in `{}`",
+                    log_path.display()
+                );
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if record.seq <= *last_event {
+            continue;
+        }
+        apply(data, record.event);
+        *last_event = record.seq;
+        replayed += 1;
+    }
+    Ok(replayed)
+}
+
+fn apply(data: &mut Data, event: Event) {
+    let lists = &mut data.lists;
+    match event {
+        Event::Add {
+            user,
+            guild,
+            task,
+            placement,
+        } => {
+            let tasks = lists.entry(ListKey { user, guild }).or_default();
+            placement.insert(tasks, task.into_task());
+        }
+        Event::Complete { user, guild, index } => {
+            let list = ListKey { user, guild };
+            if let Some(tasks) = lists.get_mut(&list) {
+                if (1..=tasks.len()).contains(&index) {
+                    tasks.remove(index - 1);
+                }
+            }
+            remove_if_empty(lists, list);
+        }
+        Event::SetPinned {
+            user,
+            guild,
+            index,
+            pinned,
+        } => {
+            let task = lists
+                .get_mut(&ListKey { user, guild })
+                .zip(index.checked_sub(1))
+                .and_then(|(tasks, idx)| tasks.get_mut(idx));
+            if let Some(task) = task {
+                task.pinned = pinned;
+            }
+        }
+        Event::SetArchived {
+            user,
+            guild,
+            index,
+            archived,
+        } => {
+            let task = lists
+                .get_mut(&ListKey { user, guild })
+                .zip(index.checked_sub(1))
+                .and_then(|(tasks, idx)| tasks.get_mut(idx));
+            if let Some(task) = task {
+                task.archived = archived;
+            }
+        }
+        Event::Transfer {
+            from,
+            from_guild,
+            to,
+            to_guild,
+            mode,
+        } => {
+            let from = ListKey {
+                user: from,
+                guild: from_guild,
+            };
+            let to = ListKey {
+                user: to,
+                guild: to_guild,
+            };
+            let (from_tasks, to_tasks) = two_lists(lists, from, to);
+            mode.apply(from_tasks, to_tasks);
+            remove_if_empty(lists, from);
+            remove_if_empty(lists, to);
+        }
+        Event::SetPreferences { user, preferences } => {
+            data.preferences.insert(user, preferences);
+        }
+        Event::DeleteUser { user } => {
+            DeletedUser::from_data(data, user);
+        }
+        Event::RecordUsage { usage } => {
+            for LoggedUsage {
+                command,
+                day,
+                usage,
+            } in usage
+            {
+                data.usage
+                    .entry(command)
+                    .or_default()
+                    .entry(day)
+                    .or_default()
+                    .add(&usage);
+            }
+        }
+    }
+}
+
+fn log_path(snapshot_path: &Path) -> PathBuf {
+    let mut path = snapshot_path.as_os_str().to_owned();
+    path.push(".log");
+    path.into()
+}
+
+/// Work done in the background on a timer.
+#[derive(Clone, Copy, Debug)]
+enum Maintenance {
+    /// Sync the log to disk.
+    Sync,
+    /// Take a new snapshot.
+    Compact,
+}
+
+/// Runs `task` every `period` for as long as the storage is alive.
+fn spawn_periodic(inner: Weak<Mutex<Inner>>, period: Duration, task: Maintenance) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let inner = match inner.upgrade() {
+                Some(inner) => inner,
+                None => break,
+            };
+            let mut inner = inner.lock().await;
+            let result = match task {
+                Maintenance::Sync => inner.sync().await,
+                Maintenance::Compact => inner.compact().await,
+            };
+            if let Err(e) = result {
+                log::error!("periodic storage maintenance ({task:?}) failed: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{task, temp_path};
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const LIMIT: usize = 100;
+
+    fn list() -> ListKey {
+        ListKey::global(Id::new(1))
+    }
+
+    async fn texts(storage: &JournalStorage) -> Vec<String> {
+        let tasks = storage.list_tasks(list()).await.unwrap();
+        tasks.into_iter().map(|task| task.text).collect()
+    }
+
+    #[tokio::test]
+    async fn recovers_the_snapshot_and_complete_events_after_a_crash() {
+        let path = temp_path("journal.json");
+        let storage = JournalStorage::open(&path, None, HOUR).await.unwrap();
+        for text in ["in the snapshot", "also in the snapshot"] {
+            storage
+                .add_task(list(), &task(text), Placement::Bottom, false, LIMIT)
+                .await
+                .unwrap();
+        }
+        drop(storage);
+        // Reopening compacts the log into the snapshot.
+        let storage = JournalStorage::open(&path, None, HOUR).await.unwrap();
+        for text in ["logged", "logged too", "logged last"] {
+            storage
+                .add_task(list(), &task(text), Placement::Bottom, false, LIMIT)
+                .await
+                .unwrap();
+        }
+        storage.complete_task(list(), 1).await.unwrap();
+        storage
+            .add_task(list(), &task("cut short"), Placement::Bottom, false, LIMIT)
+            .await
+            .unwrap();
+        drop(storage);
+
+        // Crash part-way through writing the last event.
+        let log = log_path(&path);
+        let mut contents = tokio::fs::read(&log).await.unwrap();
+        let last_line = contents[..contents.len() - 1]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .unwrap()
+            + 1;
+        contents.truncate(last_line + (contents.len() - last_line) / 2);
+        tokio::fs::write(&log, &contents).await.unwrap();
+
+        let storage = JournalStorage::open(&path, None, HOUR).await.unwrap();
+        assert_eq!(
+            texts(&storage).await,
+            [
+                "also in the snapshot",
+                "logged",
+                "logged too",
+                "logged last"
+            ],
+        );
+        drop(storage);
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::remove_file(&log).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletes_only_that_user() {
+        let path = temp_path("delete-user.json");
+        let storage = JournalStorage::open(&path, None, HOUR).await.unwrap();
+        super::super::tests::deletes_only_that_user(&storage, false).await;
+        drop(storage);
+        // The deletion is compacted into the snapshot straight away, so neither file has the
+        // user's tasks.
+        let log = log_path(&path);
+        let logged = tokio::fs::read_to_string(&log).await.unwrap();
+        assert!(!logged.contains("water the plants"));
+        let (data, _) = load_snapshot(&path).await.unwrap();
+        assert!(data.lists.keys().all(|list| list.user != Id::new(1)));
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::remove_file(&log).await.unwrap();
+    }
+}
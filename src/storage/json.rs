@@ -0,0 +1,563 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use super::{
+    add_usage, batch_added, digest_subscribers, from_millis, plan_batch, remove_if_empty,
+    sum_usage, to_millis, two_lists, AddTask, Data, DeletedUser, ListKey, Placement, Preferences,
+    Stats, Storage, StorageError, TransferMode, Usage, UsageCounts,
+};
+use crate::task::{same_task, ReactionEmoji, Task};
+
+/// The schema version of the first file format, which every file has recorded since.
+const FIRST_VERSION: u64 = 1;
+
+/// Upgrades to the file format, in order: `MIGRATIONS[n]` upgrades a file from schema version
+/// `FIRST_VERSION + n` to the next.
+///
+/// Whenever the format changes, add a migration to the end rather than changing an existing one.
+const MIGRATIONS: &[fn(Value) -> anyhow::Result<Value>] = &[add_guild_lists];
+
+/// The version of the file format written by this version of the bot.
+const SCHEMA_VERSION: u64 = FIRST_VERSION + MIGRATIONS.len() as u64;
+
+/// Persistent storage of every user's todo list, in a single JSON file which is rewritten on
+/// every change.
+pub struct JsonFileStorage {
+    path: PathBuf,
+    data: Mutex<Data>,
+}
+
+/// The contents of the file.
+#[derive(Deserialize, Serialize)]
+struct Snapshot {
+    schema_version: u64,
+    /// The sequence number of the last event from the log included in the snapshot, when it's
+    /// used by [`JournalStorage`](super::JournalStorage).
+    #[serde(default, skip_serializing_if = "is_zero")]
+    last_event: u64,
+    /// Each user's global list.
+    lists: BTreeMap<Id<UserMarker>, Vec<StoredTask>>,
+    /// Each user's list in each guild.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    guild_lists: BTreeMap<Id<GuildMarker>, BTreeMap<Id<UserMarker>, Vec<StoredTask>>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    preferences: BTreeMap<Id<UserMarker>, Preferences>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    usage: UsageCounts,
+}
+
+#[derive(Deserialize, Serialize)]
+pub(super) struct StoredTask {
+    text: String,
+    emoji: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image_url: Option<String>,
+    /// Milliseconds since the Unix epoch.
+    created_at: i64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    archived: bool,
+}
+
+impl JsonFileStorage {
+    /// Loads the lists from the file at `path`, starting empty if it doesn't exist yet.
+    ///
+    /// A file which can't be parsed is moved aside, so that it isn't overwritten.
+    pub async fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let (data, _) = load_snapshot(&path).await?;
+        Ok(JsonFileStorage {
+            path,
+            data: Mutex::new(data),
+        })
+    }
+
+    async fn save(&self, data: &Data) -> Result<(), StorageError> {
+        save_snapshot(&self.path, data, 0).await
+    }
+}
+
+/// Loads the data from the snapshot at `path`, along with the sequence number of the last logged
+/// event it includes, starting empty if it doesn't exist yet.
+///
+/// A file which can't be parsed is moved aside, so that it isn't overwritten. A file written by an
+/// older version of the bot is upgraded to the current format, keeping a copy of the original,
+/// while one written by a newer version is refused.
+pub(super) async fn load_snapshot(path: &Path) -> anyhow::Result<(Data, u64)> {
+    let contents = match tokio::fs::read(path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Data::default(), 0)),
+        Err(e) => return Err(e).context(format!("failed to read `{}`", path.display())),
+    };
+    let value = match serde_json::from_slice(&contents) {
+        Ok(value) => value,
+        Err(error) => return start_over(path, error).await,
+    };
+    let version =
+        schema_version(&value).with_context(|| format!("can't load `{}`", path.display()))?;
+    let value = migrate(value, version)
+        .with_context(|| format!("failed to upgrade `{}`", path.display()))?;
+    let snapshot = match serde_json::from_value::<Snapshot>(value) {
+        Ok(snapshot) => snapshot,
+        Err(error) => return start_over(path, error).await,
+    };
+    let last_event = snapshot.last_event;
+    let data = snapshot.data();
+    if version < SCHEMA_VERSION {
+        upgrade_file(path, version, &data, last_event).await?;
+    }
+    Ok((data, last_event))
+}
+
+/// Moves a corrupt file aside, and starts with no tasks.
+async fn start_over(path: &Path, error: serde_json::Error) -> anyhow::Result<(Data, u64)> {
+    let aside = move_aside(path).await?;
+    log::error!(
+        "`{}` is corrupt ({error}); moved it to `{}` and starting with no tasks",
+        path.display(),
+        aside.display(),
+    );
+    Ok((Data::default(), 0))
+}
+
+/// The schema version a file was written with.
+fn schema_version(value: &Value) -> anyhow::Result<u64> {
+    let version = value
+        .get("schema_version")
+        .context("it has no schema version")?;
+    let version = version
+        .as_u64()
+        .filter(|&version| version >= FIRST_VERSION)
+        .with_context(|| format!("invalid schema version {version}"))?;
+    if version > SCHEMA_VERSION {
+        anyhow::bail!(
+            "it has schema version {version}, but this version of the bot only supports up to \
+             {SCHEMA_VERSION}"
+        );
+    }
+    Ok(version)
+}
+
+/// Applies every migration from `version` onwards.
+fn migrate(value: Value, version: u64) -> anyhow::Result<Value> {
+    MIGRATIONS[(version - FIRST_VERSION) as usize..]
+        .iter()
+        .zip(version..)
+        .try_fold(value, |value, (migration, version)| {
+            migration(value).with_context(|| format!("from schema version {version}"))
+        })
+}
+
+/// Replaces a file written in an older format with the upgraded data, keeping a copy of the
+/// original alongside it.
+async fn upgrade_file(
+    path: &Path,
+    version: u64,
+    data: &Data,
+    last_event: u64,
+) -> anyhow::Result<()> {
+    let mut copy = path.as_os_str().to_owned();
+    copy.push(format!(".v{version}"));
+    let copy = PathBuf::from(copy);
+    tokio::fs::copy(path, &copy)
+        .await
+        .with_context(|| format!("failed to copy `{}` before upgrading it", path.display()))?;
+    save_snapshot(path, data, last_event).await?;
+    log::info!(
+        "upgraded `{}` from schema version {version} to {SCHEMA_VERSION}; the original is at `{}`",
+        path.display(),
+        copy.display(),
+    );
+    Ok(())
+}
+
+/// Version 2 added lists kept per guild, which a version 1 file has none of.
+///
+/// The version still has to be bumped, since an older bot would silently drop them.
+fn add_guild_lists(mut value: Value) -> anyhow::Result<Value> {
+    value["schema_version"] = 2.into();
+    Ok(value)
+}
+
+/// Writes the data to `path` in the format of [`JsonFileStorage`], which can then be restored from
+/// by pointing `json_path` at it.
+pub async fn export(path: &Path, data: &Data) -> Result<(), StorageError> {
+    save_snapshot(path, data, 0).await
+}
+
+/// Renders the data as indented JSON in the format of [`JsonFileStorage`], for reading.
+pub fn to_json(data: &Data) -> Result<Vec<u8>, StorageError> {
+    serde_json::to_vec_pretty(&Snapshot::new(data, 0)).map_err(backend)
+}
+
+/// Writes the data to the snapshot at `path`.
+///
+/// It's written to a temporary file which then replaces the old one, so a crash part way through
+/// can't leave a truncated file behind.
+pub(super) async fn save_snapshot(
+    path: &Path,
+    data: &Data,
+    last_event: u64,
+) -> Result<(), StorageError> {
+    let contents = serde_json::to_vec(&Snapshot::new(data, last_event)).map_err(backend)?;
+    let temp = temp_path(path);
+    let mut file = tokio::fs::File::create(&temp).await.map_err(backend)?;
+    file.write_all(&contents).await.map_err(backend)?;
+    file.sync_all().await.map_err(backend)?;
+    tokio::fs::rename(&temp, path).await.map_err(backend)
+}
+
+#[async_trait::async_trait]
+impl Storage for JsonFileStorage {
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError> {
+        let mut data = self.data.lock().await;
+        let tasks = data.lists.entry(list).or_default();
+        if dedup {
+            if let Some(idx) = tasks
+                .iter()
+                .position(|existing| same_task(&existing.text, &task.text))
+            {
+                return Ok(AddTask::Duplicate(idx + 1));
+            }
+        }
+        if tasks.len() >= limit {
+            return Err(StorageError::ListFull(limit));
+        }
+        let idx = placement.insert(tasks, task.clone());
+        self.save(&data).await?;
+        Ok(AddTask::Added(idx))
+    }
+
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        batch: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError> {
+        let mut data = self.data.lock().await;
+        let tasks = data.lists.entry(list).or_default();
+        let outcomes = plan_batch(tasks, batch, dedup, limit)?;
+        tasks.extend(batch_added(batch, &outcomes).cloned());
+        self.save(&data).await?;
+        Ok(outcomes)
+    }
+
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let mut data = self.data.lock().await;
+        let task = data
+            .lists
+            .get_mut(&list)
+            .zip(index.checked_sub(1))
+            .filter(|(tasks, idx)| *idx < tasks.len())
+            .map(|(tasks, idx)| tasks.remove(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        remove_if_empty(&mut data.lists, list);
+        self.save(&data).await?;
+        Ok(task)
+    }
+
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError> {
+        let mut data = self.data.lock().await;
+        let task = data
+            .lists
+            .get_mut(&list)
+            .zip(index.checked_sub(1))
+            .and_then(|(tasks, idx)| tasks.get_mut(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        task.pinned = pinned;
+        let task = task.clone();
+        self.save(&data).await?;
+        Ok(task)
+    }
+
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError> {
+        let mut data = self.data.lock().await;
+        let task = data
+            .lists
+            .get_mut(&list)
+            .zip(index.checked_sub(1))
+            .and_then(|(tasks, idx)| tasks.get_mut(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        task.archived = archived;
+        let task = task.clone();
+        self.save(&data).await?;
+        Ok(task)
+    }
+
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError> {
+        Ok(self
+            .data
+            .lock()
+            .await
+            .lists
+            .get(&list)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        Ok(Stats::from_lists(&self.data.lock().await.lists))
+    }
+
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        Ok(digest_subscribers(&self.data.lock().await.preferences))
+    }
+
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError> {
+        let mut data = self.data.lock().await;
+        let (from_tasks, to_tasks) = two_lists(&mut data.lists, from, to);
+        let count = mode.apply(from_tasks, to_tasks);
+        remove_if_empty(&mut data.lists, from);
+        remove_if_empty(&mut data.lists, to);
+        self.save(&data).await?;
+        Ok(count)
+    }
+
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError> {
+        Ok(self
+            .data
+            .lock()
+            .await
+            .preferences
+            .get(&user)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError> {
+        let mut data = self.data.lock().await;
+        data.preferences.insert(user, preferences.clone());
+        self.save(&data).await
+    }
+
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError> {
+        let mut data = self.data.lock().await;
+        let deleted = DeletedUser::from_data(&mut data, user);
+        self.save(&data).await?;
+        Ok(deleted)
+    }
+
+    async fn export_all(&self) -> Result<Data, StorageError> {
+        Ok(self.data.lock().await.clone())
+    }
+
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError> {
+        let mut data = self.data.lock().await;
+        add_usage(&mut data.usage, usage);
+        self.save(&data).await
+    }
+
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError> {
+        Ok(sum_usage(&self.data.lock().await.usage, since))
+    }
+}
+
+impl Snapshot {
+    fn new(data: &Data, last_event: u64) -> Self {
+        let mut lists = BTreeMap::new();
+        let mut guild_lists = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for (key, tasks) in data.lists.iter().filter(|(_, tasks)| !tasks.is_empty()) {
+            let tasks = tasks.iter().map(StoredTask::new).collect();
+            match key.guild {
+                None => lists.insert(key.user, tasks),
+                Some(guild) => guild_lists
+                    .entry(guild)
+                    .or_default()
+                    .insert(key.user, tasks),
+            };
+        }
+        Snapshot {
+            schema_version: SCHEMA_VERSION,
+            last_event,
+            lists,
+            guild_lists,
+            preferences: data.preferences.clone(),
+            usage: data.usage.clone(),
+        }
+    }
+
+    fn data(self) -> Data {
+        let global = self
+            .lists
+            .into_iter()
+            .map(|(user, tasks)| (ListKey::global(user), tasks));
+        let guilds = self.guild_lists.into_iter().flat_map(|(guild, lists)| {
+            lists.into_iter().map(move |(user, tasks)| {
+                let key = ListKey {
+                    user,
+                    guild: Some(guild),
+                };
+                (key, tasks)
+            })
+        });
+        Data {
+            // Empty lists were kept by older versions of the bot.
+            lists: global
+                .chain(guilds)
+                .filter(|(_, tasks)| !tasks.is_empty())
+                .map(|(key, tasks)| (key, tasks.into_iter().map(StoredTask::into_task).collect()))
+                .collect(),
+            preferences: self.preferences,
+            usage: self.usage,
+        }
+    }
+}
+
+impl StoredTask {
+    pub(super) fn new(task: &Task) -> Self {
+        StoredTask {
+            text: task.text.clone(),
+            emoji: task.emoji.as_ref().map(ToString::to_string),
+            image_url: task.image_url.clone(),
+            created_at: to_millis(task.created_at),
+            pinned: task.pinned,
+            archived: task.archived,
+        }
+    }
+
+    pub(super) fn into_task(self) -> Task {
+        Task {
+            text: self.text,
+            emoji: self.emoji.as_deref().and_then(ReactionEmoji::parse),
+            image_url: self.image_url,
+            created_at: from_millis(self.created_at),
+            pinned: self.pinned,
+            archived: self.archived,
+        }
+    }
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    temp.into()
+}
+
+/// Renames a corrupt file out of the way, returning its new path.
+async fn move_aside(path: &Path) -> anyhow::Result<PathBuf> {
+    let timestamp = to_millis(SystemTime::now());
+    let mut aside = path.as_os_str().to_owned();
+    aside.push(format!(".corrupt-{timestamp}"));
+    let aside = PathBuf::from(aside);
+    tokio::fs::rename(path, &aside)
+        .await
+        .with_context(|| format!("failed to move corrupt `{}` aside", path.display()))?;
+    Ok(aside)
+}
+
+pub(super) fn backend(error: impl std::error::Error + Send + Sync + 'static) -> StorageError {
+    StorageError::Backend(error.into())
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    /// A file written by the first version of the JSON backend, before lists were kept per guild.
+    const V1_FILE: &str = r#"{
+        "schema_version": 1,
+        "lists": {
+            "1": [{ "text": "water the plants", "emoji": null, "created_at": 1650000000000 }]
+        }
+    }"#;
+
+    #[tokio::test]
+    async fn upgrades_a_v1_file_and_keeps_the_original() {
+        let path = temp_path("v1.json");
+        tokio::fs::write(&path, V1_FILE).await.unwrap();
+
+        let (data, last_event) = load_snapshot(&path).await.unwrap();
+        let list = &data.lists[&ListKey::global(Id::new(1))];
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].text, "water the plants");
+        assert_eq!(last_event, 0);
+
+        let mut original = path.as_os_str().to_owned();
+        original.push(".v1");
+        let kept = tokio::fs::read_to_string(&original).await.unwrap();
+        assert_eq!(kept, V1_FILE);
+        let upgraded: Value =
+            serde_json::from_slice(&tokio::fs::read(&path).await.unwrap()).unwrap();
+        assert_eq!(upgraded["schema_version"], SCHEMA_VERSION);
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::remove_file(&original).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_a_file_without_a_schema_version() {
+        let path = temp_path("unversioned.json");
+        tokio::fs::write(&path, r#"{ "1": ["water the plants"] }"#)
+            .await
+            .unwrap();
+        assert!(load_snapshot(&path).await.is_err());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_a_file_from_a_newer_version() {
+        let path = temp_path("newer.json");
+        let newer = format!(
+            r#"{{ "schema_version": {}, "lists": {{}} }}"#,
+            SCHEMA_VERSION + 1
+        );
+        tokio::fs::write(&path, newer).await.unwrap();
+        assert!(load_snapshot(&path).await.is_err());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deletes_only_that_user() {
+        let path = temp_path("delete-user.json");
+        let storage = JsonFileStorage::open(&path).await.unwrap();
+        super::super::tests::deletes_only_that_user(&storage, false).await;
+        drop(storage);
+        let (data, _) = load_snapshot(&path).await.unwrap();
+        assert!(data.lists.keys().all(|list| list.user != Id::new(1)));
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use dashmap::DashMap;
+use twilight_model::id::{marker::UserMarker, Id};
+
+use super::{
+    add_usage, batch_added, plan_batch, sum_usage, AddTask, Data, DeletedUser, ListKey, Placement,
+    Preferences, Stats, Storage, StorageError, TransferMode, Usage, UsageCounts,
+};
+use crate::task::{same_task, Task};
+
+/// Storage which only lives as long as the process.
+///
+/// Lists are kept in a sharded map, so operations on different lists rarely contend. A shard's
+/// lock is held only for the duration of each (synchronous) operation on a list, and never while
+/// another list is locked, since two lists in the same shard would deadlock.
+#[derive(Default)]
+pub struct MemoryStorage {
+    db: DashMap<ListKey, Vec<Task>>,
+    preferences: DashMap<Id<UserMarker>, Preferences>,
+    usage: Mutex<UsageCounts>,
+}
+
+impl MemoryStorage {
+    /// Removes `list` if it's empty, so that users who've completed all of their tasks don't
+    /// keep an entry forever.
+    ///
+    /// The list must not be locked by the caller. It's checked again under the shard's lock, so
+    /// a task added to it in the meantime isn't lost.
+    fn remove_if_empty(&self, list: ListKey) {
+        self.db.remove_if(&list, |_, tasks| tasks.is_empty());
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for MemoryStorage {
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError> {
+        let mut tasks = self.db.entry(list).or_default();
+        if dedup {
+            if let Some(idx) = tasks
+                .iter()
+                .position(|existing| same_task(&existing.text, &task.text))
+            {
+                return Ok(AddTask::Duplicate(idx + 1));
+            }
+        }
+        if tasks.len() >= limit {
+            return Err(StorageError::ListFull(limit));
+        }
+        Ok(AddTask::Added(placement.insert(&mut tasks, task.clone())))
+    }
+
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        batch: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError> {
+        let mut tasks = self.db.entry(list).or_default();
+        let outcomes = plan_batch(&tasks, batch, dedup, limit)?;
+        tasks.extend(batch_added(batch, &outcomes).cloned());
+        Ok(outcomes)
+    }
+
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let mut tasks = self
+            .db
+            .get_mut(&list)
+            .ok_or(StorageError::NoSuchTask(index))?;
+        let task = index
+            .checked_sub(1)
+            .filter(|&idx| idx < tasks.len())
+            .map(|idx| tasks.remove(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        drop(tasks);
+        self.remove_if_empty(list);
+        Ok(task)
+    }
+
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError> {
+        let mut tasks = self
+            .db
+            .get_mut(&list)
+            .ok_or(StorageError::NoSuchTask(index))?;
+        let task = index
+            .checked_sub(1)
+            .and_then(|idx| tasks.get_mut(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        task.pinned = pinned;
+        Ok(task.clone())
+    }
+
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError> {
+        let mut tasks = self
+            .db
+            .get_mut(&list)
+            .ok_or(StorageError::NoSuchTask(index))?;
+        let task = index
+            .checked_sub(1)
+            .and_then(|idx| tasks.get_mut(idx))
+            .ok_or(StorageError::NoSuchTask(index))?;
+        task.archived = archived;
+        Ok(task.clone())
+    }
+
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError> {
+        Ok(self
+            .db
+            .get(&list)
+            .map(|tasks| tasks.clone())
+            .unwrap_or_default())
+    }
+
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        let mut tasks = 0;
+        let mut users = HashSet::new();
+        for entry in self.db.iter() {
+            let open = entry.value().iter().filter(|task| !task.archived).count();
+            if open > 0 {
+                tasks += open;
+                users.insert(entry.key().user);
+            }
+        }
+        Ok(Stats {
+            tasks,
+            users: users.len(),
+        })
+    }
+
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        Ok(self
+            .preferences
+            .iter()
+            .filter(|entry| entry.value().digest.is_some())
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect())
+    }
+
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError> {
+        // The lists can't both be locked at once without risking a deadlock, so the tasks are
+        // taken from one list and then added to the other.
+        let mut moved = match self.db.get_mut(&from) {
+            Some(mut tasks) => std::mem::take(&mut *tasks),
+            None => Vec::new(),
+        };
+        let count = mode.apply(&mut moved, &mut self.db.entry(to).or_default());
+        self.remove_if_empty(from);
+        self.remove_if_empty(to);
+        Ok(count)
+    }
+
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError> {
+        Ok(self
+            .preferences
+            .get(&user)
+            .map(|preferences| preferences.clone())
+            .unwrap_or_default())
+    }
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError> {
+        self.preferences.insert(user, preferences.clone());
+        Ok(())
+    }
+
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError> {
+        let mut deleted = DeletedUser::default();
+        self.db.retain(|key, tasks| {
+            if key.user == user {
+                deleted.tasks += tasks.len();
+            }
+            key.user != user
+        });
+        deleted.preferences = self.preferences.remove(&user).is_some();
+        Ok(deleted)
+    }
+
+    async fn export_all(&self) -> Result<Data, StorageError> {
+        Ok(Data {
+            lists: self
+                .db
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().clone()))
+                .collect(),
+            preferences: self
+                .preferences
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().clone()))
+                .collect(),
+            usage: self.usage.lock().unwrap().clone(),
+        })
+    }
+
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError> {
+        add_usage(&mut self.usage.lock().unwrap(), usage);
+        Ok(())
+    }
+
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError> {
+        Ok(sum_usage(&self.usage.lock().unwrap(), since))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_util::task;
+
+    fn list() -> ListKey {
+        ListKey::global(Id::new(1))
+    }
+
+    /// Runs `test` on a multi-threaded runtime, failing if it hasn't finished within ten seconds.
+    ///
+    /// A deadlock would block the runtime's workers, and with them any timer on the runtime, so
+    /// the deadline is kept by the test's own thread instead.
+    fn run_with_deadline<T: Send + 'static>(test: impl Future<Output = T> + Send + 'static) -> T {
+        let (done, finished) = mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(4)
+                .build()
+                .unwrap();
+            done.send(runtime.block_on(test)).unwrap();
+        });
+        finished
+            .recv_timeout(Duration::from_secs(10))
+            .expect("storage deadlocked")
+    }
+
+    #[test]
+    fn concurrent_adds_to_a_fresh_list_are_all_kept() {
+        const TASKS: usize = 500;
+        let storage = Arc::new(MemoryStorage::default());
+        let tasks = run_with_deadline(async move {
+            let adds = (0..TASKS)
+                .map(|n| {
+                    let storage = Arc::clone(&storage);
+                    tokio::spawn(async move {
+                        let task = task(&n.to_string());
+                        storage
+                            .add_task(list(), &task, Placement::Bottom, false, usize::MAX)
+                            .await
+                            .unwrap();
+                    })
+                })
+                .collect::<Vec<_>>();
+            for add in adds {
+                add.await.unwrap();
+            }
+            storage.list_tasks(list()).await.unwrap()
+        });
+        assert_eq!(tasks.len(), TASKS);
+    }
+
+    #[test]
+    fn concurrent_changes_to_lists_in_one_shard_lose_no_tasks() {
+        const USERS: u64 = 16;
+        const ROUNDS: usize = 200;
+        // With only two shards, most of the lists share a shard with several others.
+        let storage = Arc::new(MemoryStorage {
+            db: DashMap::with_shard_amount(2),
+            ..MemoryStorage::default()
+        });
+        let mut texts = run_with_deadline(async move {
+            let users = (1..=USERS)
+                .map(|user| {
+                    let storage = Arc::clone(&storage);
+                    tokio::spawn(async move {
+                        let list = ListKey::global(Id::new(user));
+                        let next = ListKey::global(Id::new(user % USERS + 1));
+                        let mut completed = Vec::new();
+                        for round in 0..ROUNDS {
+                            let task = task(&format!("{user}-{round}"));
+                            storage
+                                .add_task(list, &task, Placement::Bottom, false, usize::MAX)
+                                .await
+                                .unwrap();
+                            if round % 3 == 0 {
+                                // The list may have just been transferred away.
+                                if let Ok(task) = storage.complete_task(list, 1).await {
+                                    completed.push(task.text);
+                                }
+                            }
+                            if round % 5 == 0 {
+                                storage
+                                    .transfer_tasks(list, next, TransferMode::Append)
+                                    .await
+                                    .unwrap();
+                            }
+                        }
+                        completed
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut texts = Vec::new();
+            for user in users {
+                texts.extend(user.await.unwrap());
+            }
+            for user in 1..=USERS {
+                let tasks = storage
+                    .list_tasks(ListKey::global(Id::new(user)))
+                    .await
+                    .unwrap();
+                texts.extend(tasks.into_iter().map(|task| task.text));
+            }
+            texts
+        });
+        texts.sort();
+        let mut added = (1..=USERS)
+            .flat_map(|user| (0..ROUNDS).map(move |round| format!("{user}-{round}")))
+            .collect::<Vec<_>>();
+        added.sort();
+        assert_eq!(texts, added);
+    }
+
+    #[tokio::test]
+    async fn deletes_only_that_user() {
+        super::super::tests::deletes_only_that_user(&MemoryStorage::default(), false).await;
+    }
+
+    async fn texts(storage: &MemoryStorage) -> Vec<String> {
+        let tasks = storage.list_tasks(list()).await.unwrap();
+        tasks.into_iter().map(|task| task.text).collect()
+    }
+
+    #[tokio::test]
+    async fn adds_tasks_at_the_top_or_bottom() {
+        let storage = MemoryStorage::default();
+        for (text, placement) in [
+            ("second", Placement::Bottom),
+            ("third", Placement::Bottom),
+            ("first", Placement::Top),
+        ] {
+            storage
+                .add_task(list(), &task(text), placement, false, 10)
+                .await
+                .unwrap();
+        }
+        assert_eq!(texts(&storage).await, ["first", "second", "third"]);
+    }
+
+    #[tokio::test]
+    async fn finds_a_duplicate_instead_of_adding_it() {
+        let storage = MemoryStorage::default();
+        storage
+            .add_task(list(), &task("buy milk"), Placement::Bottom, true, 10)
+            .await
+            .unwrap();
+        let added = storage
+            .add_task(list(), &task("Buy milk"), Placement::Bottom, true, 10)
+            .await
+            .unwrap();
+        assert!(matches!(added, AddTask::Duplicate(1)));
+        assert_eq!(texts(&storage).await, ["buy milk"]);
+    }
+
+    #[tokio::test]
+    async fn adds_a_batch_only_if_all_of_it_fits() {
+        let storage = MemoryStorage::default();
+        let batch = [task("one"), task("two"), task("three")];
+        assert!(matches!(
+            storage.add_tasks(list(), &batch, false, 2).await,
+            Err(StorageError::ListFull(2))
+        ));
+        assert!(texts(&storage).await.is_empty());
+
+        let added = storage.add_tasks(list(), &batch, false, 3).await.unwrap();
+        assert!(matches!(
+            added[..],
+            [AddTask::Added(1), AddTask::Added(2), AddTask::Added(3)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn completing_the_last_task_forgets_the_list() {
+        let storage = MemoryStorage::default();
+        storage
+            .add_task(list(), &task("buy milk"), Placement::Bottom, false, 10)
+            .await
+            .unwrap();
+        assert!(matches!(
+            storage.complete_task(list(), 2).await,
+            Err(StorageError::NoSuchTask(2))
+        ));
+        let done = storage.complete_task(list(), 1).await.unwrap();
+        assert_eq!(done.text, "buy milk");
+        assert!(storage.db.is_empty());
+    }
+}
@@ -0,0 +1,823 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::config::Config;
+use crate::task::{same_task, ReactionEmoji, Task};
+
+mod journal;
+mod json;
+mod memory;
+mod postgres;
+mod sqlite;
+
+pub use self::journal::JournalStorage;
+pub use self::json::{export, to_json, JsonFileStorage};
+pub use self::memory::MemoryStorage;
+pub use self::postgres::PostgresStorage;
+pub use self::sqlite::SqliteStorage;
+
+/// Storage of every user's todo lists.
+#[async_trait::async_trait]
+pub trait Storage: Send + Sync {
+    /// Adds a task to the top or bottom of a list, according to `placement`.
+    ///
+    /// If `dedup` is set, the task isn't added if the same task is already on the list. Fails with
+    /// [`StorageError::ListFull`] if the list already has `limit` tasks.
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError>;
+
+    /// Adds a batch of tasks to the bottom of a list, in order, returning what became of each.
+    ///
+    /// If `dedup` is set, tasks already on the list, or earlier in the batch, aren't added. Either
+    /// every other task is added, or, if they wouldn't all fit in `limit` tasks, none are and this
+    /// fails with [`StorageError::ListFull`].
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        tasks: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError>;
+
+    /// Marks the task at the given (one-based) index of a list as completed, removing it from the
+    /// list.
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError>;
+
+    /// Removes the task at the given (one-based) index of a list without completing it, to undo
+    /// adding it.
+    ///
+    /// Only the database backends keep completed tasks, so for the others this is the same as
+    /// completing it.
+    async fn remove_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        self.complete_task(list, index).await
+    }
+
+    /// Puts a completed task back on a list at the given (one-based) index, or at the bottom if
+    /// the list has since become shorter, to undo completing it.
+    ///
+    /// Backends which keep completed tasks should override this to forget that the task was
+    /// completed, rather than keeping it both completed and on the list.
+    async fn restore_task(
+        &self,
+        list: ListKey,
+        index: usize,
+        task: &Task,
+        limit: usize,
+    ) -> Result<usize, StorageError> {
+        match self
+            .add_task(list, task, Placement::At(index), false, limit)
+            .await?
+        {
+            AddTask::Added(index) | AddTask::Duplicate(index) => Ok(index),
+        }
+    }
+
+    /// Pins or unpins the task at the given (one-based) index of a list, returning the task.
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError>;
+
+    /// Archives or restores the task at the given (one-based) index of a list, returning the
+    /// task.
+    ///
+    /// An archived task keeps its place on the list, but isn't shown by `/list`.
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError>;
+
+    /// The tasks on a list, in order.
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError>;
+
+    /// How many tasks are on a list, and how many have been completed from it.
+    ///
+    /// Only the database backends keep completed tasks; the others report no completed count.
+    async fn count_tasks(&self, list: ListKey) -> Result<TaskCount, StorageError> {
+        Ok(TaskCount {
+            open: self.list_tasks(list).await?.len(),
+            completed: None,
+        })
+    }
+
+    /// How many tasks there are across every list, for the bot's status.
+    ///
+    /// Archived tasks aren't counted. This reads everything by default, so backends should
+    /// override it with something cheaper.
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        Ok(Stats::from_lists(&self.export_all().await?.lists))
+    }
+
+    /// The preferences of every user who has asked for a daily digest.
+    ///
+    /// This reads everything by default, so backends should override it with something cheaper.
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        Ok(digest_subscribers(&self.export_all().await?.preferences))
+    }
+
+    /// Moves every task on the `from` list to the `to` list, returning how many were moved.
+    ///
+    /// `from` and `to` must be different lists.
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError>;
+
+    /// A user's preferences, or the defaults if they haven't set any.
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError>;
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError>;
+
+    /// Deletes everything stored about a user: every one of their lists, any completed tasks
+    /// kept from them, and their preferences.
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError>;
+
+    /// Everything stored, for every user.
+    async fn export_all(&self) -> Result<Data, StorageError>;
+
+    /// Everything stored about one user: each of their lists, and their preferences.
+    ///
+    /// This reads everything by default, so backends with a cheaper way should override it.
+    async fn export_user(&self, user: Id<UserMarker>) -> Result<Data, StorageError> {
+        let mut data = self.export_all().await?;
+        data.lists.retain(|key, _| key.user == user);
+        data.preferences.retain(|&key, _| key == user);
+        data.usage.clear();
+        Ok(data)
+    }
+
+    /// Adds to the usage counts of commands.
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError>;
+
+    /// How much each command has been used, summed over the days from `since` (in days since
+    /// the Unix epoch) onwards, or over all time.
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError>;
+
+    /// Checks that the backend is answering, for the readiness endpoint.
+    async fn ping(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+
+    /// Makes sure every change so far is durably stored, before the bot exits.
+    async fn flush(&self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+/// What to do with the tasks already on the list a list is transferred to.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferMode {
+    /// Keep them, adding the transferred tasks after them.
+    #[default]
+    Append,
+    /// Discard them.
+    Replace,
+}
+
+impl TransferMode {
+    /// Moves the tasks from one list to the other.
+    fn apply(self, from: &mut Vec<Task>, to: &mut Vec<Task>) -> usize {
+        if let TransferMode::Replace = self {
+            to.clear();
+        }
+        let count = from.len();
+        to.append(from);
+        count
+    }
+}
+
+/// Where on a list a new task goes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// After every task already on the list.
+    #[default]
+    Bottom,
+    /// Before every task already on the list.
+    Top,
+    /// At the given (one-based) index, or after every task if the list is shorter than that.
+    At(usize),
+}
+
+impl Placement {
+    /// Adds the task to the list, returning its (one-based) index.
+    fn insert(self, tasks: &mut Vec<Task>, task: Task) -> usize {
+        match self {
+            Placement::Bottom => {
+                tasks.push(task);
+                tasks.len()
+            }
+            Placement::Top => {
+                tasks.insert(0, task);
+                1
+            }
+            Placement::At(_) => {
+                let position = self.position(tasks.len());
+                tasks.insert(position, task);
+                position + 1
+            }
+        }
+    }
+
+    /// The (zero-based) position a task goes at on a list of `len` tasks.
+    fn position(self, len: usize) -> usize {
+        match self {
+            Placement::Bottom => len,
+            Placement::Top => 0,
+            Placement::At(index) => index.saturating_sub(1).min(len),
+        }
+    }
+}
+
+/// Identifies a todo list: either a user's global list, or their list in one guild.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ListKey {
+    pub user: Id<UserMarker>,
+    /// The guild the list belongs to, or `None` for the user's global list.
+    pub guild: Option<Id<GuildMarker>>,
+}
+
+impl ListKey {
+    /// A user's global list.
+    pub fn global(user: Id<UserMarker>) -> Self {
+        ListKey { user, guild: None }
+    }
+}
+
+/// Every todo list.
+pub type Lists = BTreeMap<ListKey, Vec<Task>>;
+
+/// Everything stored for every user.
+#[derive(Clone, Default)]
+pub struct Data {
+    pub lists: Lists,
+    pub preferences: BTreeMap<Id<UserMarker>, Preferences>,
+    /// How much the bot's commands have been used.
+    pub usage: UsageCounts,
+}
+
+/// How much each command was used on each day, by command name and then by day since the Unix
+/// epoch (in UTC).
+pub type UsageCounts = BTreeMap<String, BTreeMap<i64, Usage>>;
+
+/// How much a command was used.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Usage {
+    pub invocations: u64,
+    /// How many of the invocations failed.
+    pub errors: u64,
+    /// When the command was last used, in milliseconds since the Unix epoch.
+    pub last_used: i64,
+}
+
+impl Usage {
+    /// Adds `other`'s counts to these.
+    pub fn add(&mut self, other: &Usage) {
+        self.invocations += other.invocations;
+        self.errors += other.errors;
+        self.last_used = self.last_used.max(other.last_used);
+    }
+}
+
+/// Adds `usage` to `counts`.
+pub fn add_usage(counts: &mut UsageCounts, usage: &UsageCounts) {
+    for (command, days) in usage {
+        let counts = counts.entry(command.clone()).or_default();
+        for (&day, usage) in days {
+            counts.entry(day).or_default().add(usage);
+        }
+    }
+}
+
+/// Sums the counts the in-process backends keep, for [`Storage::usage`].
+fn sum_usage(counts: &UsageCounts, since: Option<i64>) -> BTreeMap<String, Usage> {
+    counts
+        .iter()
+        .filter_map(|(command, days)| {
+            let mut total = Usage::default();
+            for usage in days
+                .range(since.unwrap_or(i64::MIN)..)
+                .map(|(_, usage)| usage)
+            {
+                total.add(usage);
+            }
+            (total.invocations > 0).then(|| (command.clone(), total))
+        })
+        .collect()
+}
+
+/// Works out what becomes of each of a batch of tasks added to the bottom of a list, as
+/// [`Storage::add_tasks`] describes, without adding them.
+fn plan_batch(
+    existing: &[Task],
+    batch: &[Task],
+    dedup: bool,
+    limit: usize,
+) -> Result<Vec<AddTask>, StorageError> {
+    let mut texts = existing
+        .iter()
+        .map(|task| task.text.as_str())
+        .collect::<Vec<_>>();
+    let mut added = 0;
+    let outcomes = batch
+        .iter()
+        .map(|task| {
+            let duplicate = dedup
+                .then(|| texts.iter().position(|text| same_task(text, &task.text)))
+                .flatten();
+            match duplicate {
+                Some(idx) => AddTask::Duplicate(idx + 1),
+                None => {
+                    texts.push(&task.text);
+                    added += 1;
+                    AddTask::Added(texts.len())
+                }
+            }
+        })
+        .collect();
+    if added > 0 && texts.len() > limit {
+        return Err(StorageError::ListFull(limit));
+    }
+    Ok(outcomes)
+}
+
+/// The tasks of a batch which [`plan_batch`] found should be added.
+fn batch_added<'a>(
+    batch: &'a [Task],
+    outcomes: &'a [AddTask],
+) -> impl Iterator<Item = &'a Task> + 'a {
+    batch
+        .iter()
+        .zip(outcomes)
+        .filter(|(_, outcome)| matches!(outcome, AddTask::Added(_)))
+        .map(|(task, _)| task)
+}
+
+/// The users in `preferences` who have asked for a daily digest, with their preferences.
+fn digest_subscribers(
+    preferences: &BTreeMap<Id<UserMarker>, Preferences>,
+) -> Vec<(Id<UserMarker>, Preferences)> {
+    preferences
+        .iter()
+        .filter(|(_, preferences)| preferences.digest.is_some())
+        .map(|(&user, preferences)| (user, preferences.clone()))
+        .collect()
+}
+
+/// The day `time` falls on, in days since the Unix epoch (in UTC).
+pub fn day(time: SystemTime) -> i64 {
+    to_millis(time).div_euclid(24 * 60 * 60 * 1000)
+}
+
+/// A user's settings.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Preferences {
+    /// Where to send output which is only meant for the user.
+    pub delivery: Delivery,
+    /// Whether the user keeps a separate list in each guild.
+    pub scope: ListScope,
+    /// When to DM the user their open tasks each day, if at all.
+    pub digest: Option<Digest>,
+    /// The user's offset from UTC in minutes, which the time of their digest is in.
+    pub utc_offset: i32,
+}
+
+/// A user's daily digest of their open tasks.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Digest {
+    /// The time of day it's sent, in minutes after midnight in the user's timezone.
+    pub at: u16,
+    /// The locale it's written in: the user's, when they asked for it.
+    pub locale: String,
+}
+
+/// Where to send output which is only meant for the user who asked for it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    /// As an ephemeral reply in the channel the command was used in.
+    #[default]
+    Ephemeral,
+    /// As a direct message.
+    Dm,
+}
+
+impl Delivery {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Delivery::Ephemeral => "ephemeral",
+            Delivery::Dm => "dm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ephemeral" => Some(Delivery::Ephemeral),
+            "dm" => Some(Delivery::Dm),
+            _ => None,
+        }
+    }
+}
+
+/// Which list a user's commands act on when used in a guild.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListScope {
+    /// The same list everywhere.
+    #[default]
+    Global,
+    /// A separate list in each guild, with the global list used in DMs.
+    Guild,
+}
+
+impl ListScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListScope::Global => "global",
+            ListScope::Guild => "guild",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "global" => Some(ListScope::Global),
+            "guild" => Some(ListScope::Guild),
+            _ => None,
+        }
+    }
+
+    /// The list a user's commands in `guild` act on; `None` for DMs.
+    pub fn list(self, user: Id<UserMarker>, guild: Option<Id<GuildMarker>>) -> ListKey {
+        match self {
+            ListScope::Global => ListKey::global(user),
+            ListScope::Guild => ListKey { user, guild },
+        }
+    }
+}
+
+/// The counts of tasks returned by [`Storage::count_tasks`].
+#[derive(Debug)]
+pub struct TaskCount {
+    /// Tasks still on the list.
+    pub open: usize,
+    /// Tasks completed from the list, or `None` if the backend doesn't keep completed tasks.
+    pub completed: Option<usize>,
+}
+
+/// The totals returned by [`Storage::stats`].
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Tasks on every list.
+    pub tasks: usize,
+    /// Users with at least one task on one of their lists.
+    pub users: usize,
+}
+
+impl Stats {
+    /// Counts the tasks in the maps the in-process backends keep.
+    fn from_lists(lists: &Lists) -> Self {
+        let mut stats = Stats::default();
+        let mut last_user = None;
+        // The lists are sorted by user, so each user's lists are next to each other.
+        for (key, tasks) in lists {
+            let open = tasks.iter().filter(|task| !task.archived).count();
+            if open > 0 && last_user != Some(key.user) {
+                stats.users += 1;
+                last_user = Some(key.user);
+            }
+            stats.tasks += open;
+        }
+        stats
+    }
+}
+
+/// What was deleted by [`Storage::delete_user`].
+#[derive(Debug, Default)]
+pub struct DeletedUser {
+    /// Tasks still on one of the user's lists.
+    pub tasks: usize,
+    /// Completed tasks, which only the database backends keep.
+    pub completed: usize,
+    /// Whether the user had set any preferences.
+    pub preferences: bool,
+}
+
+impl DeletedUser {
+    /// Deletes a user's lists and preferences from the maps the in-process backends keep.
+    fn from_data(data: &mut Data, user: Id<UserMarker>) -> Self {
+        let mut deleted = DeletedUser::default();
+        data.lists.retain(|key, tasks| {
+            if key.user == user {
+                deleted.tasks += tasks.len();
+            }
+            key.user != user
+        });
+        deleted.preferences = data.preferences.remove(&user).is_some();
+        deleted
+    }
+}
+
+/// The result of adding a task to a list.
+pub enum AddTask {
+    /// The task was added at the given (one-based) index.
+    Added(usize),
+    /// The task was already on the list at the given (one-based) index, so it wasn't added.
+    Duplicate(usize),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("there is no task at index {0}")]
+    NoSuchTask(usize),
+    #[error("the list already has the maximum of {0} tasks")]
+    ListFull(usize),
+    #[error("storage backend failed")]
+    Backend(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Which implementation of [`Storage`] to use.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Keep everything in memory, losing it on restart.
+    Memory,
+    /// A JSON file, which needs no database.
+    Json,
+    /// A JSON snapshot plus a log of the changes made since, which is cheaper to write to than
+    /// rewriting the whole file.
+    Journal,
+    Sqlite,
+}
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "memory" => Ok(Backend::Memory),
+            "json" => Ok(Backend::Json),
+            "journal" => Ok(Backend::Journal),
+            "sqlite" => Ok(Backend::Sqlite),
+            _ => Err(UnknownBackend(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("unknown storage backend `{0}`")]
+pub struct UnknownBackend(String);
+
+/// Removes `list` if it's empty, so that users who've completed all of their tasks don't keep an
+/// entry forever.
+fn remove_if_empty(lists: &mut Lists, list: ListKey) {
+    if lists.get(&list).is_some_and(Vec::is_empty) {
+        lists.remove(&list);
+    }
+}
+
+/// Mutably borrows two different lists from `lists` at once.
+fn two_lists(lists: &mut Lists, a: ListKey, b: ListKey) -> (&mut Vec<Task>, &mut Vec<Task>) {
+    assert_ne!(a, b, "can't borrow the same list twice");
+    lists.entry(a).or_default();
+    lists.entry(b).or_default();
+    let mut both = lists
+        .iter_mut()
+        .filter(|(key, _)| **key == a || **key == b)
+        .map(|(key, tasks)| (*key, tasks));
+    let (first_key, first) = both.next().unwrap();
+    let (_, second) = both.next().unwrap();
+    if first_key == a {
+        (first, second)
+    } else {
+        (second, first)
+    }
+}
+
+/// Opens the storage backend selected by the configuration.
+///
+/// A configured database URL takes precedence over the selected backend.
+pub async fn open(config: &Config) -> anyhow::Result<Box<dyn Storage>> {
+    if let Some(url) = &config.database_url {
+        return Ok(Box::new(PostgresStorage::open(url).await?));
+    }
+    Ok(match config.storage {
+        Backend::Memory => Box::new(MemoryStorage::default()),
+        Backend::Json => Box::new(JsonFileStorage::open(&config.json_path).await?),
+        Backend::Journal => Box::new(
+            JournalStorage::open(
+                &config.json_path,
+                config.fsync_interval,
+                config.snapshot_interval,
+            )
+            .await?,
+        ),
+        Backend::Sqlite => Box::new(SqliteStorage::open(&config.db_path).await?),
+    })
+}
+
+impl From<sqlx::Error> for StorageError {
+    fn from(error: sqlx::Error) -> Self {
+        StorageError::Backend(error.into())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct TaskRow {
+    text: String,
+    emoji: Option<String>,
+    image_url: Option<String>,
+    created_at: i64,
+    pinned: bool,
+    archived: bool,
+}
+
+impl TaskRow {
+    fn into_task(self) -> Task {
+        Task {
+            text: self.text,
+            emoji: self.emoji.as_deref().and_then(ReactionEmoji::parse),
+            image_url: self.image_url,
+            created_at: from_millis(self.created_at),
+            pinned: self.pinned,
+            archived: self.archived,
+        }
+    }
+}
+
+/// A task along with the list it's on, for exporting every list.
+#[derive(sqlx::FromRow)]
+struct ExportRow {
+    user_id: i64,
+    guild_id: Option<i64>,
+    #[sqlx(flatten)]
+    task: TaskRow,
+}
+
+#[derive(sqlx::FromRow)]
+struct PreferencesRow {
+    delivery: String,
+    scope: String,
+    digest_at: Option<i32>,
+    digest_locale: Option<String>,
+    utc_offset: i32,
+}
+
+impl PreferencesRow {
+    fn new(preferences: &Preferences) -> Self {
+        PreferencesRow {
+            delivery: preferences.delivery.as_str().into(),
+            scope: preferences.scope.as_str().into(),
+            digest_at: preferences.digest.as_ref().map(|digest| digest.at.into()),
+            digest_locale: preferences
+                .digest
+                .as_ref()
+                .map(|digest| digest.locale.clone()),
+            utc_offset: preferences.utc_offset,
+        }
+    }
+
+    /// Reads preferences back from their columns, using the defaults for any unknown values.
+    fn into_preferences(self) -> Preferences {
+        let digest = match (self.digest_at, self.digest_locale) {
+            (Some(at), Some(locale)) => u16::try_from(at).ok().map(|at| Digest { at, locale }),
+            _ => None,
+        };
+        Preferences {
+            delivery: Delivery::from_name(&self.delivery).unwrap_or_default(),
+            scope: ListScope::from_name(&self.scope).unwrap_or_default(),
+            digest,
+            utc_offset: self.utc_offset,
+        }
+    }
+}
+
+/// A user's preferences, for exporting everyone's.
+#[derive(sqlx::FromRow)]
+struct UserPreferencesRow {
+    user_id: i64,
+    #[sqlx(flatten)]
+    preferences: PreferencesRow,
+}
+
+/// Snowflakes fit in 63 bits, so they can be stored in SQL's signed 64-bit integers.
+fn user_key(user: Id<UserMarker>) -> i64 {
+    user.get() as i64
+}
+
+fn guild_key(guild: Option<Id<GuildMarker>>) -> Option<i64> {
+    guild.map(|guild| guild.get() as i64)
+}
+
+fn user_from_key(key: i64) -> Option<Id<UserMarker>> {
+    Id::new_checked(key as u64)
+}
+
+/// Reads a list's key back from the `user_id` and `guild_id` columns.
+fn list_from_keys(user: i64, guild: Option<i64>) -> Option<ListKey> {
+    Some(ListKey {
+        user: user_from_key(user)?,
+        guild: guild.and_then(|guild| Id::new_checked(guild as u64)),
+    })
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::task;
+
+    /// Checks that deleting a user removes their global and guild lists and their preferences,
+    /// and nobody else's.
+    ///
+    /// `keeps_completed` is whether the backend keeps completed tasks, which are then deleted
+    /// too.
+    pub(super) async fn deletes_only_that_user(storage: &dyn Storage, keeps_completed: bool) {
+        let (user, other) = (Id::new(1), Id::new(2));
+        let guild = ListKey {
+            user,
+            guild: Some(Id::new(10)),
+        };
+        let lists = [
+            (ListKey::global(user), "done"),
+            (ListKey::global(user), "water the plants"),
+            (guild, "buy milk"),
+            (ListKey::global(other), "call mum"),
+        ];
+        for (list, text) in lists {
+            storage
+                .add_task(list, &task(text), Placement::Bottom, false, 10)
+                .await
+                .unwrap();
+        }
+        storage
+            .complete_task(ListKey::global(user), 1)
+            .await
+            .unwrap();
+        let preferences = Preferences {
+            scope: ListScope::Guild,
+            ..Preferences::default()
+        };
+        for user in [user, other] {
+            storage.set_preferences(user, &preferences).await.unwrap();
+        }
+
+        let deleted = storage.delete_user(user).await.unwrap();
+        assert_eq!(deleted.tasks, 2);
+        assert_eq!(deleted.completed, usize::from(keeps_completed));
+        assert!(deleted.preferences);
+
+        let data = storage.export_all().await.unwrap();
+        let lists = data
+            .lists
+            .iter()
+            .map(|(list, tasks)| (list.user, tasks.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(lists, [(other, 1)]);
+        assert_eq!(data.preferences.keys().collect::<Vec<_>>(), [&other]);
+        assert_eq!(
+            storage.preferences(user).await.unwrap().scope,
+            ListScope::Global
+        );
+
+        let again = storage.delete_user(user).await.unwrap();
+        assert_eq!(
+            (again.tasks, again.completed, again.preferences),
+            (0, 0, false)
+        );
+    }
+}
@@ -0,0 +1,633 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::{Postgres, Transaction};
+use twilight_model::id::{marker::UserMarker, Id};
+
+use super::{
+    guild_key, list_from_keys, to_millis, user_from_key, user_key, AddTask, Data, DeletedUser,
+    ExportRow, ListKey, Placement, Preferences, PreferencesRow, Stats, Storage, StorageError,
+    TaskCount, TaskRow, TransferMode, Usage, UsageCounts, UserPreferencesRow,
+};
+use crate::task::{same_task, Task};
+
+/// How long to wait for a connection before giving up, including at startup.
+const ACQUIRE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Persistent storage of every user's todo list, in a Postgres database which may be shared by
+/// several instances of the bot.
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    /// Connects to the database at `url` and applies any pending migrations.
+    pub async fn open(url: &str) -> anyhow::Result<Self> {
+        // The URL may contain a password, so it's left out of the error.
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(ACQUIRE_TIMEOUT)
+            .connect(url)
+            .await
+            .context("failed to connect to the Postgres database")?;
+        sqlx::migrate!("migrations/postgres")
+            .run(&pool)
+            .await
+            .context("failed to migrate the Postgres database")?;
+        Ok(PostgresStorage { pool })
+    }
+}
+
+/// Locks a list for the rest of the transaction, returning the text of each active task.
+///
+/// Operations refer to tasks by their position, so another instance changing the list between
+/// reading and writing it could make an operation act on the wrong task, or complete the same
+/// task twice.
+async fn lock_list(
+    tx: &mut Transaction<'_, Postgres>,
+    list: ListKey,
+) -> Result<Vec<String>, StorageError> {
+    // Row locks can't stop a task being added to an empty list, so the list as a whole is
+    // locked as well. The lock covers all of the user's lists, which are rarely used at once.
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(user_key(list.user))
+        .execute(&mut **tx)
+        .await?;
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT text FROM tasks \
+         WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position IS NOT NULL \
+         ORDER BY position FOR UPDATE",
+    )
+    .bind(user_key(list.user))
+    .bind(guild_key(list.guild))
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows.into_iter().map(|(text,)| text).collect())
+}
+
+/// Adds a task to a list as part of a transaction, as [`Storage::add_task`] does, locking the
+/// list first.
+async fn insert_task(
+    tx: &mut Transaction<'_, Postgres>,
+    list: ListKey,
+    task: &Task,
+    placement: Placement,
+    dedup: bool,
+    limit: usize,
+) -> Result<AddTask, StorageError> {
+    let existing = lock_list(tx, list).await?;
+    if dedup {
+        if let Some(idx) = existing.iter().position(|text| same_task(text, &task.text)) {
+            return Ok(AddTask::Duplicate(idx + 1));
+        }
+    }
+    if existing.len() >= limit {
+        return Err(StorageError::ListFull(limit));
+    }
+    let position = placement.position(existing.len());
+    if position < existing.len() {
+        sqlx::query(
+            "UPDATE tasks SET position = position + 1 \
+             WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position >= $3",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(position as i64)
+        .execute(&mut **tx)
+        .await?;
+    }
+    sqlx::query(
+        "INSERT INTO tasks \
+         (user_id, guild_id, position, text, emoji, image_url, created_at, pinned, archived) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(user_key(list.user))
+    .bind(guild_key(list.guild))
+    .bind(position as i64)
+    .bind(&task.text)
+    .bind(task.emoji.as_ref().map(ToString::to_string))
+    .bind(&task.image_url)
+    .bind(to_millis(task.created_at))
+    .bind(task.pinned)
+    .bind(task.archived)
+    .execute(&mut **tx)
+    .await?;
+    Ok(AddTask::Added(position + 1))
+}
+
+#[async_trait::async_trait]
+impl Storage for PostgresStorage {
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let added = insert_task(&mut tx, list, task, placement, dedup, limit).await?;
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        batch: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let mut outcomes = Vec::with_capacity(batch.len());
+        for task in batch {
+            // Failing drops the transaction, rolling back the tasks already inserted.
+            outcomes.push(insert_task(&mut tx, list, task, Placement::Bottom, dedup, limit).await?);
+        }
+        tx.commit().await?;
+        Ok(outcomes)
+    }
+
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let (user, guild) = (user_key(list.user), guild_key(list.guild));
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let mut tx = self.pool.begin().await?;
+        lock_list(&mut tx, list).await?;
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET position = NULL, completed_at = $1 \
+             WHERE user_id = $2 AND guild_id IS NOT DISTINCT FROM $3 AND position = $4 \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(to_millis(SystemTime::now()))
+        .bind(user)
+        .bind(guild)
+        .bind(position)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if row.is_some() {
+            sqlx::query(
+                "UPDATE tasks SET position = position - 1 \
+                 WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position > $3",
+            )
+            .bind(user)
+            .bind(guild)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn remove_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let (user, guild) = (user_key(list.user), guild_key(list.guild));
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let mut tx = self.pool.begin().await?;
+        lock_list(&mut tx, list).await?;
+        let row: Option<TaskRow> = sqlx::query_as(
+            "DELETE FROM tasks \
+             WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position = $3 \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(user)
+        .bind(guild)
+        .bind(position)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if row.is_some() {
+            sqlx::query(
+                "UPDATE tasks SET position = position - 1 \
+                 WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position > $3",
+            )
+            .bind(user)
+            .bind(guild)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn restore_task(
+        &self,
+        list: ListKey,
+        index: usize,
+        task: &Task,
+        limit: usize,
+    ) -> Result<usize, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let added = insert_task(&mut tx, list, task, Placement::At(index), false, limit).await?;
+        // The task is back on the list as a new row, so the row it was completed as goes.
+        sqlx::query(
+            "DELETE FROM tasks WHERE id = (\
+                 SELECT id FROM tasks \
+                 WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position IS NULL \
+                 AND text = $3 AND created_at = $4 \
+                 ORDER BY completed_at DESC LIMIT 1\
+             )",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(&task.text)
+        .bind(to_millis(task.created_at))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        match added {
+            AddTask::Added(index) | AddTask::Duplicate(index) => Ok(index),
+        }
+    }
+
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError> {
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET pinned = $1 \
+             WHERE user_id = $2 AND guild_id IS NOT DISTINCT FROM $3 AND position = $4 \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(pinned)
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(position)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError> {
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET archived = $1 \
+             WHERE user_id = $2 AND guild_id IS NOT DISTINCT FROM $3 AND position = $4 \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(archived)
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(position)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError> {
+        let rows: Vec<TaskRow> = sqlx::query_as(
+            "SELECT text, emoji, image_url, created_at, pinned, archived FROM tasks \
+             WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position IS NOT NULL \
+             ORDER BY position",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(TaskRow::into_task).collect())
+    }
+
+    async fn count_tasks(&self, list: ListKey) -> Result<TaskCount, StorageError> {
+        let (open, completed): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(position), COUNT(*) - COUNT(position) FROM tasks \
+             WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(TaskCount {
+            open: open as usize,
+            completed: Some(completed as usize),
+        })
+    }
+
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        let (tasks, users): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COUNT(DISTINCT user_id) FROM tasks \
+             WHERE position IS NOT NULL AND NOT archived",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Stats {
+            tasks: tasks as usize,
+            users: users as usize,
+        })
+    }
+
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        // Both lists are locked in order of user id, so that two transfers in opposite directions
+        // can't each hold one lock while waiting for the other.
+        let (first, second) = if from < to { (from, to) } else { (to, from) };
+        let first = lock_list(&mut tx, first).await?;
+        let second = lock_list(&mut tx, second).await?;
+        let existing = if from < to { second.len() } else { first.len() };
+        let (from_user, from_guild) = (user_key(from.user), guild_key(from.guild));
+        let (to_user, to_guild) = (user_key(to.user), guild_key(to.guild));
+        if let TransferMode::Replace = mode {
+            sqlx::query(
+                "DELETE FROM tasks \
+                 WHERE user_id = $1 AND guild_id IS NOT DISTINCT FROM $2 AND position IS NOT NULL",
+            )
+            .bind(to_user)
+            .bind(to_guild)
+            .execute(&mut *tx)
+            .await?;
+        }
+        let offset = match mode {
+            TransferMode::Append => existing as i64,
+            TransferMode::Replace => 0,
+        };
+        let moved = sqlx::query(
+            "UPDATE tasks SET user_id = $1, guild_id = $2, position = position + $3 \
+             WHERE user_id = $4 AND guild_id IS NOT DISTINCT FROM $5 AND position IS NOT NULL",
+        )
+        .bind(to_user)
+        .bind(to_guild)
+        .bind(offset)
+        .bind(from_user)
+        .bind(from_guild)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(moved.rows_affected() as usize)
+    }
+
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError> {
+        let row: Option<PreferencesRow> = sqlx::query_as(
+            "SELECT delivery, scope, digest_at, digest_locale, utc_offset FROM preferences \
+             WHERE user_id = $1",
+        )
+        .bind(user_key(user))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row
+            .map(PreferencesRow::into_preferences)
+            .unwrap_or_default())
+    }
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError> {
+        let row = PreferencesRow::new(preferences);
+        sqlx::query(
+            "INSERT INTO preferences \
+             (user_id, delivery, scope, digest_at, digest_locale, utc_offset) \
+             VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (user_id) DO UPDATE \
+             SET delivery = excluded.delivery, scope = excluded.scope, \
+             digest_at = excluded.digest_at, digest_locale = excluded.digest_locale, \
+             utc_offset = excluded.utc_offset",
+        )
+        .bind(user_key(user))
+        .bind(row.delivery)
+        .bind(row.scope)
+        .bind(row.digest_at)
+        .bind(row.digest_locale)
+        .bind(row.utc_offset)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences WHERE digest_at IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let user = user_from_key(row.user_id)?;
+                Some((user, row.preferences.into_preferences()))
+            })
+            .collect())
+    }
+
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError> {
+        let user = user_key(user);
+        let mut tx = self.pool.begin().await?;
+        // Takes the same lock as `lock_list`, covering every one of the user's lists.
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+        let (tasks, completed): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(position), COUNT(*) - COUNT(position) FROM tasks WHERE user_id = $1",
+        )
+        .bind(user)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM tasks WHERE user_id = $1")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+        let preferences = sqlx::query("DELETE FROM preferences WHERE user_id = $1")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(DeletedUser {
+            tasks: tasks as usize,
+            completed: completed as usize,
+            preferences: preferences.rows_affected() > 0,
+        })
+    }
+
+    async fn export_all(&self) -> Result<Data, StorageError> {
+        let mut data = Data::default();
+        let rows: Vec<ExportRow> = sqlx::query_as(
+            "SELECT user_id, guild_id, text, emoji, image_url, created_at, pinned, archived \
+             FROM tasks WHERE position IS NOT NULL ORDER BY user_id, guild_id, position",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(list) = list_from_keys(row.user_id, row.guild_id) {
+                data.lists
+                    .entry(list)
+                    .or_default()
+                    .push(row.task.into_task());
+            }
+        }
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(user) = user_from_key(row.user_id) {
+                data.preferences
+                    .insert(user, row.preferences.into_preferences());
+            }
+        }
+        let rows: Vec<(String, i64, i64, i64, i64)> = sqlx::query_as(
+            "SELECT command, day, invocations, errors, last_used FROM command_usage",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for (command, day, invocations, errors, last_used) in rows {
+            data.usage.entry(command).or_default().insert(
+                day,
+                Usage {
+                    invocations: invocations as u64,
+                    errors: errors as u64,
+                    last_used,
+                },
+            );
+        }
+        Ok(data)
+    }
+
+    async fn export_user(&self, user: Id<UserMarker>) -> Result<Data, StorageError> {
+        let mut data = Data::default();
+        let rows: Vec<ExportRow> = sqlx::query_as(
+            "SELECT user_id, guild_id, text, emoji, image_url, created_at, pinned, archived \
+             FROM tasks WHERE user_id = $1 AND position IS NOT NULL ORDER BY guild_id, position",
+        )
+        .bind(user_key(user))
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(list) = list_from_keys(row.user_id, row.guild_id) {
+                data.lists
+                    .entry(list)
+                    .or_default()
+                    .push(row.task.into_task());
+            }
+        }
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences WHERE user_id = $1",
+        )
+        .bind(user_key(user))
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(user) = user_from_key(row.user_id) {
+                data.preferences
+                    .insert(user, row.preferences.into_preferences());
+            }
+        }
+        Ok(data)
+    }
+
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+        for (command, days) in usage {
+            for (&day, usage) in days {
+                sqlx::query(
+                    "INSERT INTO command_usage (command, day, invocations, errors, last_used) \
+                     VALUES ($1, $2, $3, $4, $5) \
+                     ON CONFLICT (command, day) DO UPDATE \
+                     SET invocations = command_usage.invocations + excluded.invocations, \
+                     errors = command_usage.errors + excluded.errors, \
+                     last_used = GREATEST(command_usage.last_used, excluded.last_used)",
+                )
+                .bind(command)
+                .bind(day)
+                .bind(usage.invocations as i64)
+                .bind(usage.errors as i64)
+                .bind(usage.last_used)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError> {
+        let rows: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+            "SELECT command, SUM(invocations)::BIGINT, SUM(errors)::BIGINT, MAX(last_used) \
+             FROM command_usage WHERE day >= $1 GROUP BY command",
+        )
+        .bind(since.unwrap_or(i64::MIN))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(command, invocations, errors, last_used)| {
+                let usage = Usage {
+                    invocations: invocations as u64,
+                    errors: errors as u64,
+                    last_used,
+                };
+                (command, usage)
+            })
+            .collect())
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), StorageError> {
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The database the tests use, which they skip without. Users 1 and 2 in it are overwritten.
+    const DATABASE_URL: &str = "TODO_BOT_TEST_DATABASE_URL";
+
+    async fn open() -> Option<PostgresStorage> {
+        match std::env::var(DATABASE_URL) {
+            Ok(url) => Some(PostgresStorage::open(&url).await.unwrap()),
+            Err(_) => {
+                eprintln!("skipping, since `{DATABASE_URL}` isn't set");
+                None
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_only_that_user() {
+        let Some(storage) = open().await else { return };
+        for user in [1, 2] {
+            storage.delete_user(Id::new(user)).await.unwrap();
+        }
+        super::super::tests::deletes_only_that_user(&storage, true).await;
+        storage.delete_user(Id::new(2)).await.unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::Transaction;
+use twilight_model::id::{marker::UserMarker, Id};
+
+use super::{
+    guild_key, list_from_keys, to_millis, user_from_key, user_key, AddTask, Data, DeletedUser,
+    ExportRow, ListKey, Placement, Preferences, PreferencesRow, Stats, Storage, StorageError,
+    TaskCount, TaskRow, TransferMode, Usage, UsageCounts, UserPreferencesRow,
+};
+use crate::task::{same_task, Task};
+
+/// Persistent storage of every user's todo list, in an SQLite database.
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if needed, and applies any pending migrations.
+    pub async fn open(path: &str) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        // Every operation on a list reads and then writes it, which SQLite can't do concurrently
+        // on separate connections without failing one of the writes, so operations are run one at
+        // a time over a single connection instead.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        sqlx::migrate!("migrations/sqlite").run(&pool).await?;
+        Ok(SqliteStorage { pool })
+    }
+}
+
+/// Adds a task to a list as part of a transaction, as [`Storage::add_task`] does.
+async fn insert_task(
+    tx: &mut Transaction<'_, Sqlite>,
+    list: ListKey,
+    task: &Task,
+    placement: Placement,
+    dedup: bool,
+    limit: usize,
+) -> Result<AddTask, StorageError> {
+    let (user, guild) = (user_key(list.user), guild_key(list.guild));
+    let existing: Vec<(String,)> = sqlx::query_as(
+        "SELECT text FROM tasks WHERE user_id = ? AND guild_id IS ? AND position IS NOT NULL \
+         ORDER BY position",
+    )
+    .bind(user)
+    .bind(guild)
+    .fetch_all(&mut **tx)
+    .await?;
+    if dedup {
+        if let Some(idx) = existing
+            .iter()
+            .position(|(text,)| same_task(text, &task.text))
+        {
+            return Ok(AddTask::Duplicate(idx + 1));
+        }
+    }
+    if existing.len() >= limit {
+        return Err(StorageError::ListFull(limit));
+    }
+    let position = placement.position(existing.len());
+    if position < existing.len() {
+        sqlx::query(
+            "UPDATE tasks SET position = position + 1 \
+             WHERE user_id = ? AND guild_id IS ? AND position >= ?",
+        )
+        .bind(user)
+        .bind(guild)
+        .bind(position as i64)
+        .execute(&mut **tx)
+        .await?;
+    }
+    sqlx::query(
+        "INSERT INTO tasks \
+         (user_id, guild_id, position, text, emoji, image_url, created_at, pinned, archived) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(user)
+    .bind(guild)
+    .bind(position as i64)
+    .bind(&task.text)
+    .bind(task.emoji.as_ref().map(ToString::to_string))
+    .bind(&task.image_url)
+    .bind(to_millis(task.created_at))
+    .bind(task.pinned)
+    .bind(task.archived)
+    .execute(&mut **tx)
+    .await?;
+    Ok(AddTask::Added(position + 1))
+}
+
+#[async_trait::async_trait]
+impl Storage for SqliteStorage {
+    async fn add_task(
+        &self,
+        list: ListKey,
+        task: &Task,
+        placement: Placement,
+        dedup: bool,
+        limit: usize,
+    ) -> Result<AddTask, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let added = insert_task(&mut tx, list, task, placement, dedup, limit).await?;
+        tx.commit().await?;
+        Ok(added)
+    }
+
+    async fn add_tasks(
+        &self,
+        list: ListKey,
+        batch: &[Task],
+        dedup: bool,
+        limit: usize,
+    ) -> Result<Vec<AddTask>, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let mut outcomes = Vec::with_capacity(batch.len());
+        for task in batch {
+            // Failing drops the transaction, rolling back the tasks already inserted.
+            outcomes.push(insert_task(&mut tx, list, task, Placement::Bottom, dedup, limit).await?);
+        }
+        tx.commit().await?;
+        Ok(outcomes)
+    }
+
+    async fn complete_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let (user, guild) = (user_key(list.user), guild_key(list.guild));
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let mut tx = self.pool.begin().await?;
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET position = NULL, completed_at = ? \
+             WHERE user_id = ? AND guild_id IS ? AND position = ? \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(to_millis(SystemTime::now()))
+        .bind(user)
+        .bind(guild)
+        .bind(position)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if row.is_some() {
+            sqlx::query(
+                "UPDATE tasks SET position = position - 1 \
+                 WHERE user_id = ? AND guild_id IS ? AND position > ?",
+            )
+            .bind(user)
+            .bind(guild)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn remove_task(&self, list: ListKey, index: usize) -> Result<Task, StorageError> {
+        let (user, guild) = (user_key(list.user), guild_key(list.guild));
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let mut tx = self.pool.begin().await?;
+        let row: Option<TaskRow> = sqlx::query_as(
+            "DELETE FROM tasks WHERE user_id = ? AND guild_id IS ? AND position = ? \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(user)
+        .bind(guild)
+        .bind(position)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if row.is_some() {
+            sqlx::query(
+                "UPDATE tasks SET position = position - 1 \
+                 WHERE user_id = ? AND guild_id IS ? AND position > ?",
+            )
+            .bind(user)
+            .bind(guild)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn restore_task(
+        &self,
+        list: ListKey,
+        index: usize,
+        task: &Task,
+        limit: usize,
+    ) -> Result<usize, StorageError> {
+        let mut tx = self.pool.begin().await?;
+        let added = insert_task(&mut tx, list, task, Placement::At(index), false, limit).await?;
+        // The task is back on the list as a new row, so the row it was completed as goes.
+        sqlx::query(
+            "DELETE FROM tasks WHERE id = (\
+                 SELECT id FROM tasks \
+                 WHERE user_id = ? AND guild_id IS ? AND position IS NULL \
+                 AND text = ? AND created_at = ? \
+                 ORDER BY completed_at DESC LIMIT 1\
+             )",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(&task.text)
+        .bind(to_millis(task.created_at))
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        match added {
+            AddTask::Added(index) | AddTask::Duplicate(index) => Ok(index),
+        }
+    }
+
+    async fn set_pinned(
+        &self,
+        list: ListKey,
+        index: usize,
+        pinned: bool,
+    ) -> Result<Task, StorageError> {
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET pinned = ? WHERE user_id = ? AND guild_id IS ? AND position = ? \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(pinned)
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(position)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn set_archived(
+        &self,
+        list: ListKey,
+        index: usize,
+        archived: bool,
+    ) -> Result<Task, StorageError> {
+        let position = match index.checked_sub(1) {
+            Some(position) => position as i64,
+            None => return Err(StorageError::NoSuchTask(index)),
+        };
+        let row: Option<TaskRow> = sqlx::query_as(
+            "UPDATE tasks SET archived = ? WHERE user_id = ? AND guild_id IS ? AND position = ? \
+             RETURNING text, emoji, image_url, created_at, pinned, archived",
+        )
+        .bind(archived)
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .bind(position)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(TaskRow::into_task)
+            .ok_or(StorageError::NoSuchTask(index))
+    }
+
+    async fn list_tasks(&self, list: ListKey) -> Result<Vec<Task>, StorageError> {
+        let rows: Vec<TaskRow> = sqlx::query_as(
+            "SELECT text, emoji, image_url, created_at, pinned, archived FROM tasks \
+             WHERE user_id = ? AND guild_id IS ? AND position IS NOT NULL ORDER BY position",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(TaskRow::into_task).collect())
+    }
+
+    async fn count_tasks(&self, list: ListKey) -> Result<TaskCount, StorageError> {
+        let (open, completed): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(position), COUNT(*) - COUNT(position) FROM tasks \
+             WHERE user_id = ? AND guild_id IS ?",
+        )
+        .bind(user_key(list.user))
+        .bind(guild_key(list.guild))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(TaskCount {
+            open: open as usize,
+            completed: Some(completed as usize),
+        })
+    }
+
+    async fn stats(&self) -> Result<Stats, StorageError> {
+        let (tasks, users): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COUNT(DISTINCT user_id) FROM tasks \
+             WHERE position IS NOT NULL AND NOT archived",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Stats {
+            tasks: tasks as usize,
+            users: users as usize,
+        })
+    }
+
+    async fn transfer_tasks(
+        &self,
+        from: ListKey,
+        to: ListKey,
+        mode: TransferMode,
+    ) -> Result<usize, StorageError> {
+        let (from_user, from_guild) = (user_key(from.user), guild_key(from.guild));
+        let (to_user, to_guild) = (user_key(to.user), guild_key(to.guild));
+        let mut tx = self.pool.begin().await?;
+        if let TransferMode::Replace = mode {
+            sqlx::query(
+                "DELETE FROM tasks WHERE user_id = ? AND guild_id IS ? AND position IS NOT NULL",
+            )
+            .bind(to_user)
+            .bind(to_guild)
+            .execute(&mut *tx)
+            .await?;
+        }
+        let (offset,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM tasks WHERE user_id = ? AND guild_id IS ? AND position IS NOT NULL",
+        )
+        .bind(to_user)
+        .bind(to_guild)
+        .fetch_one(&mut *tx)
+        .await?;
+        let moved = sqlx::query(
+            "UPDATE tasks SET user_id = ?, guild_id = ?, position = position + ? \
+             WHERE user_id = ? AND guild_id IS ? AND position IS NOT NULL",
+        )
+        .bind(to_user)
+        .bind(to_guild)
+        .bind(offset)
+        .bind(from_user)
+        .bind(from_guild)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(moved.rows_affected() as usize)
+    }
+
+    async fn preferences(&self, user: Id<UserMarker>) -> Result<Preferences, StorageError> {
+        let row: Option<PreferencesRow> = sqlx::query_as(
+            "SELECT delivery, scope, digest_at, digest_locale, utc_offset FROM preferences \
+             WHERE user_id = ?",
+        )
+        .bind(user_key(user))
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row
+            .map(PreferencesRow::into_preferences)
+            .unwrap_or_default())
+    }
+
+    async fn set_preferences(
+        &self,
+        user: Id<UserMarker>,
+        preferences: &Preferences,
+    ) -> Result<(), StorageError> {
+        let row = PreferencesRow::new(preferences);
+        sqlx::query(
+            "INSERT INTO preferences \
+             (user_id, delivery, scope, digest_at, digest_locale, utc_offset) \
+             VALUES (?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (user_id) DO UPDATE \
+             SET delivery = excluded.delivery, scope = excluded.scope, \
+             digest_at = excluded.digest_at, digest_locale = excluded.digest_locale, \
+             utc_offset = excluded.utc_offset",
+        )
+        .bind(user_key(user))
+        .bind(row.delivery)
+        .bind(row.scope)
+        .bind(row.digest_at)
+        .bind(row.digest_locale)
+        .bind(row.utc_offset)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn digest_subscribers(&self) -> Result<Vec<(Id<UserMarker>, Preferences)>, StorageError> {
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences WHERE digest_at IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let user = user_from_key(row.user_id)?;
+                Some((user, row.preferences.into_preferences()))
+            })
+            .collect())
+    }
+
+    async fn delete_user(&self, user: Id<UserMarker>) -> Result<DeletedUser, StorageError> {
+        let user = user_key(user);
+        let mut tx = self.pool.begin().await?;
+        let (tasks, completed): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(position), COUNT(*) - COUNT(position) FROM tasks WHERE user_id = ?",
+        )
+        .bind(user)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM tasks WHERE user_id = ?")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+        let preferences = sqlx::query("DELETE FROM preferences WHERE user_id = ?")
+            .bind(user)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(DeletedUser {
+            tasks: tasks as usize,
+            completed: completed as usize,
+            preferences: preferences.rows_affected() > 0,
+        })
+    }
+
+    async fn export_all(&self) -> Result<Data, StorageError> {
+        let mut data = Data::default();
+        let rows: Vec<ExportRow> = sqlx::query_as(
+            "SELECT user_id, guild_id, text, emoji, image_url, created_at, pinned, archived \
+             FROM tasks WHERE position IS NOT NULL ORDER BY user_id, guild_id, position",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(list) = list_from_keys(row.user_id, row.guild_id) {
+                data.lists
+                    .entry(list)
+                    .or_default()
+                    .push(row.task.into_task());
+            }
+        }
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(user) = user_from_key(row.user_id) {
+                data.preferences
+                    .insert(user, row.preferences.into_preferences());
+            }
+        }
+        let rows: Vec<(String, i64, i64, i64, i64)> = sqlx::query_as(
+            "SELECT command, day, invocations, errors, last_used FROM command_usage",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for (command, day, invocations, errors, last_used) in rows {
+            data.usage.entry(command).or_default().insert(
+                day,
+                Usage {
+                    invocations: invocations as u64,
+                    errors: errors as u64,
+                    last_used,
+                },
+            );
+        }
+        Ok(data)
+    }
+
+    async fn export_user(&self, user: Id<UserMarker>) -> Result<Data, StorageError> {
+        let mut data = Data::default();
+        let rows: Vec<ExportRow> = sqlx::query_as(
+            "SELECT user_id, guild_id, text, emoji, image_url, created_at, pinned, archived \
+             FROM tasks WHERE user_id = ? AND position IS NOT NULL ORDER BY guild_id, position",
+        )
+        .bind(user_key(user))
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(list) = list_from_keys(row.user_id, row.guild_id) {
+                data.lists
+                    .entry(list)
+                    .or_default()
+                    .push(row.task.into_task());
+            }
+        }
+        let rows: Vec<UserPreferencesRow> = sqlx::query_as(
+            "SELECT user_id, delivery, scope, digest_at, digest_locale, utc_offset \
+             FROM preferences WHERE user_id = ?",
+        )
+        .bind(user_key(user))
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            if let Some(user) = user_from_key(row.user_id) {
+                data.preferences
+                    .insert(user, row.preferences.into_preferences());
+            }
+        }
+        Ok(data)
+    }
+
+    async fn record_usage(&self, usage: &UsageCounts) -> Result<(), StorageError> {
+        let mut tx = self.pool.begin().await?;
+        for (command, days) in usage {
+            for (&day, usage) in days {
+                sqlx::query(
+                    "INSERT INTO command_usage (command, day, invocations, errors, last_used) \
+                     VALUES (?, ?, ?, ?, ?) \
+                     ON CONFLICT (command, day) DO UPDATE \
+                     SET invocations = command_usage.invocations + excluded.invocations, \
+                     errors = command_usage.errors + excluded.errors, \
+                     last_used = MAX(command_usage.last_used, excluded.last_used)",
+                )
+                .bind(command)
+                .bind(day)
+                .bind(usage.invocations as i64)
+                .bind(usage.errors as i64)
+                .bind(usage.last_used)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn usage(&self, since: Option<i64>) -> Result<BTreeMap<String, Usage>, StorageError> {
+        let rows: Vec<(String, i64, i64, i64)> = sqlx::query_as(
+            "SELECT command, SUM(invocations), SUM(errors), MAX(last_used) \
+             FROM command_usage WHERE day >= ? GROUP BY command",
+        )
+        .bind(since.unwrap_or(i64::MIN))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(command, invocations, errors, last_used)| {
+                let usage = Usage {
+                    invocations: invocations as u64,
+                    errors: errors as u64,
+                    last_used,
+                };
+                (command, usage)
+            })
+            .collect())
+    }
+
+    async fn ping(&self) -> Result<(), StorageError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), StorageError> {
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[tokio::test]
+    async fn deletes_only_that_user() {
+        let path = temp_path("delete-user.sqlite");
+        let storage = SqliteStorage::open(path.to_str().unwrap()).await.unwrap();
+        super::super::tests::deletes_only_that_user(&storage, true).await;
+        storage.pool.close().await;
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::time::SystemTime;
+
+use twilight_model::{
+    application::{
+        command::CommandOptionType, interaction::application_command::CommandOptionValue,
+    },
+    id::{marker::EmojiMarker, Id},
+};
+
+use crate::parser::{OptionError, ParseOption};
+
+/// An entry on a user's todo list.
+#[derive(Clone, Debug)]
+pub struct Task {
+    pub text: String,
+    pub emoji: Option<ReactionEmoji>,
+    /// An image attached to the task.
+    ///
+    /// This is the URL of an attachment uploaded with the command, which Discord may expire, so
+    /// it can't be relied on to still point at the image.
+    pub image_url: Option<String>,
+    pub created_at: SystemTime,
+    /// Whether the task is shown before the others, however the list is sorted.
+    pub pinned: bool,
+    /// Whether the task is hidden from the list without having been completed, e.g. because it's
+    /// been put off indefinitely.
+    pub archived: bool,
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(emoji) = &self.emoji {
+            write!(f, "{emoji} ")?;
+        }
+        f.write_str(&self.text)
+    }
+}
+
+/// Whether two task descriptions refer to the same task, ignoring case and surrounding whitespace.
+pub fn same_task(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// An emoji used to mark a task, either a unicode emoji or a custom Discord emoji.
+#[derive(Clone, Debug)]
+pub enum ReactionEmoji {
+    Custom {
+        animated: bool,
+        name: String,
+        id: Id<EmojiMarker>,
+    },
+    Unicode(String),
+}
+
+impl ReactionEmoji {
+    /// Parses the `<:name:id>` (or `<a:name:id>` for animated emoji) syntax Discord uses for custom
+    /// emoji, or a raw unicode emoji.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(custom) = s.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            let (animated, custom) = match custom.strip_prefix('a') {
+                Some(custom) => (true, custom),
+                None => (false, custom),
+            };
+            let (name, id) = custom.strip_prefix(':')?.split_once(':')?;
+            if name.is_empty() {
+                return None;
+            }
+            Some(ReactionEmoji::Custom {
+                animated,
+                name: name.into(),
+                id: id.parse().ok()?,
+            })
+        } else if !s.is_empty() && !s.contains(char::is_whitespace) && !s.is_ascii() {
+            // Unicode emoji come in too many shapes (modifiers, joiners, keycaps) to validate
+            // exactly, but none of them are plain ASCII or contain whitespace.
+            Some(ReactionEmoji::Unicode(s.into()))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for ReactionEmoji {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReactionEmoji::Custom {
+                animated: true,
+                name,
+                id,
+            } => write!(f, "<a:{name}:{id}>"),
+            ReactionEmoji::Custom {
+                animated: false,
+                name,
+                id,
+            } => write!(f, "<:{name}:{id}>"),
+            ReactionEmoji::Unicode(emoji) => f.write_str(emoji),
+        }
+    }
+}
+
+impl ParseOption for ReactionEmoji {
+    const KIND: CommandOptionType = CommandOptionType::String;
+
+    fn parse_option(value: CommandOptionValue) -> Result<Self, OptionError> {
+        let string = String::parse_option(value)?;
+        ReactionEmoji::parse(&string).ok_or_else(|| OptionError::InvalidValue {
+            value: string,
+            reason: "expected a unicode emoji or a custom emoji like `<:name:id>`".into(),
+        })
+    }
+}
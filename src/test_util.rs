@@ -0,0 +1,468 @@
+//! Builders for the interactions tests feed to the bot, so that a test only has to spell out the
+//! parts of the payload it's about, and a fake of Discord's API to run the bot against.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Map, Value};
+use twilight_http::request::AttachmentFile;
+use twilight_model::{
+    application::{
+        callback::{CallbackData, InteractionResponse},
+        command::Command,
+        component::Component,
+        interaction::{ApplicationCommand, Interaction},
+    },
+    channel::embed::Embed,
+    guild::Guild,
+    id::{
+        marker::{ChannelMarker, CommandMarker, GuildMarker, InteractionMarker, UserMarker},
+        Id,
+    },
+    oauth::CurrentApplicationInfo,
+};
+
+use crate::config::Config;
+use crate::discord::DiscordApi;
+use crate::storage::MemoryStorage;
+use crate::task::Task;
+use crate::State;
+
+/// The application every fixture is addressed to.
+pub const APPLICATION_ID: u64 = 900;
+
+/// The user who owns the application in [`state`].
+pub const OWNER_ID: u64 = 99;
+
+/// Builds an application command interaction, as Discord would send it.
+///
+/// By default the command is interaction 700, a slash command used in a DM by user 1, with the
+/// `en-US` locale and no options.
+///
+/// ```ignore
+/// let command = InteractionFixture::new("task")
+///     .guild(10)
+///     .string_option("task", "buy milk")
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct InteractionFixture {
+    id: u64,
+    name: String,
+    kind: u8,
+    user: u64,
+    guild: Option<u64>,
+    permissions: u64,
+    locale: String,
+    options: Vec<Value>,
+    resolved: Map<String, Value>,
+    target_id: Option<u64>,
+}
+
+impl InteractionFixture {
+    pub fn new(name: &str) -> Self {
+        InteractionFixture {
+            id: 700,
+            name: name.into(),
+            kind: 1,
+            user: 1,
+            guild: None,
+            permissions: 0,
+            locale: "en-US".into(),
+            options: Vec::new(),
+            resolved: Map::new(),
+            target_id: None,
+        }
+    }
+
+    /// A message context menu command, used on a message by user 2 with the given content.
+    pub fn message_command(name: &str, message: u64, content: &str) -> Self {
+        let mut fixture = InteractionFixture::new(name);
+        fixture.kind = 3;
+        fixture.target_id = Some(message);
+        fixture.resolve(
+            "messages",
+            message,
+            json!({
+                "id": message.to_string(),
+                "channel_id": "20",
+                "author": user_json(2),
+                "content": content,
+                "timestamp": "2022-04-15T05:20:00.000000+00:00",
+                "edited_timestamp": null,
+                "tts": false,
+                "mention_everyone": false,
+                "mentions": [],
+                "mention_roles": [],
+                "attachments": [],
+                "embeds": [],
+                "pinned": false,
+                "type": 0,
+            }),
+        );
+        fixture
+    }
+
+    /// Sets the interaction's id; Discord delivers the same interaction with the same id.
+    pub fn id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Sets who used the command.
+    pub fn user(mut self, id: u64) -> Self {
+        self.user = id;
+        self
+    }
+
+    /// Uses the command in a guild, rather than a DM.
+    pub fn guild(mut self, id: u64) -> Self {
+        self.guild = Some(id);
+        self
+    }
+
+    /// Sets the permissions of the member who used the command, in a guild.
+    pub fn permissions(mut self, bits: u64) -> Self {
+        self.permissions = bits;
+        self
+    }
+
+    pub fn locale(mut self, locale: &str) -> Self {
+        self.locale = locale.into();
+        self
+    }
+
+    /// Adds an option of the given type, whether or not it's the type the command expects.
+    pub fn option(mut self, name: &str, kind: u8, value: Value) -> Self {
+        self.options
+            .push(json!({ "name": name, "type": kind, "value": value }));
+        self
+    }
+
+    pub fn string_option(self, name: &str, value: &str) -> Self {
+        self.option(name, 3, value.into())
+    }
+
+    pub fn int_option(self, name: &str, value: i64) -> Self {
+        self.option(name, 4, value.into())
+    }
+
+    pub fn bool_option(self, name: &str, value: bool) -> Self {
+        self.option(name, 5, value.into())
+    }
+
+    /// Adds a user option, along with the user in the resolved data.
+    pub fn user_option(mut self, name: &str, id: u64) -> Self {
+        self.resolve("users", id, user_json(id));
+        self.option(name, 6, id.to_string().into())
+    }
+
+    /// Adds an attachment option, along with the attachment in the resolved data.
+    pub fn attachment_option(mut self, name: &str, id: u64, content_type: &str) -> Self {
+        self.resolve(
+            "attachments",
+            id,
+            json!({
+                "id": id.to_string(),
+                "filename": "file",
+                "content_type": content_type,
+                "size": 16,
+                "url": format!("https://cdn.example.com/{id}"),
+                "proxy_url": format!("https://media.example.com/{id}"),
+            }),
+        );
+        self.option(name, 11, id.to_string().into())
+    }
+
+    /// Invokes the subcommand `name`, with the options `build` adds to the fixture it's given.
+    pub fn subcommand(mut self, name: &str, build: impl FnOnce(Self) -> Self) -> Self {
+        let sub = build(InteractionFixture::new(name));
+        self.resolved.extend(sub.resolved);
+        self.options
+            .push(json!({ "name": name, "type": 1, "options": sub.options }));
+        self
+    }
+
+    /// Invokes a subcommand in the group `name`, which `build` adds with
+    /// [`subcommand`](Self::subcommand) to the fixture it's given.
+    pub fn subcommand_group(mut self, name: &str, build: impl FnOnce(Self) -> Self) -> Self {
+        let group = build(InteractionFixture::new(name));
+        self.resolved.extend(group.resolved);
+        self.options
+            .push(json!({ "name": name, "type": 2, "options": group.options }));
+        self
+    }
+
+    fn resolve(&mut self, kind: &str, id: u64, value: Value) {
+        let resolved = self
+            .resolved
+            .entry(kind)
+            .or_insert_with(|| Value::Object(Map::new()));
+        resolved[id.to_string()] = value;
+    }
+
+    /// The interaction, as JSON.
+    pub fn to_json(&self) -> Value {
+        let mut data = json!({
+            "id": "800",
+            "name": self.name,
+            "type": self.kind,
+            "options": self.options,
+        });
+        if !self.resolved.is_empty() {
+            data["resolved"] = Value::Object(self.resolved.clone());
+        }
+        if let Some(target_id) = self.target_id {
+            data["target_id"] = target_id.to_string().into();
+        }
+        let mut interaction = json!({
+            "id": self.id.to_string(),
+            "application_id": APPLICATION_ID.to_string(),
+            "type": 2,
+            "channel_id": "20",
+            "data": data,
+            "locale": self.locale,
+            "token": "fixture-token",
+            "version": 1,
+        });
+        match self.guild {
+            Some(guild) => {
+                interaction["guild_id"] = guild.to_string().into();
+                interaction["member"] = json!({
+                    "user": user_json(self.user),
+                    "roles": [],
+                    "joined_at": "2022-01-01T00:00:00.000000+00:00",
+                    "deaf": false,
+                    "mute": false,
+                    "permissions": self.permissions.to_string(),
+                });
+            }
+            None => interaction["user"] = user_json(self.user),
+        }
+        interaction
+    }
+
+    pub fn build(&self) -> ApplicationCommand {
+        application_command(&self.to_json())
+    }
+}
+
+/// The current application, as Discord's API describes it.
+pub fn application_json() -> Value {
+    json!({
+        "id": APPLICATION_ID.to_string(),
+        "name": "todo",
+        "description": "",
+        "summary": "",
+        "verify_key": "",
+        "bot_public": true,
+        "bot_require_code_grant": false,
+        "owner": user_json(OWNER_ID),
+    })
+}
+
+/// A plain task with the given text, created at a fixed time so that tests are repeatable.
+pub fn task(text: &str) -> Task {
+    Task {
+        text: text.into(),
+        emoji: None,
+        image_url: None,
+        created_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_650_000_000),
+        pinned: false,
+        archived: false,
+    }
+}
+
+/// A fresh path in the temporary directory, so that tests don't see each other's files.
+pub fn temp_path(name: &str) -> PathBuf {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let mut path = std::env::temp_dir();
+    path.push(format!(
+        "todo-bot-{}-{}-{name}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+    ));
+    path
+}
+
+/// Parses an interaction from JSON, panicking if it isn't valid.
+pub fn parse_interaction(json: &str) -> Interaction {
+    serde_json::from_str(json).expect("fixture should be a valid interaction")
+}
+
+/// Parses an application command interaction from JSON, e.g. a fixture's JSON with a field
+/// removed, panicking if it's anything else.
+pub fn application_command(json: &Value) -> ApplicationCommand {
+    match parse_interaction(&json.to_string()) {
+        Interaction::ApplicationCommand(command) => *command,
+        other => panic!("fixture isn't an application command: {other:?}"),
+    }
+}
+
+fn user_json(id: u64) -> Value {
+    json!({
+        "id": id.to_string(),
+        "username": format!("user{id}"),
+        "discriminator": "0001",
+        "avatar": null,
+    })
+}
+
+/// A request the bot made to [`FakeDiscord`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Callback(InteractionResponse),
+    Followup(CallbackData),
+    /// An ephemeral follow-up with this many files.
+    FollowupFiles(usize),
+    UpdateOriginal(CallbackData),
+    UpdateComponents(Vec<Component>),
+    Message {
+        channel: Id<ChannelMarker>,
+        content: Option<String>,
+        embeds: Vec<Embed>,
+        files: usize,
+    },
+}
+
+/// A stand-in for Discord's API, which records the requests the bot makes to it instead of
+/// sending them. Clones share the same record, so a test can keep one to look at what the bot
+/// sent.
+///
+/// A user's DM channel has the same id as the user, and looking up a guild fails.
+#[derive(Clone, Default)]
+pub struct FakeDiscord {
+    inner: Arc<Mutex<Fake>>,
+}
+
+#[derive(Default)]
+struct Fake {
+    requests: Vec<Request>,
+    commands: Vec<Command>,
+    next_command: u64,
+}
+
+impl FakeDiscord {
+    /// Takes the requests made so far, oldest first.
+    pub fn take_requests(&self) -> Vec<Request> {
+        std::mem::take(&mut self.inner.lock().unwrap().requests)
+    }
+
+    fn record(&self, request: Request) {
+        self.inner.lock().unwrap().requests.push(request);
+    }
+}
+
+#[async_trait::async_trait]
+impl DiscordApi for FakeDiscord {
+    async fn interaction_callback(
+        &self,
+        _id: Id<InteractionMarker>,
+        _token: &str,
+        response: &InteractionResponse,
+    ) -> anyhow::Result<()> {
+        self.record(Request::Callback(response.clone()));
+        Ok(())
+    }
+
+    async fn create_followup(&self, _token: &str, data: &CallbackData) -> anyhow::Result<()> {
+        self.record(Request::Followup(data.clone()));
+        Ok(())
+    }
+
+    async fn create_followup_files(
+        &self,
+        _token: &str,
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()> {
+        self.record(Request::FollowupFiles(files.len()));
+        Ok(())
+    }
+
+    async fn update_original(&self, _token: &str, data: &CallbackData) -> anyhow::Result<()> {
+        self.record(Request::UpdateOriginal(data.clone()));
+        Ok(())
+    }
+
+    async fn update_original_components(
+        &self,
+        _token: &str,
+        components: &[Component],
+    ) -> anyhow::Result<()> {
+        self.record(Request::UpdateComponents(components.to_vec()));
+        Ok(())
+    }
+
+    async fn create_private_channel(
+        &self,
+        user: Id<UserMarker>,
+    ) -> anyhow::Result<Id<ChannelMarker>> {
+        Ok(user.cast())
+    }
+
+    async fn create_message(
+        &self,
+        channel: Id<ChannelMarker>,
+        content: Option<&str>,
+        embeds: &[Embed],
+        files: &[AttachmentFile<'_>],
+    ) -> anyhow::Result<()> {
+        self.record(Request::Message {
+            channel,
+            content: content.map(String::from),
+            embeds: embeds.to_vec(),
+            files: files.len(),
+        });
+        Ok(())
+    }
+
+    async fn guild(&self, guild: Id<GuildMarker>) -> anyhow::Result<Guild> {
+        anyhow::bail!("the fake doesn't know guild {guild}")
+    }
+
+    async fn commands(&self, _guild: Option<Id<GuildMarker>>) -> anyhow::Result<Vec<Command>> {
+        Ok(self.inner.lock().unwrap().commands.clone())
+    }
+
+    async fn upsert_command(
+        &self,
+        _guild: Option<Id<GuildMarker>>,
+        command: &Command,
+    ) -> anyhow::Result<()> {
+        let mut fake = self.inner.lock().unwrap();
+        fake.next_command += 1;
+        let mut command = command.clone();
+        command.id = Some(Id::new(fake.next_command));
+        fake.commands
+            .retain(|existing| existing.name != command.name);
+        fake.commands.push(command);
+        Ok(())
+    }
+
+    async fn delete_command(
+        &self,
+        _guild: Option<Id<GuildMarker>>,
+        id: Id<CommandMarker>,
+    ) -> anyhow::Result<()> {
+        let mut fake = self.inner.lock().unwrap();
+        fake.commands.retain(|command| command.id != Some(id));
+        Ok(())
+    }
+}
+
+/// The bot's state with the default configuration, the real commands, in-memory storage and
+/// `discord` in place of Discord's API.
+pub fn state(discord: &FakeDiscord) -> Arc<State> {
+    let application = serde_json::from_value::<CurrentApplicationInfo>(application_json())
+        .expect("fixture should be a valid application");
+    State::from_parts(
+        Config::for_tests(),
+        crate::commands(String::new()).expect("commands.yaml should load"),
+        application,
+        Box::new(discord.clone()),
+        Box::<MemoryStorage>::default(),
+    )
+    .expect("the test state should build")
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use twilight_model::id::{marker::UserMarker, Id};
+
+use crate::storage::{self, ListKey, Preferences};
+use crate::task::Task;
+
+/// The most changes remembered per user; beyond this, the oldest are forgotten.
+const DEPTH: usize = 10;
+
+/// How long a change can be undone for.
+const TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A change a user made, with what's needed to reverse it.
+#[derive(Debug)]
+pub enum Action {
+    /// A task was added at `index`.
+    Added {
+        list: ListKey,
+        index: usize,
+        task: Task,
+    },
+    /// The task at `index` was completed.
+    Completed {
+        list: ListKey,
+        index: usize,
+        task: Task,
+    },
+    /// The task at `index` was pinned or unpinned.
+    Pinned {
+        list: ListKey,
+        index: usize,
+        task: Task,
+        pinned: bool,
+    },
+    /// The task at `index` was archived or unarchived.
+    Archived {
+        list: ListKey,
+        index: usize,
+        task: Task,
+        archived: bool,
+    },
+    /// The user's preferences were changed from `previous`.
+    Preferences { previous: Preferences },
+}
+
+/// The changes each user has made recently, most recent last, so that `/undo` can reverse them.
+///
+/// History is only kept in memory, so it's lost when the bot restarts, and each user's is capped
+/// at [`DEPTH`] changes made within the last [`TTL`]. Changes which move or delete tasks in bulk
+/// (`/transfer`, `/migrate-list` and `/forget-me`) can't be undone, and clear the history of
+/// every user whose lists they touch, since the positions it records no longer hold.
+pub struct UndoHistory {
+    history: Mutex<History>,
+}
+
+struct History {
+    users: HashMap<Id<UserMarker>, VecDeque<(Action, Instant)>>,
+    /// When histories were last checked for ones which have expired.
+    swept_at: Instant,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        UndoHistory {
+            history: Mutex::new(History {
+                users: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl UndoHistory {
+    /// Records a change made by `user`, forgetting their oldest if they're at the cap.
+    pub fn record(&self, user: Id<UserMarker>, action: Action) {
+        let now = Instant::now();
+        let mut history = self.history.lock().unwrap();
+        if now.duration_since(history.swept_at) >= TTL {
+            // A user's most recent change is the last to expire.
+            history.users.retain(|_, actions| {
+                actions
+                    .back()
+                    .is_some_and(|&(_, at)| now.duration_since(at) < TTL)
+            });
+            history.swept_at = now;
+        }
+        let actions = history.users.entry(user).or_default();
+        if actions.len() >= DEPTH {
+            actions.pop_front();
+        }
+        actions.push_back((action, now));
+    }
+
+    /// Takes `user`'s most recent change, if they've made one which can still be undone.
+    pub fn pop(&self, user: Id<UserMarker>) -> Option<Action> {
+        let mut history = self.history.lock().unwrap();
+        let actions = history.users.get_mut(&user)?;
+        let popped = match actions.pop_back() {
+            Some((action, at)) if at.elapsed() < TTL => Some(action),
+            // Every earlier change has expired too.
+            _ => None,
+        };
+        if popped.is_none() || actions.is_empty() {
+            history.users.remove(&user);
+        }
+        popped
+    }
+
+    /// Forgets every change made by `user`.
+    pub fn forget(&self, user: Id<UserMarker>) {
+        self.history.lock().unwrap().users.remove(&user);
+    }
+}
+
+/// Finds `task` on a list, where it was last seen at `index`.
+///
+/// Other changes may have moved it since, so if it's no longer at `index`, it's looked for by its
+/// text and when it was created. Returns its (one-based) index, or `None` if it's gone.
+pub fn find_task(tasks: &[Task], index: usize, task: &Task) -> Option<usize> {
+    let matches = |other: &Task| {
+        other.text == task.text
+            && storage::to_millis(other.created_at) == storage::to_millis(task.created_at)
+    };
+    match index.checked_sub(1).and_then(|idx| tasks.get(idx)) {
+        Some(other) if matches(other) => Some(index),
+        _ => tasks.iter().position(matches).map(|idx| idx + 1),
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use tokio::time::MissedTickBehavior;
+
+use crate::storage::{self, Storage, Usage, UsageCounts};
+use crate::State;
+
+/// How often the counts collected since the last flush are added to storage.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts how much each command is used, so the owner of the bot can see which ones matter.
+///
+/// Counts are collected in memory and only added to storage by [`run_periodic`], so that
+/// recording them never slows down a response.
+#[derive(Default)]
+pub struct UsageRecorder {
+    pending: Mutex<UsageCounts>,
+}
+
+impl UsageRecorder {
+    /// Counts an invocation of `command`, which failed unless `ok`.
+    pub fn record(&self, command: &str, ok: bool) {
+        let now = SystemTime::now();
+        let mut pending = self.pending.lock().unwrap();
+        let usage = pending
+            .entry(command.into())
+            .or_default()
+            .entry(storage::day(now))
+            .or_default();
+        usage.add(&Usage {
+            invocations: 1,
+            errors: u64::from(!ok),
+            last_used: storage::to_millis(now),
+        });
+    }
+
+    /// Adds the counts collected since the last flush to storage.
+    ///
+    /// If that fails, the counts are kept to try again with the next flush.
+    pub async fn flush(&self, storage: &dyn Storage) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return;
+        }
+        if let Err(e) = storage.record_usage(&pending).await {
+            log::warn!("failed to record command usage: {e}");
+            storage::add_usage(&mut self.pending.lock().unwrap(), &pending);
+        }
+    }
+}
+
+/// Adds the collected counts to storage every [`FLUSH_INTERVAL`].
+pub async fn run_periodic(state: Arc<State>) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        state.usage.flush(&*state.storage).await;
+    }
+}
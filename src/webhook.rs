@@ -0,0 +1,56 @@
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use twilight_model::id::{marker::UserMarker, Id};
+
+use crate::storage::to_millis;
+use crate::task::Task;
+
+/// How long to wait for the webhook to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook which is told about every completed task, e.g. for an external dashboard.
+pub struct CompletionWebhook {
+    client: reqwest::Client,
+    url: String,
+}
+
+/// The JSON body posted to the webhook.
+#[derive(Serialize)]
+struct Completion<'a> {
+    user_id: Id<UserMarker>,
+    task: &'a str,
+    /// Milliseconds since the Unix epoch.
+    completed_at: i64,
+}
+
+impl CompletionWebhook {
+    pub fn new(url: String) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(TIMEOUT).build()?;
+        Ok(CompletionWebhook { client, url })
+    }
+
+    /// Posts the completed task to the webhook in the background.
+    ///
+    /// Failures are logged rather than returned, since they shouldn't fail the command.
+    pub fn notify(&self, user: Id<UserMarker>, task: &Task) {
+        let request = self.client.post(&self.url).json(&Completion {
+            user_id: user,
+            task: &task.text,
+            completed_at: to_millis(SystemTime::now()),
+        });
+        tokio::spawn(async move {
+            let result = request
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(e) = result {
+                // The URL may hold a secret, so it's left out of the message.
+                log::warn!(
+                    "failed to notify the completion webhook: {}",
+                    e.without_url()
+                );
+            }
+        });
+    }
+}